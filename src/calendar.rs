@@ -0,0 +1,280 @@
+use serde_derive::{Deserialize, Serialize};
+
+const SECONDS_PER_DAY: f64 = 86400.0;
+
+/// Days between 1970-01-01 and the given proleptic Gregorian date.
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = (if year >= 0 { year } else { year - 399 }) / 400;
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+/// Proleptic Gregorian date of the day that is `days` after 1970-01-01.
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = (if days >= 0 { days } else { days - 146096 }) / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+// Days between 1970-01-01 and 2000-01-01
+const J2000_DAYS_FROM_UNIX: i64 = 10957;
+
+/// Formats a time given in seconds since J2000 as `YYYY-MM-DDThh:mm:ss.sss`.
+/// Leap seconds are ignored, the time scale is assumed to be uniform.
+pub fn format_iso8601(seconds_since_j2000: f64) -> String {
+    // Count from midnight instead of noon
+    let seconds = seconds_since_j2000 + SECONDS_PER_DAY / 2.0;
+    let days = (seconds / SECONDS_PER_DAY).floor();
+    let mut second_of_day = seconds - days * SECONDS_PER_DAY;
+
+    // Avoid printing 60 seconds after rounding
+    let mut days = days as i64;
+    if second_of_day >= SECONDS_PER_DAY - 0.0005 {
+        second_of_day = 0.0;
+        days += 1;
+    }
+
+    let (year, month, day) = civil_from_days(days + J2000_DAYS_FROM_UNIX);
+    let hour = (second_of_day / 3600.0).floor();
+    let minute = ((second_of_day - hour * 3600.0) / 60.0).floor();
+    let second = second_of_day - hour * 3600.0 - minute * 60.0;
+
+    format!("{year:04}-{month:02}-{day:02}T{:02}:{:02}:{second:06.3}", hour as u32, minute as u32)
+}
+
+/// Parses `YYYY-MM-DDThh:mm:ss[.f]` or `YYYY-DDDThh:mm:ss[.f]` (day of year), with an optional
+/// trailing `Z`, into seconds since J2000.
+pub fn parse_iso8601(text: &str) -> Option<f64> {
+    let text = text.trim().trim_end_matches('Z');
+    let (date, time) = text.split_once('T').unwrap_or((text, "00:00:00"));
+
+    let date_parts: Vec<&str> = date.split('-').collect();
+    let days = match date_parts.as_slice() {
+        [year, month, day] => days_from_civil(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?),
+        [year, day_of_year] => days_from_civil(year.parse().ok()?, 1, 1) + day_of_year.parse::<i64>().ok()? - 1,
+        _ => return None,
+    };
+
+    let time_parts: Vec<&str> = time.split(':').collect();
+    let second_of_day = match time_parts.as_slice() {
+        [hour, minute, second] => {
+            hour.parse::<f64>().ok()? * 3600.0 + minute.parse::<f64>().ok()? * 60.0 + second.parse::<f64>().ok()?
+        }
+        [hour, minute] => hour.parse::<f64>().ok()? * 3600.0 + minute.parse::<f64>().ok()? * 60.0,
+        _ => return None,
+    };
+
+    Some((days - J2000_DAYS_FROM_UNIX) as f64 * SECONDS_PER_DAY + second_of_day - SECONDS_PER_DAY / 2.0)
+}
+
+/// Julian Date of a time given in seconds since J2000.
+pub fn julian_date(seconds_since_j2000: f64) -> f64 {
+    2451545.0 + seconds_since_j2000 / SECONDS_PER_DAY
+}
+
+/// Seconds since J2000 of a Julian Date.
+pub fn seconds_from_julian_date(julian_date: f64) -> f64 {
+    (julian_date - 2451545.0) * SECONDS_PER_DAY
+}
+
+// The calendar of KSP, counted from Year 1, Day 1 at midnight
+const KERBIN_SECONDS_PER_DAY: f64 = 6.0 * 3600.0;
+const KERBIN_DAYS_PER_YEAR: f64 = 426.0;
+
+/// Formats a time given in seconds since the Kerbin epoch as `Y1, D001, 0:00:00`.
+pub fn format_kerbin_time(seconds: f64) -> String {
+    // Keep the seconds from rounding up to 60
+    let seconds = seconds.max(0.0).floor();
+    let seconds_per_year = KERBIN_SECONDS_PER_DAY * KERBIN_DAYS_PER_YEAR;
+
+    let year = (seconds / seconds_per_year).floor();
+    let second_of_year = seconds - year * seconds_per_year;
+    let day = (second_of_year / KERBIN_SECONDS_PER_DAY).floor();
+    let second_of_day = second_of_year - day * KERBIN_SECONDS_PER_DAY;
+    let hour = (second_of_day / 3600.0).floor();
+    let minute = ((second_of_day - hour * 3600.0) / 60.0).floor();
+    let second = second_of_day - hour * 3600.0 - minute * 60.0;
+
+    format!("Y{}, D{:03}, {}:{:02}:{:02}", year as u64 + 1, day as u64 + 1, hour as u32, minute as u32, second as u32)
+}
+
+/// Parses `Y1, D1, 0:00:00` like [`format_kerbin_time`] writes it, the time of day being optional,
+/// into seconds since the Kerbin epoch.
+pub fn parse_kerbin_time(text: &str) -> Option<f64> {
+    let mut parts = text.split([',', ' ']).filter(|part| !part.is_empty());
+
+    let year = parts.next()?.strip_prefix(['Y', 'y'])?.parse::<u64>().ok().filter(|year| *year >= 1)?;
+    let day = parts.next()?.strip_prefix(['D', 'd'])?.parse::<u64>().ok()
+        .filter(|day| *day >= 1 && *day as f64 <= KERBIN_DAYS_PER_YEAR)?;
+
+    let second_of_day = match parts.next() {
+        Some(time) => {
+            let fields = time.split(':').map(|field| field.parse::<f64>().ok()).collect::<Option<Vec<_>>>()?;
+            match fields.as_slice() {
+                [hour, minute, second] => hour * 3600.0 + minute * 60.0 + second,
+                [hour, minute] => hour * 3600.0 + minute * 60.0,
+                _ => return None,
+            }
+        }
+        None => 0.0,
+    };
+    if parts.next().is_some() || second_of_day >= KERBIN_SECONDS_PER_DAY {
+        return None;
+    }
+
+    Some(((year - 1) as f64 * KERBIN_DAYS_PER_YEAR + (day - 1) as f64) * KERBIN_SECONDS_PER_DAY + second_of_day)
+}
+
+/// Calendar the simulated time is shown in, written by name in scenario files.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(into = "String", try_from = "String")]
+pub enum Calendar {
+    /// 6 hour days and 426 day years, counted from Year 1, Day 1
+    #[default]
+    Kerbin,
+    /// UTC dates and Julian Dates, counted from J2000, leap seconds ignored
+    Earth,
+}
+
+impl Calendar {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Calendar::Kerbin => "kerbin",
+            Calendar::Earth => "earth",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "kerbin" => Some(Calendar::Kerbin),
+            "earth" => Some(Calendar::Earth),
+            _ => None,
+        }
+    }
+
+    /// Formats a time given in seconds since the origin of the calendar.
+    pub fn format(&self, seconds: f64) -> String {
+        match self {
+            Calendar::Kerbin => format_kerbin_time(seconds),
+            Calendar::Earth => format!("{} UTC", format_iso8601(seconds)),
+        }
+    }
+
+    /// Parses a date written in the format of the calendar, an Earth date can also be a Julian Date.
+    pub fn parse(&self, text: &str) -> Option<f64> {
+        match self {
+            Calendar::Kerbin => parse_kerbin_time(text),
+            Calendar::Earth => {
+                let text = text.trim();
+                match text.strip_prefix("JD") {
+                    Some(julian) => julian.trim().parse().ok().map(seconds_from_julian_date),
+                    None => parse_iso8601(text.trim_end_matches("UTC").trim()),
+                }
+            }
+        }
+    }
+}
+
+impl From<Calendar> for String {
+    fn from(calendar: Calendar) -> Self {
+        calendar.name().to_string()
+    }
+}
+
+impl TryFrom<String> for Calendar {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        Calendar::from_name(&name).ok_or_else(|| format!("unknown calendar {name}, expected kerbin or earth"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() <= tolerance, "{actual} is not within {tolerance} of {expected}");
+    }
+
+    #[test]
+    fn j2000_is_noon_on_the_first_of_january_2000() {
+        assert_eq!(format_iso8601(0.0), "2000-01-01T12:00:00.000");
+        assert_eq!(parse_iso8601("2000-01-01T12:00:00"), Some(0.0));
+        assert_eq!(julian_date(0.0), 2451545.0);
+        assert_eq!(seconds_from_julian_date(2451545.0), 0.0);
+        assert_eq!(Calendar::Earth.parse("JD 2451545.0"), Some(0.0));
+    }
+
+    #[test]
+    fn leap_days_follow_the_gregorian_rules() {
+        // 2000 is divisible by 400, so it is a leap year
+        assert_eq!(civil_from_days(days_from_civil(2000, 2, 28) + 1), (2000, 2, 29));
+        assert_eq!(civil_from_days(days_from_civil(2000, 2, 29) + 1), (2000, 3, 1));
+        assert_eq!(parse_iso8601("2000-060T00:00:00"), parse_iso8601("2000-02-29T00:00:00"));
+
+        // 1900 is divisible by 100 but not by 400, so it is not
+        assert_eq!(civil_from_days(days_from_civil(1900, 2, 28) + 1), (1900, 3, 1));
+        assert_eq!(days_from_civil(1901, 1, 1) - days_from_civil(1900, 1, 1), 365);
+        assert_eq!(days_from_civil(2001, 1, 1) - days_from_civil(2000, 1, 1), 366);
+    }
+
+    #[test]
+    fn earth_dates_survive_a_round_trip() {
+        for date in ["2000-01-01T12:00:00.000", "2000-02-29T06:30:15.250", "1900-03-01T00:00:00.000", "2024-12-31T23:59:59.000"] {
+            let seconds = parse_iso8601(date).unwrap();
+            assert_eq!(format_iso8601(seconds), date);
+        }
+
+        for seconds in [0.0, -86400.0 * 36525.0, 1.0e9 + 0.5] {
+            let text = Calendar::Earth.format(seconds);
+            assert_close(Calendar::Earth.parse(&text).unwrap(), seconds, 1e-3);
+        }
+    }
+
+    #[test]
+    fn kerbin_time_starts_at_year_1_day_1() {
+        assert_eq!(format_kerbin_time(0.0), "Y1, D001, 0:00:00");
+        assert_eq!(parse_kerbin_time("Y1, D1"), Some(0.0));
+        assert_eq!(parse_kerbin_time("Y1, D001, 0:00:00"), Some(0.0));
+        assert_eq!(parse_kerbin_time("Y0, D1"), None);
+        assert_eq!(parse_kerbin_time("Y1, D0"), None);
+    }
+
+    #[test]
+    fn kerbin_years_roll_over_after_day_426() {
+        let last_day = 425.0 * KERBIN_SECONDS_PER_DAY;
+        let year = 426.0 * KERBIN_SECONDS_PER_DAY;
+
+        assert_eq!(format_kerbin_time(last_day), "Y1, D426, 0:00:00");
+        assert_eq!(format_kerbin_time(year - 1.0), "Y1, D426, 5:59:59");
+        assert_eq!(format_kerbin_time(year), "Y2, D001, 0:00:00");
+        assert_eq!(parse_kerbin_time("Y1, D426"), Some(last_day));
+        assert_eq!(parse_kerbin_time("Y2, D1"), Some(year));
+        assert_eq!(parse_kerbin_time("Y1, D427"), None);
+    }
+
+    #[test]
+    fn kerbin_times_survive_a_round_trip() {
+        for seconds in [0.0, 3599.0, 21599.0, 426.0 * 21600.0, 12345678.0] {
+            assert_eq!(parse_kerbin_time(&Calendar::Kerbin.format(seconds)), Some(seconds));
+        }
+
+        for text in ["Y1, D001, 0:00:00", "Y3, D200, 5:07:09", "Y12, D426, 5:59:59"] {
+            assert_eq!(Calendar::Kerbin.format(Calendar::Kerbin.parse(text).unwrap()), text);
+        }
+    }
+}
@@ -1,28 +1,53 @@
+use crate::free_flight_plugin::*;
+use crate::input_map::*;
 use crate::planet_components::*;
+use crate::planet_models::*;
 use crate::labels::*;
+use crate::map_view_plugin::*;
+use crate::startup_options::*;
 use bevy::core_pipeline::bloom::BloomSettings;
-use bevy::input::keyboard::*;
 use bevy::input::mouse::*;
-use bevy::input::ButtonState;
 use bevy::prelude::*;
+use serde_derive::{Deserialize, Serialize};
 
 pub struct CameraPlugin;
 
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
         app.add_startup_system(spawn_camera)
-            .add_system(focus_camera.label(SystemTypes::CameraLabel))
-            .add_system(orbit_camera.label(SystemTypes::CameraLabel));
+            .add_startup_system_to_stage(StartupStage::PostStartup, focus_initial_body)
+            .add_system(focus_camera
+                .label(SystemTypes::CameraLabel)
+                .after(follow_camera_frame))
+            .add_system(orbit_camera.label(SystemTypes::CameraLabel))
+            .add_system(change_camera_lock.before(follow_camera_frame))
+            .add_system(follow_camera_frame
+                .label(SystemTypes::CameraLabel)
+                .after(orbit_camera))
+            .add_system(update_clipping_planes.after(SystemTypes::CameraLabel));
     }
 }
 
-/// Tags an entity as capable of panning and orbiting.
+/// Near plane of a perspective camera as a fraction of the distance to the closest surface.
+/// The projection is reversed and infinite with a floating point depth buffer, so the precision
+/// hardly depends on the near plane and it can stay well in front of anything drawn.
+pub const NEAR_PLANE_FRACTION: f32 = 0.01;
+
+/// Tags a camera that orbits the focused body. `FreeFlight` takes it over while flying freely.
 #[derive(Component)]
 pub struct PanOrbitCamera {
-    /// The "focus point" to orbit around. It is automatically updated when panning the camera
+    /// The "focus point" to orbit around, follows the focused body
     pub focus: Vec3,
     pub radius: f32,
     pub upside_down: bool,
+    /// Frame the camera keeps its orientation in
+    pub lock: CameraLock,
+    /// Radius the next fly-to arrives at instead of the usual distance from the body, a fly-to
+    /// starts even without a new focus
+    pub arrival_radius: Option<f32>,
+    // Orientation of that frame in the world, and the body it was taken from
+    frame: Quat,
+    frame_body: Option<Entity>,
 }
 
 impl Default for PanOrbitCamera {
@@ -31,44 +56,163 @@ impl Default for PanOrbitCamera {
             focus: Vec3::ZERO,
             radius: 3.0,
             upside_down: false,
+            lock: CameraLock::default(),
+            arrival_radius: None,
+            frame: Quat::IDENTITY,
+            frame_body: None,
+        }
+    }
+}
+
+impl PanOrbitCamera {
+    /// Orientation of the locked frame in the world, its Y axis is the up of orbiting.
+    pub fn frame(&self) -> Quat {
+        self.frame
+    }
+
+    /// Takes the frame as it is next frame instead of turning the camera with it, after the camera
+    /// was moved by something else.
+    pub fn reframe(&mut self) {
+        self.frame_body = None;
+    }
+}
+
+/// What the orientation of an orbiting camera is fixed to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CameraLock {
+    /// Fixed to the stars
+    #[default]
+    Inertial,
+    /// Turns with the spin of the focused body, so its surface stays still
+    BodyFixed,
+    /// Keeps the prograde and the direction of the body it orbits in place, so the orbit stays still
+    OrbitAligned,
+}
+
+impl CameraLock {
+    fn next(self) -> Self {
+        match self {
+            CameraLock::Inertial => CameraLock::BodyFixed,
+            CameraLock::BodyFixed => CameraLock::OrbitAligned,
+            CameraLock::OrbitAligned => CameraLock::Inertial,
         }
     }
 }
 
+/// Distance the camera flies to from a newly focused body, in radii of that body.
+pub const FOCUS_DISTANCE_IN_RADII: f32 = 4.0;
+
+/// A gamepad stick turns the camera like a mouse drag of this many logical pixels per notch.
+pub const PIXELS_PER_NOTCH: f32 = 40.0;
+
+/// Eased fly-to from the previous focus to the focused body, started whenever the focus changes.
+#[derive(Component, Debug)]
+pub struct CameraTransition {
+    start_focus: Vec3,
+    start_radius: f32,
+    /// Radius to arrive at, none once the user interrupted the flight
+    target_radius: Option<f32>,
+    elapsed: f32,
+    duration: f32,
+}
+
+impl CameraTransition {
+    fn new(start_focus: Vec3, start_radius: f32, target: Vec3, target_radius: f32) -> Self {
+        // Longer flights for longer trips, measured in sizes of the destination
+        let travel = (target - start_focus).length() / target_radius;
+
+        CameraTransition {
+            start_focus,
+            start_radius,
+            target_radius: Some(target_radius),
+            elapsed: 0.0,
+            duration: (0.5 + 0.15 * travel.ln_1p()).clamp(0.75, 2.5),
+        }
+    }
+
+    /// Focus and radius at this point of the flight towards a body now at `target`.
+    fn sample(&self, target: Vec3, radius: f32) -> (Vec3, f32) {
+        let t = (self.elapsed / self.duration).min(1.0);
+        // Ease in and out, so neither end of the flight jerks
+        let s = if t < 0.5 { 4.0 * t * t * t } else { 1.0 - (-2.0 * t + 2.0).powi(3) / 2.0 };
+
+        let focus = self.start_focus.lerp(target, s);
+
+        let Some(target_radius) = self.target_radius else {
+            return (focus, radius);
+        };
+
+        // Geometric so zooming from the whole system down to a moon takes as long at every scale,
+        // pulled back halfway so both ends of a long trip stay in view
+        let travel = (target - self.start_focus).length();
+        let radius = self.start_radius.powf(1.0 - s) * target_radius.powf(s)
+            + 0.5 * travel * (std::f32::consts::PI * s).sin();
+
+        (focus, radius)
+    }
+
+    fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// Ends the flight at the body it was heading to, leaving the radius where the user takes it.
+    fn interrupt(&mut self) {
+        self.elapsed = self.duration;
+        self.target_radius = None;
+    }
+}
 
 fn focus_camera(
-    mut key_evr: EventReader<KeyboardInput>,
-    mut cameras: Query<(&mut PanOrbitCamera, &mut Transform, &Projection)>,
-    mut query_focus: Query<(&mut FocusableEntity, &mut Transform), Without<PanOrbitCamera>>,
+    mut commands: Commands,
+    time: Res<Time>,
+    mut cameras: Query<(Entity, &mut PanOrbitCamera, &mut Transform, Option<&mut CameraTransition>), Without<FreeFlight>>,
+    query_focus: Query<(Entity, &FocusableEntity, &Transform, &CelestialBody), Without<PanOrbitCamera>>,
+    mut previous_focus: Local<Option<Entity>>,
 ) {
-    let change_focus_button = KeyCode::Tab;
+    // Nothing to look at when the scenario could not be loaded
+    let Some((focused, _, focused_pos, focused_body)) = query_focus.iter().find(|x| x.1.is_focused) else {
+        return;
+    };
 
-    for ev in key_evr.iter() {
-        // Change focus to the next object when the button is pressed
+    // The first focus keeps the overview of the whole system the camera starts with
+    let focus_changed = previous_focus.is_some_and(|previous| previous != focused);
+    *previous_focus = Some(focused);
 
-        if ev.state == ButtonState::Pressed && ev.key_code == Some(change_focus_button) {
-            let focused_item = query_focus.iter_mut().position(|x| x.0.is_focused);
+    for (camera, mut pan_orbit, mut transform, transition) in cameras.iter_mut() {
+        // Leaving free flight already puts the camera in orbit around the new focus
+        let already_there = pan_orbit.focus.distance(focused_pos.translation) <= focused_body.radius;
 
-            if focused_item.is_none() {
-                query_focus.iter_mut().next().unwrap().0.is_focused = true;
-            } else {
-                let index = focused_item.unwrap();
-                query_focus.iter_mut().nth(index).unwrap().0.is_focused = false;
+        let arrival_radius = pan_orbit.arrival_radius.take();
+        let start_flight = (focus_changed && !already_there) || arrival_radius.is_some();
 
-                if index == query_focus.iter().count() - 1 {
-                    query_focus.iter_mut().next().unwrap().0.is_focused = true;
-                } else {
-                    query_focus.iter_mut().nth(index + 1).unwrap().0.is_focused = true;
+        if start_flight {
+            let target_radius = arrival_radius
+                .unwrap_or_else(|| f32::max(focused_body.radius * FOCUS_DISTANCE_IN_RADII, focused_body.radius + 0.2));
+            commands.entity(camera).insert(CameraTransition::new(
+                pan_orbit.focus,
+                pan_orbit.radius,
+                focused_pos.translation,
+                target_radius,
+            ));
+        }
+
+        match transition {
+            // A new flight starts from wherever the current one got to
+            Some(mut transition) if !start_flight => {
+                transition.elapsed += time.delta_seconds();
+
+                let (focus, radius) = transition.sample(focused_pos.translation, pan_orbit.radius);
+                pan_orbit.focus = focus;
+                pan_orbit.radius = radius;
+
+                if transition.is_finished() {
+                    commands.entity(camera).remove::<CameraTransition>();
                 }
             }
+            _ if start_flight => {}
+            _ => pan_orbit.focus = focused_pos.translation,
         }
-    }
-
-    let focused = query_focus.iter().find(|x| x.0.is_focused).unwrap();
-    for (mut pan_orbit, mut transform, _) in cameras.iter_mut() {
-        pan_orbit.focus.x = focused.1.translation.x;
-        pan_orbit.focus.y = focused.1.translation.y;
-        pan_orbit.focus.z = focused.1.translation.z;
 
         let rot_matrix = Mat3::from_quat(transform.rotation);
         transform.translation =
@@ -76,39 +220,146 @@ fn focus_camera(
     }
 }
 
-/// Zoom with scroll wheel, orbit with right mouse click.
+/// L goes through the lock modes. The camera stays where it is, only what it follows changes.
+fn change_camera_lock(
+    actions: Res<ActionState>,
+    mut cameras: Query<&mut PanOrbitCamera>,
+) {
+    if actions.just_pressed(Action::CameraLock) {
+        for mut pan_orbit in cameras.iter_mut() {
+            pan_orbit.lock = pan_orbit.lock.next();
+            pan_orbit.reframe();
+            info!("Camera locked {:?}", pan_orbit.lock);
+        }
+    }
+}
+
+/// Orientation of the frame of `lock` for a body, none when the body has nothing to follow.
+pub fn lock_frame(
+    lock: CameraLock,
+    body: Entity,
+    config: &SolarSystemConfiguration,
+    bodies: &Query<(Entity, &Transform, &CelestialBody, Option<&Star>), Without<PanOrbitCamera>>,
+) -> Option<Quat> {
+    let (_, transform, celestial_body, _) = bodies.get(body).ok()?;
+
+    match lock {
+        CameraLock::Inertial => Some(Quat::IDENTITY),
+        CameraLock::BodyFixed => Some(transform.rotation),
+        CameraLock::OrbitAligned => {
+            // Moons orbit the planet named in the scenario, everything else the first star
+            let parent_name = config.solar_system.planets.iter()
+                .find(|planet| planet.name == celestial_body.name)
+                .and_then(|planet| planet.parent.as_deref());
+            let parent = match parent_name {
+                Some(name) => bodies.iter().find(|(_, _, candidate, _)| candidate.name == name),
+                None => bodies.iter().find(|(_, _, _, star)| star.is_some()),
+            };
+            let (parent, parent_transform, parent_body, _) = parent?;
+            if parent == body {
+                return None;
+            }
+
+            let radial = (transform.translation - parent_transform.translation).try_normalize()?;
+            let velocity = celestial_body.vel.vector - parent_body.vel.vector;
+            let normal = radial.cross(velocity).try_normalize()?;
+            let along_track = normal.cross(radial);
+
+            Some(Quat::from_mat3(&Mat3::from_cols(along_track, normal, radial)))
+        }
+    }
+}
+
+/// Turns the camera with its locked frame, so it keeps looking at the same side of the body or orbit.
+fn follow_camera_frame(
+    config: Res<SolarSystemConfiguration>,
+    mut cameras: Query<(&mut PanOrbitCamera, &mut Transform), Without<FreeFlight>>,
+    bodies: Query<(Entity, &Transform, &CelestialBody, Option<&Star>), Without<PanOrbitCamera>>,
+    focusables: Query<(Entity, &FocusableEntity)>,
+) {
+    let Some((focused, _)) = focusables.iter().find(|(_, focus)| focus.is_focused) else {
+        return;
+    };
+
+    for (mut pan_orbit, mut transform) in cameras.iter_mut() {
+        let frame = lock_frame(pan_orbit.lock, focused, &config, &bodies).unwrap_or(Quat::IDENTITY);
+
+        // A new body or mode only starts being followed, the camera does not jump to it
+        if pan_orbit.frame_body == Some(focused) {
+            let turn = frame * pan_orbit.frame.inverse();
+            transform.rotation = (turn * transform.rotation).normalize();
+        }
+
+        pan_orbit.frame = frame;
+        pan_orbit.frame_body = Some(focused);
+    }
+}
+
+/// Looks at the body named on the command line instead of the star.
+fn focus_initial_body(
+    options: Option<Res<StartupOptions>>,
+    mut bodies: Query<(&CelestialBody, &mut FocusableEntity)>,
+) {
+    let Some(name) = options.and_then(|options| options.focus.clone()) else {
+        return;
+    };
+
+    if !bodies.iter().any(|(body, _)| body.name.eq_ignore_ascii_case(&name)) {
+        warn!("There is no body named {name} to focus");
+        return;
+    }
+
+    for (body, mut focus) in bodies.iter_mut() {
+        focus.is_focused = body.name.eq_ignore_ascii_case(&name);
+    }
+}
+
+/// Zoom with scroll wheel, orbit with right mouse click. Any of them during a fly-to ends it,
+/// so the user gets the camera back at once.
 fn orbit_camera(
     windows: Res<Windows>,
+    map: Res<MapView>,
+    actions: Res<ActionState>,
     mut ev_motion: EventReader<MouseMotion>,
-    mut ev_scroll: EventReader<MouseWheel>,
-    input_mouse: Res<Input<MouseButton>>,
-    mut query_camera: Query<(&mut PanOrbitCamera, &mut Transform, &Projection)>,
+    mut query_camera: Query<(&mut PanOrbitCamera, &mut Transform, Option<&mut CameraTransition>), Without<FreeFlight>>,
     planets: Query<(&FocusableEntity, &CelestialBody)>,
 ) {
-    // change input mapping for orbit and panning here
-    let orbit_button = MouseButton::Right;
+    // The wheel zooms the map instead
+    if map.active {
+        ev_motion.clear();
+        return;
+    }
+
     let mut rotation_move = Vec2::ZERO;
-    let mut scroll = 0.0;
+    let scroll = actions.value(Action::Zoom);
     let mut orbit_button_changed = false;
 
-    if input_mouse.pressed(orbit_button) {
+    if actions.pressed(Action::Rotate) {
         for ev in ev_motion.iter() {
             rotation_move += ev.delta;
         }
     }
+    ev_motion.clear();
 
-    for ev in ev_scroll.iter() {
-        scroll += ev.y;
-    }
-    if input_mouse.just_released(orbit_button) || input_mouse.just_pressed(orbit_button) {
+    rotation_move += Vec2::new(actions.value(Action::RotateHorizontal), actions.value(Action::RotateVertical)) * PIXELS_PER_NOTCH;
+
+    if actions.just_released(Action::Rotate) || actions.just_pressed(Action::Rotate) {
         orbit_button_changed = true;
     }
 
-    for (mut pan_orbit, mut transform, _) in query_camera.iter_mut() {
+    let interrupt = actions.just_pressed(Action::Rotate) || rotation_move.length_squared() > 0.0 || scroll.abs() > 0.0;
+
+    for (mut pan_orbit, mut transform, transition) in query_camera.iter_mut() {
+        if interrupt {
+            if let Some(mut transition) = transition {
+                transition.interrupt();
+            }
+        }
+
         if orbit_button_changed {
             // only check for upside down when orbiting started or ended this frame
             // if the camera is "upside" down, panning horizontally would be inverted, so invert the input to make it correct
-            let up = transform.rotation * Vec3::Y;
+            let up = pan_orbit.frame.inverse() * transform.rotation * Vec3::Y;
             pan_orbit.upside_down = up.y <= 0.0;
         }
 
@@ -125,16 +376,16 @@ fn orbit_camera(
                 }
             };
             let delta_y = rotation_move.y / window.y * std::f32::consts::PI;
-            let yaw = Quat::from_rotation_y(-delta_x);
+            let yaw = Quat::from_axis_angle(pan_orbit.frame * Vec3::Y, -delta_x);
             let pitch = Quat::from_rotation_x(-delta_y);
-            transform.rotation = yaw * transform.rotation; // rotate around global y axis
+            transform.rotation = yaw * transform.rotation; // rotate around the y axis of the locked frame
             transform.rotation = transform.rotation * pitch; // rotate around local x axis
         } else if scroll.abs() > 0.0 {
             any = true;
             pan_orbit.radius -= scroll * pan_orbit.radius * 0.15;
 
             // minimum zoom is the radius of the currently focused body plus 1
-            let min_zoom = planets.iter().find(|x| x.0.is_focused).unwrap().1.radius;
+            let min_zoom = planets.iter().find(|x| x.0.is_focused).map_or(0.0, |x| x.1.radius);
             pan_orbit.radius = f32::max(pan_orbit.radius, min_zoom + 0.2);
         }
 
@@ -159,6 +410,64 @@ fn get_primary_window_size(windows: &Res<Windows>) -> Vec2 {
     window
 }
 
+/// Moves the near and far planes of every camera to just around the bodies, from a moon a few
+/// hundred meters away to planets at the other end of the system.
+fn update_clipping_planes(
+    mut cameras: Query<(&Transform, &mut Projection), With<Camera>>,
+    bodies: Query<(&Transform, &CelestialBody), Without<Camera>>,
+) {
+    for (camera_transform, mut projection) in cameras.iter_mut() {
+        let position = camera_transform.translation;
+        let forward = camera_transform.forward();
+
+        match &mut *projection {
+            Projection::Perspective(perspective) => {
+                let mut closest_surface = f32::MAX;
+                let mut farthest = 0.0_f32;
+                for (transform, body) in bodies.iter() {
+                    let distance = position.distance(transform.translation);
+                    closest_surface = closest_surface.min(distance - body.radius);
+                    farthest = farthest.max(distance + body.radius);
+                }
+
+                if farthest == 0.0 {
+                    continue;
+                }
+
+                // Only the frustum culling uses the far plane, the projection itself has none
+                let near = (closest_surface * NEAR_PLANE_FRACTION).clamp(1e-6, 10.0);
+                let far = (farthest * 1.5).max(near * 10.0);
+                if perspective.near != near || perspective.far != far {
+                    perspective.near = near;
+                    perspective.far = far;
+                }
+            }
+            Projection::Orthographic(orthographic) => {
+                // The depth of an orthographic camera is linear, the tighter the better, with room
+                // left for the particles that stray farther from the ecliptic than the bodies
+                let (nearest, farthest) = bodies.iter()
+                    .map(|(transform, body)| {
+                        let depth = (transform.translation - position).dot(forward);
+                        (depth - body.radius, depth + body.radius)
+                    })
+                    .fold((f32::MAX, f32::MIN), |(near, far), (body_near, body_far)| (near.min(body_near), far.max(body_far)));
+
+                if nearest > farthest {
+                    continue;
+                }
+
+                let margin = (farthest - nearest).max(1.0);
+                let near = (nearest - margin).max(0.0);
+                let far = farthest + margin;
+                if orthographic.near != near || orthographic.far != far {
+                    orthographic.near = near;
+                    orthographic.far = far;
+                }
+            }
+        }
+    }
+}
+
 /// Spawn a camera like this
 fn spawn_camera(mut commands: Commands) {
     let translation = Vec3::new(-2.0, 2.5, 5.0);
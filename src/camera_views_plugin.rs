@@ -0,0 +1,491 @@
+use std::{ops::{Add, Mul, Sub}, path::Path};
+
+use crate::{camera_plugin::*, focus_plugin::*, free_flight_plugin::*, input_map::*, planet_components::*, planet_models::*, scenario_formats::*, simulation_clock::*, startup_options::*, labels::*};
+use bevy::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+
+/// B bookmarks the view of the orbiting camera under a name typed in the search box, and Shift
+/// with 1 to 9 flies back to one of the bookmarks. K adds the view to the camera path as a
+/// keyframe, Shift+K clears the path, J plays it back along a spline over simulated time. Both
+/// are saved to the camera views file as they change.
+pub struct CameraViewsPlugin;
+
+impl Plugin for CameraViewsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraViews>()
+            .init_resource::<CameraPathPlayback>()
+            .add_system(save_bookmark.after(SystemTypes::CameraLabel))
+            .add_system(recall_bookmark.before(SystemTypes::CameraLabel))
+            .add_system(record_keyframe.after(SystemTypes::CameraLabel))
+            .add_system(clear_camera_path.before(record_keyframe))
+            .add_system(toggle_camera_path.before(SystemTypes::CameraLabel))
+            .add_system(play_camera_path.after(SystemTypes::CameraLabel));
+    }
+}
+
+/// A view of the orbiting camera, kept by the name of its body so it still works after a restart.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct CameraView {
+    pub focus: String,
+    /// Orientation of the camera in the frame of `lock`, as a quaternion
+    pub rotation: [f32; 4],
+    /// Distance from the center of the body, in Mm
+    pub radius: f32,
+    pub lock: CameraLock,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct CameraBookmark {
+    pub name: String,
+    pub view: CameraView,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct CameraKeyframe {
+    /// Simulated seconds since the first keyframe
+    pub time: f64,
+    pub view: CameraView,
+}
+
+/// Contents of the camera views file.
+#[derive(Deserialize, Serialize, Default, Clone, Debug)]
+pub struct CameraViewsModel {
+    #[serde(default)]
+    pub bookmarks: Vec<CameraBookmark>,
+    /// Keyframes of the camera path, in the order of their times
+    #[serde(default)]
+    pub path: Vec<CameraKeyframe>,
+}
+
+#[derive(Resource, Debug)]
+pub struct CameraViews {
+    pub model: CameraViewsModel,
+    /// File the views are read from and saved to, in any of the scenario formats
+    pub file: String,
+    // Simulation time of the last keyframe recorded since the start, in time units
+    last_keyframe_at: Option<f64>,
+}
+
+impl CameraViews {
+    fn save(&self) {
+        let result = ScenarioFormat::from_path(&self.file).write(&self.model)
+            .and_then(|text| std::fs::write(&self.file, text).map_err(|err| err.to_string()));
+
+        if let Err(err) = result {
+            error!("Could not save the camera views to {}: {err}", self.file);
+        }
+    }
+}
+
+fn load_camera_views(path: &str) -> Result<CameraViewsModel, String> {
+    let text = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let value = ScenarioFormat::from_path(path).parse(&text)?;
+    serde_json::from_value(value).map_err(|err| err.to_string())
+}
+
+impl FromWorld for CameraViews {
+    fn from_world(world: &mut World) -> Self {
+        let file = world.get_resource::<StartupOptions>()
+            .map_or_else(|| StartupOptions::default().camera_views_path, |options| options.camera_views_path.clone());
+
+        let model = if Path::new(&file).is_file() {
+            load_camera_views(&file).unwrap_or_else(|err| {
+                warn!("Could not read the camera views from {file}: {err}");
+                CameraViewsModel::default()
+            })
+        } else {
+            CameraViewsModel::default()
+        };
+
+        CameraViews { model, file, last_keyframe_at: None }
+    }
+}
+
+/// Whether the camera path is playing, and since when.
+#[derive(Resource, Debug)]
+pub struct CameraPathPlayback {
+    pub playing: bool,
+    // Simulation time the playback started at, in time units, set on its first frame
+    start: Option<f64>,
+}
+
+impl FromWorld for CameraPathPlayback {
+    fn from_world(world: &mut World) -> Self {
+        let playing = world.get_resource::<StartupOptions>().is_some_and(|options| options.play_camera_path);
+
+        CameraPathPlayback { playing, start: None }
+    }
+}
+
+type BodyQuery<'w, 's, 'a> = Query<'w, 's, (Entity, &'a Transform, &'a CelestialBody, Option<&'a Star>), Without<PanOrbitCamera>>;
+
+/// Value at `time` of the curve going through `values` at `times`, smooth through every keyframe
+/// and easing in and out at both ends. The times have to increase.
+fn sample_spline<T>(times: &[f64], values: &[T], time: f64) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f32, Output = T>,
+{
+    let last = values.len() - 1;
+
+    if time <= times[0] {
+        return values[0];
+    }
+    let Some(segment) = times.windows(2).position(|pair| time < pair[1]) else {
+        return values[last];
+    };
+
+    // Tangents by the neighbours of each keyframe, over time so keyframes unevenly spread out
+    // do not change the speed abruptly
+    let tangent = |i: usize| {
+        if i == 0 || i == last {
+            values[i] * 0.0
+        } else {
+            (values[i + 1] - values[i - 1]) * (1.0 / (times[i + 1] - times[i - 1]) as f32)
+        }
+    };
+
+    let duration = times[segment + 1] - times[segment];
+    let dt = duration as f32;
+    let u = ((time - times[segment]) / duration) as f32;
+    let (u2, u3) = (u * u, u * u * u);
+
+    // Cubic Hermite curve between the two keyframes
+    values[segment] * (2.0 * u3 - 3.0 * u2 + 1.0)
+        + tangent(segment) * ((u3 - 2.0 * u2 + u) * dt)
+        + values[segment + 1] * (3.0 * u2 - 2.0 * u3)
+        + tangent(segment + 1) * ((u3 - u2) * dt)
+}
+
+// The view of the camera orbiting the focused body, none while flying freely or without a focus
+fn current_view(
+    config: &SolarSystemConfiguration,
+    cameras: &Query<(&PanOrbitCamera, &Transform), Without<FreeFlight>>,
+    bodies: &BodyQuery,
+    focusables: &Query<(Entity, &FocusableEntity)>,
+) -> Option<CameraView> {
+    let (focused, _) = focusables.iter().find(|(_, focus)| focus.is_focused)?;
+    let (_, _, body, _) = bodies.get(focused).ok()?;
+    let (pan_orbit, transform) = cameras.iter().next()?;
+    let frame = lock_frame(pan_orbit.lock, focused, config, bodies).unwrap_or(Quat::IDENTITY);
+
+    Some(CameraView {
+        focus: body.name.clone(),
+        rotation: (frame.inverse() * transform.rotation).normalize().to_array(),
+        radius: pan_orbit.radius,
+        lock: pan_orbit.lock,
+    })
+}
+
+fn focus_body(focusables: &mut Query<(Entity, &mut FocusableEntity)>, target: Entity) {
+    for (entity, mut focus) in focusables.iter_mut() {
+        let is_focused = entity == target;
+        if focus.is_focused != is_focused {
+            focus.is_focused = is_focused;
+        }
+    }
+}
+
+/// Keeps the view when B is pressed and saves it once its name is typed. Without a name, bookmarks
+/// are named after their body, with a number from the second view of the same body on.
+#[allow(clippy::too_many_arguments)]
+fn save_bookmark(
+    actions: Res<ActionState>,
+    config: Res<SolarSystemConfiguration>,
+    mut views: ResMut<CameraViews>,
+    mut search: ResMut<FocusSearch>,
+    cameras: Query<(&PanOrbitCamera, &Transform), Without<FreeFlight>>,
+    bodies: BodyQuery,
+    focusables: Query<(Entity, &FocusableEntity)>,
+    mut pending: Local<Option<CameraView>>,
+) {
+    if actions.just_pressed(Action::SaveBookmark) {
+        *pending = current_view(&config, &cameras, &bodies, &focusables);
+        if pending.is_some() {
+            search.open_for(SearchPurpose::BookmarkName);
+        } else {
+            warn!("Only the views of the camera orbiting a body can be bookmarked");
+        }
+        return;
+    }
+
+    // Only taken once there is something, so the search is not marked as changed every frame
+    if search.purpose != SearchPurpose::BookmarkName || search.accepted.is_none() {
+        return;
+    }
+    let typed = search.accepted.take().unwrap_or_default();
+    let Some(view) = pending.take() else {
+        return;
+    };
+
+    let same_body = views.model.bookmarks.iter().filter(|bookmark| bookmark.view.focus == view.focus).count();
+    let name = match (typed.trim(), same_body) {
+        ("", 0) => view.focus.clone(),
+        ("", _) => format!("{} {}", view.focus, same_body + 1),
+        (typed, _) => typed.to_string(),
+    };
+
+    views.model.bookmarks.push(CameraBookmark { name: name.clone(), view });
+    info!("Saved the view of {name} as bookmark {}", views.model.bookmarks.len());
+    views.save();
+}
+
+/// Flies to the body of the bookmark and on to its distance, turned as it was saved.
+#[allow(clippy::too_many_arguments)]
+fn recall_bookmark(
+    mut commands: Commands,
+    actions: Res<ActionState>,
+    config: Res<SolarSystemConfiguration>,
+    views: Res<CameraViews>,
+    mut playback: ResMut<CameraPathPlayback>,
+    mut cameras: Query<(Entity, &mut PanOrbitCamera, &mut Transform)>,
+    bodies: BodyQuery,
+    mut focusables: Query<(Entity, &mut FocusableEntity)>,
+) {
+    let bookmark_actions = [
+        Action::Bookmark1, Action::Bookmark2, Action::Bookmark3, Action::Bookmark4, Action::Bookmark5,
+        Action::Bookmark6, Action::Bookmark7, Action::Bookmark8, Action::Bookmark9,
+    ];
+
+    let Some(index) = bookmark_actions.iter().position(|action| actions.just_pressed(*action)) else {
+        return;
+    };
+    let Some(bookmark) = views.model.bookmarks.get(index) else {
+        warn!("There is no bookmark {}", index + 1);
+        return;
+    };
+    let Some((target, _, _, _)) = bodies.iter().find(|(_, _, body, _)| body.name == bookmark.view.focus) else {
+        warn!("There is no body named {} for the bookmark {}", bookmark.view.focus, bookmark.name);
+        return;
+    };
+
+    let frame = lock_frame(bookmark.view.lock, target, &config, &bodies).unwrap_or(Quat::IDENTITY);
+
+    for (camera, mut pan_orbit, mut transform) in cameras.iter_mut() {
+        pan_orbit.lock = bookmark.view.lock;
+        pan_orbit.arrival_radius = Some(bookmark.view.radius);
+        pan_orbit.reframe();
+        transform.rotation = frame * Quat::from_array(bookmark.view.rotation).normalize();
+        commands.entity(camera).remove::<FreeFlight>();
+    }
+
+    focus_body(&mut focusables, target);
+    playback.playing = false;
+    info!("Back to {}", bookmark.name);
+}
+
+/// Keyframes continue the path of the camera views file, each one later in simulated time.
+/// The first one since the start comes after the last keyframe by the time simulated so far.
+fn record_keyframe(
+    actions: Res<ActionState>,
+    clock: Res<SimulationClock>,
+    config: Res<SolarSystemConfiguration>,
+    mut views: ResMut<CameraViews>,
+    cameras: Query<(&PanOrbitCamera, &Transform), Without<FreeFlight>>,
+    bodies: BodyQuery,
+    focusables: Query<(Entity, &FocusableEntity)>,
+) {
+    if !actions.just_pressed(Action::RecordKeyframe) {
+        return;
+    }
+
+    let Some(view) = current_view(&config, &cameras, &bodies, &focusables) else {
+        warn!("Only the views of the camera orbiting a body can be keyframes");
+        return;
+    };
+
+    let time = match views.model.path.last() {
+        Some(last) => {
+            let since_last = clock.elapsed - views.last_keyframe_at.unwrap_or(0.0);
+            if since_last <= 0.0 {
+                warn!("No simulated time passed since the last keyframe, let the simulation run between keyframes");
+                return;
+            }
+            last.time + since_last * clock.seconds_per_time_unit
+        }
+        None => 0.0,
+    };
+
+    views.last_keyframe_at = Some(clock.elapsed);
+    views.model.path.push(CameraKeyframe { time, view });
+    info!("Keyframe {} at {time:.0} s", views.model.path.len());
+    views.save();
+}
+
+/// Shift+K throws the path away, so the next keyframe starts a new one.
+fn clear_camera_path(
+    actions: Res<ActionState>,
+    mut views: ResMut<CameraViews>,
+    mut playback: ResMut<CameraPathPlayback>,
+) {
+    if !actions.just_pressed(Action::ClearCameraPath) {
+        return;
+    }
+
+    views.model.path.clear();
+    views.last_keyframe_at = None;
+    playback.playing = false;
+    info!("Cleared the camera path");
+    views.save();
+}
+
+fn toggle_camera_path(
+    actions: Res<ActionState>,
+    mut playback: ResMut<CameraPathPlayback>,
+    mut cameras: Query<&mut PanOrbitCamera>,
+) {
+    if !actions.just_pressed(Action::PlayCameraPath) {
+        return;
+    }
+
+    playback.playing = !playback.playing;
+    playback.start = None;
+
+    // Back to orbiting from wherever the path left the camera
+    if !playback.playing {
+        for mut pan_orbit in cameras.iter_mut() {
+            pan_orbit.reframe();
+        }
+    }
+}
+
+/// Moves the camera along the path, between the keyframes as the bodies are now. The focus
+/// follows the nearest keyframe.
+#[allow(clippy::too_many_arguments)]
+fn play_camera_path(
+    mut commands: Commands,
+    clock: Res<SimulationClock>,
+    config: Res<SolarSystemConfiguration>,
+    views: Res<CameraViews>,
+    mut playback: ResMut<CameraPathPlayback>,
+    mut cameras: Query<(Entity, &mut PanOrbitCamera, &mut Transform), Without<FreeFlight>>,
+    bodies: BodyQuery,
+    mut focusables: Query<(Entity, &mut FocusableEntity)>,
+) {
+    if !playback.playing {
+        return;
+    }
+
+    let path = &views.model.path;
+    let keyframes: Option<Vec<(Entity, Vec3, Quat)>> = path.iter()
+        .map(|keyframe| {
+            let (body, transform, _, _) = bodies.iter().find(|(_, _, body, _)| body.name == keyframe.view.focus)?;
+            let frame = lock_frame(keyframe.view.lock, body, &config, &bodies).unwrap_or(Quat::IDENTITY);
+            Some((body, transform.translation, frame * Quat::from_array(keyframe.view.rotation).normalize()))
+        })
+        .collect();
+
+    let Some(keyframes) = keyframes.filter(|keyframes| keyframes.len() >= 2) else {
+        warn!("The camera path needs at least two keyframes, all of them on bodies of this system");
+        playback.playing = false;
+        return;
+    };
+
+    let start = *playback.start.get_or_insert(clock.elapsed);
+    let time = (clock.elapsed - start) * clock.seconds_per_time_unit;
+    let times: Vec<f64> = path.iter().map(|keyframe| keyframe.time).collect();
+
+    let focuses: Vec<Vec3> = keyframes.iter().map(|(_, position, _)| *position).collect();
+    // Geometric, so going from the whole system down to a moon takes as long at every scale
+    let log_radii: Vec<f32> = path.iter().map(|keyframe| keyframe.view.radius.ln()).collect();
+    // Each quaternion on the side of the previous one, so the camera turns the short way round
+    let mut rotations: Vec<Vec4> = Vec::with_capacity(keyframes.len());
+    for (_, _, rotation) in keyframes.iter() {
+        let rotation = Vec4::from(*rotation);
+        match rotations.last() {
+            Some(previous) if previous.dot(rotation) < 0.0 => rotations.push(-rotation),
+            _ => rotations.push(rotation),
+        }
+    }
+
+    let focus = sample_spline(&times, &focuses, time);
+    let radius = sample_spline(&times, &log_radii, time).exp();
+    let rotation = Quat::from_vec4(sample_spline(&times, &rotations, time)).normalize();
+
+    let nearest = times.iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| (*a - time).abs().total_cmp(&(*b - time).abs()))
+        .map_or(0, |(index, _)| index);
+    focus_body(&mut focusables, keyframes[nearest].0);
+
+    for (camera, mut pan_orbit, mut transform) in cameras.iter_mut() {
+        // The path moves the camera, not the fly-to of the focus changes along the way
+        commands.entity(camera).remove::<CameraTransition>();
+
+        pan_orbit.focus = focus;
+        pan_orbit.radius = radius;
+        pan_orbit.lock = path[nearest].view.lock;
+        pan_orbit.reframe();
+        transform.rotation = rotation;
+        transform.translation = focus + rotation * Vec3::new(0.0, 0.0, radius);
+    }
+
+    if time >= times[times.len() - 1] {
+        playback.playing = false;
+        playback.start = None;
+        info!("End of the camera path");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!((actual - expected).abs() <= tolerance, "{actual} is not within {tolerance} of {expected}");
+    }
+
+    #[test]
+    fn goes_through_every_keyframe() {
+        let times = [0.0, 10.0, 25.0, 60.0];
+        let values = [1.0_f32, -3.0, 4.0, 2.0];
+
+        for (time, value) in times.iter().zip(values.iter()) {
+            assert_close(sample_spline(&times, &values, *time), *value, 1e-5);
+        }
+    }
+
+    #[test]
+    fn holds_the_ends_outside_of_the_path() {
+        let times = [5.0, 10.0];
+        let values = [Vec3::X, Vec3::Y];
+
+        assert_eq!(sample_spline(&times, &values, -100.0), Vec3::X);
+        assert_eq!(sample_spline(&times, &values, 1e9), Vec3::Y);
+    }
+
+    #[test]
+    fn eases_in_and_out_between_two_keyframes() {
+        let times = [0.0, 100.0];
+        let values = [0.0_f32, 1.0];
+
+        assert_close(sample_spline(&times, &values, 50.0), 0.5, 1e-6);
+        // Standing still at both ends, so the first and last percent of the time barely move
+        assert!(sample_spline(&times, &values, 1.0) < 0.001);
+        assert!(sample_spline(&times, &values, 99.0) > 0.999);
+    }
+
+    #[test]
+    fn keeps_a_steady_speed_through_uneven_keyframes() {
+        // Keyframes of a motion at a constant speed, unevenly spread out in time
+        let times = [0.0, 1.0, 4.0, 5.0, 11.0];
+        let values: Vec<f32> = times.iter().map(|time| 3.0 * *time as f32).collect();
+
+        // Away from the eased ends, the curve is the motion itself
+        for time in [1.5, 2.0, 3.0, 4.5] {
+            assert_close(sample_spline(&times, &values, time), 3.0 * time as f32, 1e-4);
+        }
+    }
+
+    #[test]
+    fn is_smooth_through_the_keyframes() {
+        let times = [0.0, 2.0, 7.0, 8.0];
+        let values = [Vec3::ZERO, Vec3::new(4.0, 1.0, -2.0), Vec3::new(-1.0, 6.0, 0.0), Vec3::new(3.0, 3.0, 3.0)];
+        let h = 1e-3;
+
+        for keyframe in [2.0, 7.0] {
+            let before = (sample_spline(&times, &values, keyframe) - sample_spline(&times, &values, keyframe - h)) / h as f32;
+            let after = (sample_spline(&times, &values, keyframe + h) - sample_spline(&times, &values, keyframe)) / h as f32;
+            assert!((before - after).length() < 0.05, "{before} then {after} at {keyframe}");
+        }
+    }
+}
@@ -0,0 +1,404 @@
+// Reading and writing the ConfigNode format of KSP and its mods:
+//
+//     Node
+//     {
+//         key = value
+//         Child { key = value }
+//     }
+
+use std::fmt::Write;
+
+#[derive(Clone, Debug, Default)]
+pub struct ConfigNode {
+    /// Name of the node, with any patch operator, e.g. `@Kopernicus:FOR[Pack]`
+    pub name: String,
+    pub values: Vec<(String, String)>,
+    pub nodes: Vec<ConfigNode>,
+}
+
+#[derive(Debug)]
+pub struct ConfigNodeError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ConfigNodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ConfigNodeError {}
+
+// ModuleManager operators in front of patched node and value names
+const PATCH_OPERATORS: [char; 7] = ['@', '%', '&', '!', '+', '$', '-'];
+
+impl ConfigNode {
+    pub fn new(name: &str) -> Self {
+        ConfigNode {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Name without the patch operator in front and the `:FOR[...]`-like passes after it.
+    pub fn base_name(&self) -> &str {
+        let name = self.name.trim_start_matches(PATCH_OPERATORS);
+        let name = name.split(':').next().unwrap_or(name);
+        name.split('[').next().unwrap_or(name).trim()
+    }
+
+    /// Patch operator in front of the name, e.g. `@` for `@Body[Kerbin]`.
+    pub fn operator(&self) -> Option<char> {
+        self.name.trim_start().chars().next().filter(|first| PATCH_OPERATORS.contains(first))
+    }
+
+    /// Name of the nodes a patch applies to, e.g. `Kerbin` for `@Body[Kerbin]:FOR[Pack]`.
+    pub fn name_filter(&self) -> Option<&str> {
+        let name = self.name.split(':').next().unwrap_or(&self.name);
+        let (_, filter) = name.split_once('[')?;
+        filter.split(']').next().map(str::trim)
+    }
+
+    /// Applies the edits of a ModuleManager patch to this node: `@` edits, `%` edits or
+    /// creates, `&` creates when missing, `!` and `-` delete, `+` and `$` copy, and values
+    /// or nodes without an operator are added. Numbers can be edited with `@key *= 2`.
+    pub fn apply_patch(&mut self, patch: &ConfigNode) {
+        for (key, value) in patch.values.iter() {
+            self.apply_value(key, value);
+        }
+        for node in patch.nodes.iter() {
+            self.apply_node(node);
+        }
+    }
+
+    fn apply_value(&mut self, key: &str, value: &str) {
+        let operator = key.chars().next().filter(|first| PATCH_OPERATORS.contains(first));
+        let key = key.trim_start_matches(PATCH_OPERATORS).trim_end();
+        let (key, arithmetic) = match key.chars().last() {
+            Some(last @ ('+' | '-' | '*' | '/')) => (key[..key.len() - 1].trim_end(), Some(last)),
+            _ => (key, None),
+        };
+        let position = self.values.iter().position(|(name, _)| name == key);
+
+        match (operator, position) {
+            (Some('@' | '%'), Some(index)) => {
+                let edited = edited_value(&self.values[index].1, arithmetic, value);
+                self.values[index].1 = edited;
+            }
+            (Some('!' | '-'), Some(index)) => {
+                self.values.remove(index);
+            }
+            (Some('%' | '&'), None) | (None, _) => self.values.push((key.to_string(), value.to_string())),
+            _ => {}
+        }
+    }
+
+    fn apply_node(&mut self, patch: &ConfigNode) {
+        let position = self.nodes.iter().position(|node| {
+            node.base_name() == patch.base_name()
+                && patch.name_filter().is_none_or(|filter| node.value("name").map(str::trim) == Some(filter))
+        });
+
+        match (patch.operator(), position) {
+            (Some('@' | '%'), Some(index)) => self.nodes[index].apply_patch(patch),
+            (Some('!' | '-'), Some(index)) => {
+                self.nodes.remove(index);
+            }
+            (Some('+' | '$'), Some(index)) => {
+                let mut copy = self.nodes[index].clone();
+                copy.apply_patch(patch);
+                self.nodes.push(copy);
+            }
+            (Some('%' | '&'), None) | (None, _) => {
+                let mut node = ConfigNode::new(patch.base_name());
+                node.apply_patch(patch);
+                self.nodes.push(node);
+            }
+            _ => {}
+        }
+    }
+
+    /// First value with the given key, ignoring the `@` and `%` edit operators.
+    pub fn value(&self, key: &str) -> Option<&str> {
+        self.values.iter()
+            .find(|(name, _)| name.trim_start_matches(['@', '%']) == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// First value with the given key that parses as a number.
+    pub fn number(&self, key: &str) -> Option<f64> {
+        self.value(key).and_then(|value| value.trim().parse().ok())
+    }
+
+    /// First child node with the given name, ignoring patch operators.
+    pub fn node(&self, name: &str) -> Option<&ConfigNode> {
+        self.nodes.iter().find(|node| node.base_name() == name)
+    }
+
+    pub fn with_value(mut self, key: &str, value: impl ToString) -> Self {
+        self.values.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_node(mut self, node: ConfigNode) -> Self {
+        self.nodes.push(node);
+        self
+    }
+
+    fn write(&self, out: &mut String, depth: usize) {
+        let indent = "\t".repeat(depth);
+        let _ = writeln!(out, "{indent}{}", self.name);
+        let _ = writeln!(out, "{indent}{{");
+
+        for (key, value) in self.values.iter() {
+            let _ = writeln!(out, "{indent}\t{key} = {value}");
+        }
+
+        for node in self.nodes.iter() {
+            node.write(out, depth + 1);
+        }
+
+        let _ = writeln!(out, "{indent}}}");
+    }
+}
+
+// `@radius *= 2` edits the number in place, anything else replaces the value
+fn edited_value(current: &str, arithmetic: Option<char>, value: &str) -> String {
+    let Some(arithmetic) = arithmetic else {
+        return value.to_string();
+    };
+    let (Ok(current_number), Ok(operand)) = (current.trim().parse::<f64>(), value.trim().parse::<f64>()) else {
+        return current.to_string();
+    };
+
+    let result = match arithmetic {
+        '+' => current_number + operand,
+        '-' => current_number - operand,
+        '*' => current_number * operand,
+        _ => current_number / operand,
+    };
+    result.to_string()
+}
+
+/// Formats nodes the way KSP writes them, one value per line and tabs for indentation.
+pub fn write_config_nodes(nodes: &[ConfigNode]) -> String {
+    let mut out = String::new();
+    for node in nodes {
+        node.write(&mut out, 0);
+    }
+    out
+}
+
+/// Parses the nodes at the top level of a config file.
+pub fn parse_config_nodes(text: &str) -> Result<Vec<ConfigNode>, ConfigNodeError> {
+    // The last element holds the top level values and nodes
+    let mut stack = vec![ConfigNode::default()];
+    // Name read on a line of its own, waiting for the opening brace on the next one
+    let mut pending_name: Option<String> = None;
+
+    for (index, raw_line) in text.lines().enumerate() {
+        let line = index + 1;
+        let content = raw_line.split("//").next().unwrap_or_default();
+
+        // Braces can share a line with names and values: `Orbit { referenceBody = Sun }`
+        let mut rest = content;
+        while !rest.trim().is_empty() {
+            let brace = rest.find(['{', '}']);
+            let (piece, brace_char) = match brace {
+                Some(position) => (&rest[..position], rest[position..].chars().next()),
+                None => (rest, None),
+            };
+            let piece = piece.trim();
+
+            if !piece.is_empty() {
+                if let Some((key, value)) = piece.split_once('=') {
+                    if let Some(name) = pending_name.take() {
+                        return Err(ConfigNodeError { line, message: format!("{name} is not followed by {{") });
+                    }
+                    stack.last_mut().unwrap().values.push((key.trim().to_string(), value.trim().to_string()));
+                } else if let Some(name) = pending_name.replace(piece.to_string()) {
+                    return Err(ConfigNodeError { line, message: format!("{name} is not followed by {{") });
+                }
+            }
+
+            match brace_char {
+                Some('{') => {
+                    let name = pending_name.take().ok_or_else(|| ConfigNodeError {
+                        line,
+                        message: "{ without a node name".to_string(),
+                    })?;
+                    stack.push(ConfigNode::new(&name));
+                }
+                Some('}') => {
+                    if stack.len() == 1 {
+                        return Err(ConfigNodeError { line, message: "unexpected }".to_string() });
+                    }
+                    let node = stack.pop().unwrap();
+                    stack.last_mut().unwrap().nodes.push(node);
+                }
+                _ => {}
+            }
+
+            rest = match brace {
+                Some(position) => &rest[position + 1..],
+                None => "",
+            };
+        }
+    }
+
+    if stack.len() > 1 {
+        return Err(ConfigNodeError {
+            line: text.lines().count(),
+            message: format!("{} is never closed", stack.last().unwrap().name),
+        });
+    }
+
+    Ok(stack.pop().unwrap().nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_one(text: &str) -> ConfigNode {
+        let mut nodes = parse_config_nodes(text).unwrap();
+        assert_eq!(nodes.len(), 1);
+        nodes.remove(0)
+    }
+
+    #[test]
+    fn nested_nodes_keep_their_values() {
+        let kopernicus = parse_one("
+            @Kopernicus:AFTER[Kopernicus]
+            {
+                Body
+                {
+                    name = Tylo
+                    Properties
+                    {
+                        radius = 600000
+                    }
+                    Orbit { referenceBody = Jool }
+                }
+            }
+        ");
+
+        assert_eq!(kopernicus.base_name(), "Kopernicus");
+        let body = kopernicus.node("Body").unwrap();
+        assert_eq!(body.value("name"), Some("Tylo"));
+        assert_eq!(body.node("Properties").unwrap().number("radius"), Some(600000.0));
+        assert_eq!(body.node("Orbit").unwrap().value("referenceBody"), Some("Jool"));
+    }
+
+    #[test]
+    fn comments_are_ignored() {
+        let body = parse_one("
+            // A moon of Jool { not a node }
+            Body // trailing comment
+            {
+                name = Laythe // the ocean moon
+                // radius = 1
+                description = Half-way // there
+            }
+        ");
+
+        assert_eq!(body.values, vec![
+            ("name".to_string(), "Laythe".to_string()),
+            ("description".to_string(), "Half-way".to_string()),
+        ]);
+        assert!(body.nodes.is_empty());
+    }
+
+    #[test]
+    fn unbalanced_braces_are_errors() {
+        let unclosed = parse_config_nodes("Body\n{\n\tname = Eeloo\n").unwrap_err();
+        assert_eq!(unclosed.line, 3);
+
+        let unexpected = parse_config_nodes("Body { }\n}").unwrap_err();
+        assert_eq!(unexpected.line, 2);
+
+        assert!(parse_config_nodes("Body\nname = Eeloo").is_err());
+    }
+
+    #[test]
+    fn written_nodes_are_read_back() {
+        let node = ConfigNode::new("Body")
+            .with_value("name", "Dres")
+            .with_node(ConfigNode::new("Orbit").with_value("semiMajorAxis", 40839348203.0_f64));
+
+        let parsed = parse_config_nodes(&write_config_nodes(&[node])).unwrap();
+        assert_eq!(parsed[0].value("name"), Some("Dres"));
+        assert_eq!(parsed[0].node("Orbit").unwrap().number("semiMajorAxis"), Some(40839348203.0));
+    }
+
+    #[test]
+    fn patch_names_give_their_operator_and_target() {
+        let patch = ConfigNode::new("@Body[Kerbin]:FOR[Pack]");
+        assert_eq!(patch.operator(), Some('@'));
+        assert_eq!(patch.base_name(), "Body");
+        assert_eq!(patch.name_filter(), Some("Kerbin"));
+
+        let body = ConfigNode::new("Body");
+        assert_eq!(body.operator(), None);
+        assert_eq!(body.name_filter(), None);
+    }
+
+    #[test]
+    fn patches_edit_add_and_delete() {
+        let mut body = parse_one("
+            Body
+            {
+                name = Kerbin
+                cacheFile = kerbin.bin
+                Properties { radius = 600000
+                    description = Home }
+                Orbit { referenceBody = Sun }
+                PQS { }
+            }
+        ");
+        let patch = parse_one("
+            @Body[Kerbin]
+            {
+                @Properties
+                {
+                    @radius *= 2
+                    %geeASL = 1
+                    !description = DEL
+                }
+                @Orbit { @referenceBody = Kerbol }
+                !PQS { }
+                !cacheFile = DEL
+                Atmosphere { enabled = true }
+            }
+        ");
+
+        body.apply_patch(&patch);
+
+        let properties = body.node("Properties").unwrap();
+        assert_eq!(properties.number("radius"), Some(1200000.0));
+        assert_eq!(properties.number("geeASL"), Some(1.0));
+        assert_eq!(properties.value("description"), None);
+        assert_eq!(body.node("Orbit").unwrap().value("referenceBody"), Some("Kerbol"));
+        assert!(body.node("PQS").is_none());
+        assert_eq!(body.value("cacheFile"), None);
+        assert_eq!(body.node("Atmosphere").unwrap().value("enabled"), Some("true"));
+        assert_eq!(body.value("name"), Some("Kerbin"));
+    }
+
+    #[test]
+    fn copy_patches_leave_the_original() {
+        let mut parent = parse_one("
+            Kopernicus
+            {
+                Body { name = Mun }
+            }
+        ");
+        let patch = parse_one("+Body[Mun] { @name = Minmus }");
+
+        parent.apply_patch(&ConfigNode::new("@Kopernicus").with_node(patch));
+
+        let names: Vec<_> = parent.nodes.iter().filter_map(|body| body.value("name")).collect();
+        assert_eq!(names, ["Mun", "Minmus"]);
+    }
+}
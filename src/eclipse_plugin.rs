@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+
+use crate::{input_map::*, planet_components::*, planet_models::*, simulation_clock::*, solar_system_plugin::*, time_warp_plugin::*, labels::*};
+use bevy::prelude::*;
+
+pub struct EclipsePlugin;
+
+impl Plugin for EclipsePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EclipseSettings>()
+            .init_resource::<EclipseLog>()
+            .add_startup_system(setup_eclipse_log_text)
+            .add_system(detect_eclipses
+                .with_run_criteria(simulation_running)
+                .label(SystemTypes::EventDetectionLabel)
+                .after(SystemTypes::PhysicsLabel)
+                .before(SystemTypes::CameraLabel))
+            .add_system(clear_eclipse_log
+                .before(SystemTypes::EventDetectionLabel))
+            .add_system(browse_eclipse_log
+                .before(SystemTypes::CameraLabel))
+            .add_system(update_eclipse_log_text);
+    }
+}
+
+#[derive(Resource)]
+pub struct EclipseSettings {
+    /// Contact times are refined until they are known to within this many time units
+    pub time_tolerance: f32,
+    /// Minimum ratio between the apparent radius of the occluder and the one of the star
+    /// for the event to be called an eclipse instead of a transit
+    pub eclipse_min_coverage: f32,
+}
+
+impl Default for EclipseSettings {
+    fn default() -> Self {
+        EclipseSettings {
+            time_tolerance: 0.01,
+            eclipse_min_coverage: 0.5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EclipseKind {
+    /// A body hides a large part of a star
+    Eclipse,
+    /// A body crosses a disc that looks bigger than its own
+    Transit,
+    /// A body hides another body that looks smaller than itself
+    Occultation,
+}
+
+#[derive(Clone, Debug)]
+pub struct EclipseEvent {
+    pub kind: EclipseKind,
+    pub observer: Entity,
+    pub observer_name: String,
+    pub occluder_name: String,
+    pub target_name: String,
+    pub start: f64,
+    /// `None` while the event is still in progress
+    pub end: Option<f64>,
+}
+
+/// Every eclipse, transit and occultation seen so far, in the order they started.
+#[derive(Resource, Default)]
+pub struct EclipseLog {
+    pub events: Vec<EclipseEvent>,
+    pub selected: Option<usize>,
+    // Index in `events` of the events still in progress, by (observer, occluder, target)
+    ongoing: HashMap<(Entity, Entity, Entity), usize>,
+}
+
+#[derive(Component)]
+struct EclipseLogText;
+
+/// State of a body at both ends of the last physics step.
+struct BodySnapshot {
+    entity: Entity,
+    name: String,
+    radius: f32,
+    is_star: bool,
+    previous: PreviousState,
+    position: Vec3,
+    velocity: Vec3,
+}
+
+impl BodySnapshot {
+    fn position_at(&self, dv: f32, s: f32) -> Vec3 {
+        self.previous.interpolate(self.position, self.velocity, dv, s)
+    }
+}
+
+/// Returns the ratio between the apparent radii of the occluder and the target
+/// if the occluder covers part of the target as seen from the observer.
+fn apparent_overlap(observer: Vec3, occluder: Vec3, occluder_radius: f32, target: Vec3, target_radius: f32) -> Option<f32> {
+    let to_occluder = occluder - observer;
+    let to_target = target - observer;
+    let occluder_distance = to_occluder.length();
+    let target_distance = to_target.length();
+
+    // The observer is inside one of the bodies, or the occluder is behind the target
+    if occluder_distance <= occluder_radius || target_distance <= target_radius || occluder_distance >= target_distance {
+        return None;
+    }
+
+    let occluder_angle = (occluder_radius / occluder_distance).asin();
+    let target_angle = (target_radius / target_distance).asin();
+
+    if to_occluder.angle_between(to_target) < occluder_angle + target_angle {
+        Some(occluder_angle / target_angle)
+    } else {
+        None
+    }
+}
+
+fn overlap_at(bodies: &[BodySnapshot], (observer, occluder, target): (usize, usize, usize), dv: f32, s: f32) -> Option<f32> {
+    apparent_overlap(
+        bodies[observer].position_at(dv, s),
+        bodies[occluder].position_at(dv, s),
+        bodies[occluder].radius,
+        bodies[target].position_at(dv, s),
+        bodies[target].radius,
+    )
+}
+
+fn detect_eclipses(
+    bodies: Query<(Entity, &Transform, &CelestialBody, &PreviousState, Option<&Star>)>,
+    clock: Res<SimulationClock>,
+    config: Res<SolarSystemConfiguration>,
+    settings: Res<EclipseSettings>,
+    mut log: ResMut<EclipseLog>,
+) {
+    let dv = config.physical_constants.dv;
+    let step_start = clock.elapsed - dv as f64;
+
+    let snapshots: Vec<BodySnapshot> = bodies
+        .iter()
+        .map(|(entity, pos, body, previous, star)| BodySnapshot {
+            entity,
+            name: body.name.clone(),
+            radius: body.radius,
+            is_star: star.is_some(),
+            previous: PreviousState {
+                position: previous.position,
+                velocity: previous.velocity,
+            },
+            position: pos.translation,
+            velocity: body.vel.vector,
+        })
+        .collect();
+
+    // A body replaced by a hot reload gets a new entity, so its events in progress can never
+    // end by themselves: close them where the body disappeared
+    let EclipseLog { events, ongoing, .. } = &mut *log;
+    ongoing.retain(|(observer, occluder, target), index| {
+        let resolved = [observer, occluder, target]
+            .iter()
+            .all(|entity| snapshots.iter().any(|snapshot| snapshot.entity == **entity));
+        if !resolved {
+            events[*index].end = Some(step_start);
+        }
+        resolved
+    });
+
+    let count = snapshots.len();
+    for observer in 0..count {
+        for occluder in 0..count {
+            for target in 0..count {
+                if observer == occluder || observer == target || occluder == target {
+                    continue;
+                }
+
+                let triple = (observer, occluder, target);
+                let key = (snapshots[observer].entity, snapshots[occluder].entity, snapshots[target].entity);
+
+                let was_overlapping = log.ongoing.contains_key(&key);
+                let overlap = overlap_at(&snapshots, triple, dv, 1.0);
+
+                if overlap.is_some() == was_overlapping {
+                    continue;
+                }
+
+                // Bisect the step until the contact is pinned down to the tolerance
+                let mut low = 0.0;
+                let mut high = 1.0;
+                let mut iterations = 0;
+                while (high - low) * dv > settings.time_tolerance && iterations < 64 {
+                    let mid = 0.5 * (low + high);
+                    if overlap_at(&snapshots, triple, dv, mid).is_some() == was_overlapping {
+                        low = mid;
+                    } else {
+                        high = mid;
+                    }
+                    iterations += 1;
+                }
+                let contact_time = step_start + (0.5 * (low + high) * dv) as f64;
+
+                if let Some(ratio) = overlap {
+                    let kind = if snapshots[target].is_star && ratio >= settings.eclipse_min_coverage {
+                        EclipseKind::Eclipse
+                    } else if ratio < 1.0 {
+                        EclipseKind::Transit
+                    } else {
+                        EclipseKind::Occultation
+                    };
+
+                    let index = log.events.len();
+                    log.events.push(EclipseEvent {
+                        kind,
+                        observer: snapshots[observer].entity,
+                        observer_name: snapshots[observer].name.clone(),
+                        occluder_name: snapshots[occluder].name.clone(),
+                        target_name: snapshots[target].name.clone(),
+                        start: contact_time,
+                        end: None,
+                    });
+                    log.ongoing.insert(key, index);
+                } else if let Some(index) = log.ongoing.remove(&key) {
+                    log.events[index].end = Some(contact_time);
+                }
+            }
+        }
+    }
+}
+
+/// The events refer to bodies that no longer exist once the system is respawned.
+fn clear_eclipse_log(
+    mut respawn_evr: EventReader<RespawnSolarSystem>,
+    mut log: ResMut<EclipseLog>,
+) {
+    if respawn_evr.iter().count() > 0 {
+        *log = EclipseLog::default();
+    }
+}
+
+/// Page up/down moves through the log, enter focuses the camera on the observer of the selected event.
+/// The clock is not moved back to the event, the simulation can't be rewound.
+fn browse_eclipse_log(
+    actions: Res<ActionState>,
+    mut log: ResMut<EclipseLog>,
+    mut query_focus: Query<(Entity, &mut FocusableEntity)>,
+) {
+    if log.events.is_empty() {
+        return;
+    }
+
+    let last = log.events.len() - 1;
+
+    if actions.just_pressed(Action::PreviousEclipse) {
+        log.selected = Some(match log.selected {
+            Some(index) => index.saturating_sub(1),
+            None => last,
+        });
+    }
+
+    if actions.just_pressed(Action::NextEclipse) {
+        log.selected = Some(match log.selected {
+            Some(index) => usize::min(index + 1, last),
+            None => last,
+        });
+    }
+
+    if actions.just_pressed(Action::FocusEclipseObserver) {
+        if let Some(index) = log.selected {
+            let observer = log.events[index].observer;
+
+            // Only move the focus if the observer still exists
+            if query_focus.get(observer).is_ok() {
+                for (entity, mut focus) in query_focus.iter_mut() {
+                    focus.is_focused = entity == observer;
+                }
+            }
+        }
+    }
+}
+
+fn setup_eclipse_log_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/LcdRoundedRegular.ttf");
+
+    commands.spawn((
+        TextBundle::from_sections([
+            TextSection::new(
+                "Events (enter focuses the observer, the clock is not rewound):\n",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 20.0,
+                    color: Color::WHITE,
+                },
+            ),
+            TextSection::from_style(TextStyle {
+                font: font.clone(),
+                font_size: 20.0,
+                color: Color::ALICE_BLUE,
+            }),
+        ])
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                bottom: Val::Px(5.0),
+                left: Val::Px(5.0),
+                ..default()
+            },
+            ..default()
+        }),
+        EclipseLogText,
+    ));
+}
+
+fn update_eclipse_log_text(
+    log: Res<EclipseLog>,
+    clock: Res<SimulationClock>,
+    mut texts: Query<&mut Text, With<EclipseLogText>>,
+) {
+    if !log.is_changed() {
+        return;
+    }
+
+    // Show a window of events around the selected one, or the latest ones
+    let shown = 8;
+    let anchor = log.selected.unwrap_or(log.events.len().saturating_sub(1));
+    let first = anchor.saturating_sub(shown / 2).min(log.events.len().saturating_sub(shown));
+
+    let lines: Vec<String> = log.events
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(shown)
+        .map(|(index, event)| {
+            let marker = if Some(index) == log.selected { ">" } else { " " };
+            let kind = match event.kind {
+                EclipseKind::Eclipse => "Eclipse",
+                EclipseKind::Transit => "Transit",
+                EclipseKind::Occultation => "Occultation",
+            };
+            let end = match event.end {
+                Some(end) => clock.format_at(end),
+                None => "...".to_string(),
+            };
+
+            format!("{marker} [{} - {end}] {kind} of {} by {} from {}",
+                clock.format_at(event.start), event.target_name, event.occluder_name, event.observer_name)
+        })
+        .collect();
+
+    for mut text in texts.iter_mut() {
+        text.sections[1].value = lines.join("\n");
+    }
+}
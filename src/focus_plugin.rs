@@ -0,0 +1,331 @@
+use std::cmp::Ordering;
+
+use crate::{input_map::*, planet_components::*, planet_models::*, solar_system_plugin::*, labels::*};
+use bevy::{prelude::*, input::{keyboard::KeyboardInput, ButtonState}};
+
+/// Chooses the focused body: Tab and Shift+Tab go through the bodies in the order of the
+/// hierarchy, the number keys jump to one of the first ten, and F opens a search by name.
+pub struct FocusPlugin;
+
+impl Plugin for FocusPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FocusSearch>()
+            .add_startup_system(setup_focus_search_text)
+            .add_system(cycle_focus.before(SystemTypes::CameraLabel))
+            .add_system(type_focus_search.before(SystemTypes::CameraLabel))
+            .add_system(update_focus_search_text.after(type_focus_search))
+            .add_system_to_stage(CoreStage::PostUpdate, open_or_close_focus_search);
+    }
+}
+
+/// What the text typed into the search box is for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SearchPurpose {
+    /// Focuses the first body matching the name as it is typed
+    #[default]
+    Focus,
+    /// Names the bookmark being saved
+    BookmarkName,
+}
+
+/// Name typed into the focus search box.
+#[derive(Resource, Default, Debug)]
+pub struct FocusSearch {
+    /// What was typed so far, none while the box is closed
+    pub query: Option<String>,
+    pub purpose: SearchPurpose,
+    /// Text kept with Return when the box was not searching a body, until its system takes it
+    pub accepted: Option<String>,
+    // Opening and closing wait for the end of the frame, so the key doing it is not also a shortcut
+    toggle: bool,
+}
+
+impl FocusSearch {
+    /// Other shortcuts are ignored while typing a name.
+    pub fn is_open(&self) -> bool {
+        self.query.is_some()
+    }
+
+    /// Opens the box at the end of the frame to type something else than a body to focus.
+    pub fn open_for(&mut self, purpose: SearchPurpose) {
+        if !self.is_open() && !self.toggle {
+            self.toggle = true;
+            self.purpose = purpose;
+            self.accepted = None;
+        }
+    }
+}
+
+#[derive(Component)]
+struct FocusSearchText;
+
+// Position of a body in the hierarchy, compared like a path: its primary's key, then its own
+#[derive(Debug)]
+struct FocusKey {
+    group: u8,
+    path: Vec<(f32, String)>,
+}
+
+impl FocusKey {
+    fn compare(&self, other: &Self) -> Ordering {
+        self.group.cmp(&other.group).then_with(|| {
+            for ((a_distance, a_name), (b_distance, b_name)) in self.path.iter().zip(other.path.iter()) {
+                let ordering = a_distance.total_cmp(b_distance).then_with(|| a_name.cmp(b_name));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            self.path.len().cmp(&other.path.len())
+        })
+    }
+}
+
+// Planets by their distance from the star at the start, each moon after its planet by its distance from it
+fn planet_path(solar_system: &SolarSystemModel, planet: &PlanetModel, sun: &StarModel) -> Vec<(f32, String)> {
+    let mut path = Vec::new();
+    let mut body = planet;
+
+    // A cycle of parents would otherwise never end, the validation does not look that far
+    for _ in 0..=solar_system.planets.len() {
+        let (position, _) = system_initial_state(body, sun, &solar_system.planets);
+        let parent = body.parent.as_deref()
+            .and_then(|name| solar_system.planets.iter().find(|planet| planet.name == name));
+
+        match parent {
+            Some(parent) => {
+                let (parent_position, _) = system_initial_state(parent, sun, &solar_system.planets);
+                path.push(((position - parent_position).length(), body.name.clone()));
+                body = parent;
+            }
+            None => {
+                path.push((position.length(), body.name.clone()));
+                break;
+            }
+        }
+    }
+
+    path.reverse();
+    path
+}
+
+/// Stars first, then every planet by its distance from the star, each followed by its moons.
+/// Bodies the scenario does not describe come last, by name.
+pub fn focus_order<'a>(
+    solar_system: &SolarSystemModel,
+    bodies: impl Iterator<Item = (Entity, &'a CelestialBody)>,
+) -> Vec<Entity> {
+    let mut keyed: Vec<(FocusKey, Entity)> = bodies
+        .map(|(entity, body)| {
+            let star = solar_system.stars.iter().position(|star| star.name == body.name);
+            let planet = solar_system.planets.iter().find(|planet| planet.name == body.name);
+
+            let key = match (star, planet, solar_system.stars.first()) {
+                (Some(index), _, _) => FocusKey { group: 0, path: vec![(index as f32, body.name.clone())] },
+                (None, Some(planet), Some(sun)) => FocusKey { group: 1, path: planet_path(solar_system, planet, sun) },
+                _ => FocusKey { group: 2, path: vec![(0.0, body.name.clone())] },
+            };
+
+            (key, entity)
+        })
+        .collect();
+
+    keyed.sort_by(|(a, a_entity), (b, b_entity)| a.compare(b).then_with(|| a_entity.cmp(b_entity)));
+    keyed.into_iter().map(|(_, entity)| entity).collect()
+}
+
+fn focus_entity(bodies: &mut Query<(Entity, &CelestialBody, &mut FocusableEntity)>, target: Entity) {
+    for (entity, _, mut focus) in bodies.iter_mut() {
+        let is_focused = entity == target;
+        if focus.is_focused != is_focused {
+            focus.is_focused = is_focused;
+        }
+    }
+}
+
+/// Tab focuses the next body, Shift+Tab the previous one, 1 to 9 and 0 the first ten.
+fn cycle_focus(
+    actions: Res<ActionState>,
+    config: Res<SolarSystemConfiguration>,
+    mut bodies: Query<(Entity, &CelestialBody, &mut FocusableEntity)>,
+) {
+    let direct_focus_actions = [
+        Action::Focus1, Action::Focus2, Action::Focus3, Action::Focus4, Action::Focus5,
+        Action::Focus6, Action::Focus7, Action::Focus8, Action::Focus9, Action::Focus10,
+    ];
+
+    let next = actions.just_pressed(Action::FocusNext);
+    let previous = actions.just_pressed(Action::FocusPrevious);
+    let direct = direct_focus_actions.iter().position(|action| actions.just_pressed(*action));
+
+    if !next && !previous && direct.is_none() {
+        return;
+    }
+
+    let order = focus_order(&config.solar_system, bodies.iter().map(|(entity, body, _)| (entity, body)));
+    // Nothing to focus when the scenario could not be loaded
+    if order.is_empty() {
+        return;
+    }
+
+    let current = bodies.iter()
+        .find(|(_, _, focus)| focus.is_focused)
+        .and_then(|(entity, _, _)| order.iter().position(|x| *x == entity));
+
+    let target = match direct {
+        Some(index) => order.get(index).copied(),
+        None => {
+            let index = match (current, previous) {
+                (Some(index), false) => (index + 1) % order.len(),
+                (Some(index), true) => (index + order.len() - 1) % order.len(),
+                (None, false) => 0,
+                (None, true) => order.len() - 1,
+            };
+            Some(order[index])
+        }
+    };
+
+    if let Some(target) = target {
+        focus_entity(&mut bodies, target);
+    }
+}
+
+/// F opens the search, typing a name focuses the first body it matches, Return keeps it and
+/// Escape goes back to the body focused before. Opened for another purpose, the box only
+/// collects the text until Return.
+fn type_focus_search(
+    actions: Res<ActionState>,
+    mut key_evr: EventReader<KeyboardInput>,
+    mut char_evr: EventReader<ReceivedCharacter>,
+    mut search: ResMut<FocusSearch>,
+    config: Res<SolarSystemConfiguration>,
+    mut bodies: Query<(Entity, &CelestialBody, &mut FocusableEntity)>,
+    mut focused_before: Local<Option<Entity>>,
+) {
+    let accept_button = KeyCode::Return;
+    let cancel_button = KeyCode::Escape;
+    let erase_button = KeyCode::Back;
+
+    let Some(mut query) = search.query.clone() else {
+        // The letter that opens the search is not part of the name
+        char_evr.clear();
+        key_evr.clear();
+
+        if actions.just_pressed(Action::OpenFocusSearch) && !search.toggle {
+            search.toggle = true;
+            search.purpose = SearchPurpose::Focus;
+            *focused_before = bodies.iter().find(|(_, _, focus)| focus.is_focused).map(|(entity, _, _)| entity);
+        }
+        return;
+    };
+
+    for ev in key_evr.iter() {
+        if ev.state != ButtonState::Pressed || search.toggle {
+            continue;
+        }
+
+        match ev.key_code {
+            Some(key) if key == accept_button => {
+                search.toggle = true;
+                if search.purpose != SearchPurpose::Focus {
+                    search.accepted = Some(query.clone());
+                }
+            }
+            Some(key) if key == cancel_button => {
+                search.toggle = true;
+                if let (Some(entity), SearchPurpose::Focus) = (focused_before.take(), search.purpose) {
+                    focus_entity(&mut bodies, entity);
+                }
+            }
+            Some(key) if key == erase_button => {
+                query.pop();
+            }
+            _ => {}
+        }
+    }
+
+    for ev in char_evr.iter() {
+        if !ev.char.is_control() && !search.toggle {
+            query.push(ev.char);
+        }
+    }
+
+    if search.query.as_ref() == Some(&query) {
+        return;
+    }
+    if search.purpose != SearchPurpose::Focus {
+        search.query = Some(query);
+        return;
+    }
+
+    // Names starting with the query come before names only containing it
+    let query_lowercase = query.to_lowercase();
+    let order = focus_order(&config.solar_system, bodies.iter().map(|(entity, body, _)| (entity, body)));
+    let name_of = |entity: &Entity| bodies.get(*entity).map(|(_, body, _)| body.name.to_lowercase()).unwrap_or_default();
+    let found = order.iter().find(|entity| name_of(entity).starts_with(&query_lowercase))
+        .or_else(|| order.iter().find(|entity| name_of(entity).contains(&query_lowercase)))
+        .copied();
+
+    if let (Some(found), false) = (found, query.is_empty()) {
+        focus_entity(&mut bodies, found);
+    }
+
+    search.query = Some(query);
+}
+
+fn open_or_close_focus_search(mut search: ResMut<FocusSearch>) {
+    if search.toggle {
+        search.toggle = false;
+        search.query = match search.query {
+            Some(_) => None,
+            None => Some(String::new()),
+        };
+    }
+}
+
+fn setup_focus_search_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/LcdRoundedRegular.ttf");
+
+    commands.spawn((
+        TextBundle::from_sections([
+            TextSection::new("Focus: ", TextStyle {
+                font: font.clone(),
+                font_size: 24.0,
+                color: Color::GOLD,
+            }),
+            TextSection::from_style(TextStyle {
+                font: font.clone(),
+                font_size: 24.0,
+                color: Color::WHITE,
+            }),
+        ])
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                top: Val::Px(5.0),
+                left: Val::Percent(45.0),
+                ..default()
+            },
+            ..default()
+        }),
+        Visibility { is_visible: false },
+        FocusSearchText,
+    ));
+}
+
+fn update_focus_search_text(
+    search: Res<FocusSearch>,
+    mut texts: Query<(&mut Text, &mut Visibility), With<FocusSearchText>>,
+) {
+    if !search.is_changed() {
+        return;
+    }
+
+    for (mut text, mut visibility) in texts.iter_mut() {
+        visibility.is_visible = search.is_open();
+        text.sections[0].value = match search.purpose {
+            SearchPurpose::Focus => "Focus: ",
+            SearchPurpose::BookmarkName => "Bookmark: ",
+        }.to_string();
+        text.sections[1].value = format!("{}_", search.query.as_deref().unwrap_or_default());
+    }
+}
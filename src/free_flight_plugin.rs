@@ -0,0 +1,161 @@
+use crate::{camera_plugin::*, input_map::*, map_view_plugin::*, planet_components::*, labels::*};
+use bevy::{prelude::*, input::mouse::MouseMotion};
+
+/// V switches the camera between orbiting the focused body and flying freely: WASD moves,
+/// Q and E go down and up, Z and C roll, right drag looks around and the wheel sets the speed.
+pub struct FreeFlightPlugin;
+
+impl Plugin for FreeFlightPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(toggle_free_flight.before(SystemTypes::CameraLabel))
+            .add_system(fly_camera
+                .label(SystemTypes::CameraLabel)
+                .after(toggle_free_flight));
+    }
+}
+
+/// Marks a camera that flies freely instead of orbiting its focus.
+#[derive(Component, Debug)]
+pub struct FreeFlight {
+    /// Speed in distances to the nearest surface per second
+    pub speed: f32,
+}
+
+impl Default for FreeFlight {
+    fn default() -> Self {
+        FreeFlight { speed: 0.5 }
+    }
+}
+
+/// Roll in radians per second.
+const ROLL_SPEED: f32 = 1.5;
+
+// Body whose surface is the closest to `position`, with the distance to that surface
+fn nearest_body<'a>(
+    position: Vec3,
+    bodies: impl Iterator<Item = (Entity, &'a GlobalTransform, &'a CelestialBody)>,
+) -> Option<(Entity, Vec3, f32)> {
+    bodies
+        .map(|(entity, transform, body)| {
+            let center = transform.translation();
+            (entity, center, position.distance(center) - body.radius)
+        })
+        .min_by(|a, b| a.2.total_cmp(&b.2))
+}
+
+/// Going back to orbiting picks the nearest body and keeps the camera where it is.
+fn toggle_free_flight(
+    mut commands: Commands,
+    actions: Res<ActionState>,
+    map: Res<MapView>,
+    mut cameras: Query<(Entity, &mut PanOrbitCamera, &mut Transform, Option<&FreeFlight>)>,
+    mut bodies: Query<(Entity, &GlobalTransform, &CelestialBody, &mut FocusableEntity)>,
+) {
+    if map.active || !actions.just_pressed(Action::FreeFlight) {
+        return;
+    }
+
+    for (camera, mut pan_orbit, mut transform, free_flight) in cameras.iter_mut() {
+        if free_flight.is_none() {
+            commands.entity(camera)
+                .insert(FreeFlight::default())
+                .remove::<CameraTransition>();
+            continue;
+        }
+
+        let nearest = nearest_body(transform.translation, bodies.iter().map(|(entity, transform, body, _)| (entity, transform, body)));
+        let Some((nearest, center, _)) = nearest else {
+            // Nothing to orbit, keep flying
+            continue;
+        };
+
+        // Look at the body without rolling, from where the camera already is
+        pan_orbit.focus = center;
+        pan_orbit.radius = transform.translation.distance(center);
+        pan_orbit.upside_down = false;
+        pan_orbit.reframe();
+        *transform = transform.looking_at(center, Vec3::Y);
+
+        for (entity, _, _, mut focus) in bodies.iter_mut() {
+            let is_focused = entity == nearest;
+            if focus.is_focused != is_focused {
+                focus.is_focused = is_focused;
+            }
+        }
+
+        commands.entity(camera).remove::<FreeFlight>();
+    }
+}
+
+fn fly_camera(
+    time: Res<Time>,
+    windows: Res<Windows>,
+    actions: Res<ActionState>,
+    map: Res<MapView>,
+    mut ev_motion: EventReader<MouseMotion>,
+    mut cameras: Query<(&mut Transform, &mut FreeFlight)>,
+    bodies: Query<(Entity, &GlobalTransform, &CelestialBody)>,
+) {
+    let movement_actions = [
+        (Action::FlyForward, Vec3::NEG_Z),
+        (Action::FlyBackward, Vec3::Z),
+        (Action::FlyLeft, Vec3::NEG_X),
+        (Action::FlyRight, Vec3::X),
+        (Action::FlyDown, Vec3::NEG_Y),
+        (Action::FlyUp, Vec3::Y),
+    ];
+
+    let mut look = Vec2::ZERO;
+    if actions.pressed(Action::Rotate) {
+        for ev in ev_motion.iter() {
+            look += ev.delta;
+        }
+    }
+    ev_motion.clear();
+    look += Vec2::new(actions.value(Action::RotateHorizontal), actions.value(Action::RotateVertical)) * PIXELS_PER_NOTCH;
+
+    let scroll = actions.value(Action::Zoom);
+
+    if map.active {
+        return;
+    }
+
+    let direction: Vec3 = movement_actions.iter()
+        .filter(|(action, _)| actions.pressed(*action))
+        .map(|(_, direction)| *direction)
+        .sum();
+    let roll = match (actions.pressed(Action::RollLeft), actions.pressed(Action::RollRight)) {
+        (true, false) => 1.0,
+        (false, true) => -1.0,
+        _ => 0.0,
+    };
+
+    let Some(window) = windows.get_primary() else {
+        return;
+    };
+    let dt = time.delta_seconds();
+
+    for (mut transform, mut free_flight) in cameras.iter_mut() {
+        if scroll != 0.0 {
+            free_flight.speed = (free_flight.speed * 1.25_f32.powf(scroll)).clamp(0.01, 100.0);
+        }
+
+        // Same feel as orbiting, half a turn for a drag across the window
+        if look.length_squared() > 0.0 {
+            let yaw = Quat::from_rotation_y(-look.x / window.width() * std::f32::consts::PI);
+            let pitch = Quat::from_rotation_x(-look.y / window.height() * std::f32::consts::PI);
+            transform.rotation = transform.rotation * yaw * pitch;
+        }
+        if roll != 0.0 {
+            transform.rotation = transform.rotation * Quat::from_rotation_z(roll * ROLL_SPEED * dt);
+        }
+
+        if direction.length_squared() > 0.0 {
+            // Fast between the planets and slow near a surface, but never stuck on it
+            let distance = nearest_body(transform.translation, bodies.iter())
+                .map_or(1.0, |(_, _, distance)| distance.max(0.01));
+
+            transform.translation += transform.rotation * direction.normalize() * free_flight.speed * distance * dt;
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::{borrow::Cow, fs::File, io::{BufWriter, Write}};
+
+use crate::{planet_components::*, planet_models::*, simulation_clock::*, solar_system_plugin::*, startup_options::*, labels::*};
+use bevy::{app::AppExit, prelude::*};
+
+/// Spawns the bodies without any mesh and writes their states to a file while the
+/// physics runs, then quits once the requested duration has been simulated.
+pub struct HeadlessPlugin {
+    pub options: HeadlessOptions,
+}
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(HeadlessRun {
+            options: self.options.clone(),
+            steps: 0,
+        })
+        .add_startup_system(spawn_bodies)
+        .add_startup_system(open_ephemeris_file)
+        .add_startup_system_to_stage(StartupStage::PostStartup, write_initial_states)
+        .add_system(write_ephemerides
+            .after(SystemTypes::PhysicsLabel));
+    }
+}
+
+#[derive(Resource)]
+struct HeadlessRun {
+    options: HeadlessOptions,
+    steps: u64,
+}
+
+#[derive(Resource)]
+struct EphemerisFile(BufWriter<File>);
+
+fn spawn_bodies(
+    mut commands: Commands,
+    mut config: ResMut<SolarSystemConfiguration>,
+    run: Res<HeadlessRun>,
+) {
+    if let Some(step) = run.options.step {
+        config.physical_constants.dv = step;
+    }
+
+    for sun in config.solar_system.stars.iter() {
+        commands.spawn((Transform::IDENTITY, star_physics(sun), Star));
+    }
+
+    if let Some(sun) = config.solar_system.stars.iter().next() {
+        for planet in config.solar_system.planets.iter() {
+            commands.spawn((planet_physics(planet, sun, &config.solar_system.planets), Planet));
+        }
+    }
+}
+
+fn open_ephemeris_file(mut commands: Commands, run: Res<HeadlessRun>) {
+    let file = File::create(&run.options.output_path)
+        .unwrap_or_else(|err| panic!("Could not create {}: {err}", run.options.output_path));
+    let mut writer = BufWriter::new(file);
+
+    if run.options.format == EphemerisFormat::Csv {
+        writeln!(writer, "time,body,x,y,z,vx,vy,vz").expect("Could not write the ephemerides");
+    }
+
+    commands.insert_resource(EphemerisFile(writer));
+}
+
+/// Quotes a CSV field when it contains a separator, a quote or a line break.
+fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+fn write_states(
+    file: &mut EphemerisFile,
+    format: EphemerisFormat,
+    time: f64,
+    bodies: &Query<(&Transform, &CelestialBody)>,
+) -> std::io::Result<()> {
+    for (pos, body) in bodies.iter() {
+        let p = pos.translation;
+        let v = body.vel.vector;
+
+        match format {
+            EphemerisFormat::Csv => writeln!(
+                file.0,
+                "{time},{},{},{},{},{},{},{}",
+                csv_field(&body.name), p.x, p.y, p.z, v.x, v.y, v.z
+            )?,
+            EphemerisFormat::JsonLines => writeln!(
+                file.0,
+                "{}",
+                serde_json::json!({
+                    "time": time,
+                    "body": body.name,
+                    "position": [p.x, p.y, p.z],
+                    "velocity": [v.x, v.y, v.z],
+                })
+            )?,
+        }
+    }
+
+    Ok(())
+}
+
+fn write_initial_states(
+    mut file: ResMut<EphemerisFile>,
+    run: Res<HeadlessRun>,
+    bodies: Query<(&Transform, &CelestialBody)>,
+) {
+    write_states(&mut file, run.options.format, 0.0, &bodies).expect("Could not write the ephemerides");
+}
+
+fn write_ephemerides(
+    mut file: ResMut<EphemerisFile>,
+    mut run: ResMut<HeadlessRun>,
+    clock: Res<SimulationClock>,
+    bodies: Query<(&Transform, &CelestialBody)>,
+    mut exit: EventWriter<AppExit>,
+) {
+    run.steps += 1;
+    let finished = clock.elapsed >= run.options.duration;
+
+    if finished || run.steps % run.options.output_every as u64 == 0 {
+        write_states(&mut file, run.options.format, clock.elapsed, &bodies).expect("Could not write the ephemerides");
+    }
+
+    if finished {
+        file.0.flush().expect("Could not write the ephemerides");
+        println!("Simulated {:.3} time units in {} steps until {}, wrote {}", clock.elapsed, run.steps, clock.format_now(), run.options.output_path);
+        exit.send(AppExit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_field_quotes_only_when_needed() {
+        assert_eq!(csv_field("Kerbin"), "Kerbin");
+        assert_eq!(csv_field("Jool, b"), "\"Jool, b\"");
+        assert_eq!(csv_field("Dres \"9\""), "\"Dres \"\"9\"\"\"");
+    }
+}
@@ -0,0 +1,531 @@
+// Building a scenario from vector tables saved from JPL Horizons
+// (EPHEM_TYPE=VECTORS, VEC_TABLE=2 or 3), in either the plain or the CSV layout.
+// The tables are read from disk, see examples/horizons for a properties file listing them
+// and how to fetch the tables it needs.
+
+use std::{collections::HashMap, path::Path};
+
+use bevy::math::DVec3;
+use serde_derive::Deserialize;
+
+use crate::{calendar::*, oem::*, physics::*, physical_constant_models::*, planet_models::*};
+
+const KM_PER_AU: f64 = 149_597_870.7;
+const SECONDS_PER_DAY: f64 = 86400.0;
+const J2000_JULIAN_DATE: f64 = 2_451_545.0;
+
+/// One row of a vector table, converted to km and km/s.
+#[derive(Clone, Copy, Debug)]
+pub struct HorizonsState {
+    /// Julian date, in the time scale of the table (normally TDB)
+    pub julian_date: f64,
+    pub position: DVec3,
+    pub velocity: DVec3,
+}
+
+/// Content of a vector table, with the states expressed in the ecliptic of J2000.
+#[derive(Clone, Debug)]
+pub struct HorizonsTable {
+    pub target_name: String,
+    pub center_name: String,
+    pub states: Vec<HorizonsState>,
+}
+
+#[derive(Debug)]
+pub enum HorizonsError {
+    Io { path: String, source: std::io::Error },
+    Properties(serde_json::Error),
+    Syntax { line: usize, message: String },
+    NoStates(String),
+}
+
+impl std::fmt::Display for HorizonsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HorizonsError::Io { path, source } => write!(f, "could not read {path}: {source}"),
+            HorizonsError::Properties(err) => write!(f, "invalid properties table: {err}"),
+            HorizonsError::Syntax { line, message } => write!(f, "line {line}: {message}"),
+            HorizonsError::NoStates(target) => write!(f, "no states between $$SOE and $$EOE for {target}"),
+        }
+    }
+}
+
+impl std::error::Error for HorizonsError {}
+
+/// Physical properties of the bodies that Horizons vector tables do not contain, and the
+/// tables to read their states from. Distances are in km, gravitational parameters in
+/// km³/s², rotation periods in seconds and angles in degrees.
+#[derive(Deserialize, Debug)]
+pub struct HorizonsProperties {
+    /// Start the system from the states nearest to this epoch (ISO 8601, TDB),
+    /// from the first state of each table otherwise
+    #[serde(default)]
+    pub epoch: Option<String>,
+    pub star: HorizonsBodyProperties,
+    pub bodies: Vec<HorizonsBodyProperties>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct HorizonsBodyProperties {
+    /// Vector table of the body, relative to the properties file. Only optional for the star,
+    /// which is needed when the other tables are not centred on it.
+    #[serde(default)]
+    pub file: Option<String>,
+    /// Name of the body, defaults to the target name of its table
+    #[serde(default)]
+    pub name: Option<String>,
+    pub radius: f64,
+    pub gravitational_parameter: f64,
+    pub sidereal_rotation_period: f64,
+    pub color: PlanetColor,
+    #[serde(default)]
+    pub color_texture: String,
+    #[serde(default)]
+    pub normal_texture: String,
+}
+
+// "Earth (399)   {source: DE441}" -> "Earth"
+fn body_name(value: &str) -> String {
+    let value = value.split('{').next().unwrap_or(value);
+    let value = match value.rfind(" (") {
+        Some(index) => &value[..index],
+        None => value,
+    };
+    value.trim().to_string()
+}
+
+fn parse_number(value: &str, line: usize) -> Result<f64, HorizonsError> {
+    value.trim().parse().map_err(|_| HorizonsError::Syntax {
+        line,
+        message: format!("{value} is not a number"),
+    })
+}
+
+// Splits "X =-2.6E+07 Y = 1.3E+08" into ("X", "-2.6E+07"), ("Y", "1.3E+08")
+fn labelled_values(line: &str) -> Vec<(String, String)> {
+    let spaced = line.replace('=', " = ");
+    let tokens: Vec<&str> = spaced.split_whitespace().collect();
+
+    tokens.windows(3)
+        .filter(|window| window[1] == "=")
+        .map(|window| (window[0].to_string(), window[2].to_string()))
+        .collect()
+}
+
+fn plane_frame(description: &str) -> Option<OemFrame> {
+    let description = description.to_lowercase();
+    if description.contains("ecliptic") {
+        Some(OemFrame::EclipticJ2000)
+    } else if description.contains("equator") || description.contains("(icrf)") || description == "frame" {
+        Some(OemFrame::Icrf)
+    } else {
+        None
+    }
+}
+
+/// Parses the text output of a Horizons vector query.
+pub fn parse_vectors(text: &str) -> Result<HorizonsTable, HorizonsError> {
+    let mut target_name = String::new();
+    let mut center_name = String::new();
+    // Horizons defaults to the ecliptic plane and km/s
+    let mut frame = OemFrame::EclipticJ2000;
+    let mut distance_scale = 1.0;
+    let mut time_scale = 1.0;
+
+    let mut in_data = false;
+    let mut in_description = false;
+    let mut csv_columns: Vec<String> = Vec::new();
+    let mut previous_line = "";
+    let mut states = Vec::new();
+    // Values of the plain layout are spread over several lines
+    let mut pending: Option<(f64, HashMap<String, f64>)> = None;
+
+    for (index, raw_line) in text.lines().enumerate() {
+        let line = index + 1;
+        let content = raw_line.trim();
+
+        if content == "$$SOE" {
+            in_data = true;
+            if previous_line.contains(',') {
+                csv_columns = previous_line.split(',').map(|column| column.trim().to_uppercase()).collect();
+            }
+            continue;
+        }
+
+        if content == "$$EOE" {
+            in_data = false;
+            continue;
+        }
+
+        if !in_data {
+            if let Some((key, value)) = content.split_once(':') {
+                let value = value.trim();
+                match key.trim() {
+                    "Target body name" => target_name = body_name(value),
+                    "Center body name" => center_name = body_name(value),
+                    "Output units" => {
+                        let units = value.to_uppercase();
+                        if units.starts_with("AU") {
+                            distance_scale = KM_PER_AU;
+                        }
+                        if units.ends_with("-D") {
+                            time_scale = 1.0 / SECONDS_PER_DAY;
+                        }
+                    }
+                    "Reference frame" | "Reference plane" | "Coordinate systm" => {
+                        frame = plane_frame(value).unwrap_or(frame);
+                    }
+                    "Coordinate system description" => in_description = true,
+                    _ => {}
+                }
+            } else if in_description && !content.is_empty() {
+                // Newer tables describe the plane in the footer instead
+                frame = plane_frame(content).unwrap_or(frame);
+                in_description = false;
+            }
+
+            // The CSV column names are followed by a line of stars
+            if !content.is_empty() && !content.starts_with('*') {
+                previous_line = content;
+            }
+            continue;
+        }
+
+        let mut values = HashMap::new();
+        let julian_date;
+
+        if !csv_columns.is_empty() {
+            let fields: Vec<&str> = content.split(',').collect();
+            julian_date = parse_number(fields.first().copied().unwrap_or_default(), line)?;
+
+            for (column, field) in csv_columns.iter().zip(fields.iter()) {
+                if let Ok(value) = field.trim().parse::<f64>() {
+                    values.insert(column.clone(), value);
+                }
+            }
+        } else if let Some(epoch) = content.split_once('=').and_then(|(first, _)| first.trim().parse::<f64>().ok()) {
+            // A new record starts with its Julian date
+            if let Some((julian_date, values)) = pending.take() {
+                states.push(table_state(julian_date, &values, line)?);
+            }
+            pending = Some((epoch, HashMap::new()));
+            continue;
+        } else {
+            let Some((_, values)) = pending.as_mut() else {
+                return Err(HorizonsError::Syntax { line, message: "values found before the first epoch".to_string() });
+            };
+
+            for (label, value) in labelled_values(content) {
+                values.insert(label.to_uppercase(), parse_number(&value, line)?);
+            }
+            continue;
+        }
+
+        states.push(table_state(julian_date, &values, line)?);
+    }
+
+    if let Some((julian_date, values)) = pending.take() {
+        states.push(table_state(julian_date, &values, text.lines().count())?);
+    }
+
+    if states.is_empty() {
+        return Err(HorizonsError::NoStates(target_name));
+    }
+
+    for state in states.iter_mut() {
+        let position = state.position * distance_scale;
+        let velocity = state.velocity * distance_scale * time_scale;
+        state.position = DVec3::from_array(frame.rotate_to_ecliptic(position.to_array()));
+        state.velocity = DVec3::from_array(frame.rotate_to_ecliptic(velocity.to_array()));
+    }
+
+    Ok(HorizonsTable {
+        target_name,
+        center_name,
+        states,
+    })
+}
+
+fn table_state(julian_date: f64, values: &HashMap<String, f64>, line: usize) -> Result<HorizonsState, HorizonsError> {
+    let get = |label: &str| values.get(label).copied().ok_or_else(|| HorizonsError::Syntax {
+        line,
+        message: format!("missing {label} in the record at JD {julian_date}"),
+    });
+
+    Ok(HorizonsState {
+        julian_date,
+        position: DVec3::new(get("X")?, get("Y")?, get("Z")?),
+        velocity: DVec3::new(get("VX")?, get("VY")?, get("VZ")?),
+    })
+}
+
+fn read_table(path: &Path) -> Result<HorizonsTable, HorizonsError> {
+    let text = std::fs::read_to_string(path).map_err(|source| HorizonsError::Io {
+        path: path.display().to_string(),
+        source,
+    })?;
+    parse_vectors(&text)
+}
+
+fn nearest_state(table: &HorizonsTable, julian_date: Option<f64>) -> HorizonsState {
+    match julian_date {
+        Some(julian_date) => *table.states.iter()
+            .min_by(|a, b| (a.julian_date - julian_date).abs().total_cmp(&(b.julian_date - julian_date).abs()))
+            .unwrap(),
+        None => table.states[0],
+    }
+}
+
+/// Reads the properties file and the vector tables it lists, and builds a system in the
+/// simulator's units, with every body placed relative to the star.
+pub fn load_horizons_system(
+    properties_path: &str,
+    constants: &PhysicalConstantsModel,
+) -> Result<SolarSystemModel, HorizonsError> {
+    let properties_text = std::fs::read_to_string(properties_path).map_err(|source| HorizonsError::Io {
+        path: properties_path.to_string(),
+        source,
+    })?;
+    let properties: HorizonsProperties = serde_json::from_str(&properties_text).map_err(HorizonsError::Properties)?;
+    let directory = Path::new(properties_path).parent().unwrap_or(Path::new(""));
+
+    let julian_date = properties.epoch.as_deref()
+        .and_then(parse_iso8601)
+        .map(|seconds| J2000_JULIAN_DATE + seconds / SECONDS_PER_DAY);
+
+    // State of every body relative to the center of its table, by name
+    let mut relative_states: HashMap<String, (String, HorizonsState)> = HashMap::new();
+    let mut names = Vec::new();
+    // Date of the states the system starts from
+    let mut epoch = None;
+
+    for body in std::iter::once(&properties.star).chain(properties.bodies.iter()) {
+        let name = match &body.file {
+            Some(file) => {
+                let table = read_table(&directory.join(file))?;
+                let name = body.name.clone().unwrap_or_else(|| table.target_name.clone());
+                let state = nearest_state(&table, julian_date);
+                epoch.get_or_insert(state.julian_date);
+                relative_states.insert(name.clone(), (table.center_name.clone(), state));
+                name
+            }
+            None if names.is_empty() => body.name.clone().unwrap_or_default(),
+            None => return Err(HorizonsError::NoStates(body.name.clone().unwrap_or_default())),
+        };
+        names.push(name);
+    }
+
+    // Follow the chain of centers, e.g. Moon -> Earth -> Sun, to a common origin
+    let absolute_state = |name: &str| {
+        let mut position = DVec3::ZERO;
+        let mut velocity = DVec3::ZERO;
+        let mut current = name.to_string();
+
+        for _ in 0..=relative_states.len() {
+            let Some((center, state)) = relative_states.get(&current) else {
+                break;
+            };
+            position += state.position;
+            velocity += state.velocity;
+            current = center.clone();
+        }
+
+        (position, velocity)
+    };
+
+    let (star_position, star_velocity) = absolute_state(&names[0]);
+    let seconds_per_time_unit = constants.seconds_per_time_unit;
+    // km³/s² to Mm³ per time unit²
+    let to_gravitational_parameter = |mu: f64| (mu * 1e-9 * seconds_per_time_unit * seconds_per_time_unit) as f32;
+
+    let star_name = names[0].clone();
+    let star_mu = to_gravitational_parameter(properties.star.gravitational_parameter);
+    let star = StarModel {
+        name: star_name,
+        radius: (properties.star.radius / 1000.0) as f32,
+        mass: star_mu / constants.gravitational_constant,
+        gravitational_parameter: star_mu,
+        sidereal_rotation_period: properties.star.sidereal_rotation_period as f32,
+        color: properties.star.color,
+    };
+
+    let planets = properties.bodies.iter().zip(names.iter().skip(1))
+        .map(|(body, name)| {
+            let (position, velocity) = absolute_state(name);
+            // km to Mm, and km/s to Mm per time unit
+            let position = ((position - star_position) / 1000.0).as_vec3();
+            let velocity = ((velocity - star_velocity) / 1000.0 * seconds_per_time_unit).as_vec3();
+
+            let normal = position.cross(velocity).normalize_or_zero();
+            let inclination = normal.z.clamp(-1.0, 1.0).acos().to_degrees();
+            let mu = to_gravitational_parameter(body.gravitational_parameter);
+
+            // Tables centred on another listed body make it a moon of that body
+            let parent = relative_states.get(name)
+                .map(|(center, _)| center.clone())
+                .filter(|center| *center != star.name && names.contains(center));
+
+            PlanetModel {
+                name: name.clone(),
+                parent,
+                color_texture: body.color_texture.clone(),
+                normal_texture: body.normal_texture.clone(),
+                radius: (body.radius / 1000.0) as f32,
+                mass: mu / constants.gravitational_constant,
+                gravitational_parameter: mu,
+                sidereal_rotation_period: body.sidereal_rotation_period as f32,
+                inclination,
+                periapsis: position.length() - star.radius,
+                orbital_velocity_pe: velocity.length(),
+                color: body.color,
+                initial_position: Some(ecliptic_to_world(position).to_array()),
+                initial_velocity: Some(ecliptic_to_world(velocity).to_array()),
+            }
+        })
+        .collect();
+
+    Ok(SolarSystemModel {
+        stars: vec![star],
+        planets,
+        populations: Vec::new(),
+        epoch: epoch.map(|julian_date| EpochModel {
+            calendar: Calendar::Earth,
+            date: Some(format!("JD {julian_date}")),
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec3;
+
+    use super::*;
+
+    const EARTH_PLAIN: &str = "\
+*******************************************************************************
+Target body name: Earth (399)                     {source: DE441}
+Center body name: Sun (10)                        {source: DE441}
+*******************************************************************************
+Reference frame : Ecliptic of J2000.0
+Output units    : KM-S
+*******************************************************************************
+$$SOE
+2451545.000000000 = A.D. 2000-Jan-01 12:00:00.0000 TDB 
+ X = 1.000000000000000E+05 Y = 2.000000000000000E+05 Z = 3.000000000000000E+04
+ VX= 1.000000000000000E+00 VY= 2.000000000000000E+00 VZ= 5.000000000000000E-01
+ LT= 7.491398264659458E-01 RG= 2.245997195153257E+05 RR= 2.293986087385917E+00
+2451546.000000000 = A.D. 2000-Jan-02 12:00:00.0000 TDB 
+ X = 1.100000000000000E+05 Y = 2.100000000000000E+05 Z = 3.100000000000000E+04
+ VX= 1.100000000000000E+00 VY= 2.100000000000000E+00 VZ= 6.000000000000000E-01
+ LT= 7.967427563094208E-01 RG= 2.388671600706040E+05 RR= 2.384018367437101E+00
+$$EOE
+*******************************************************************************
+";
+
+    const MOON_CSV: &str = "\
+*******************************************************************************
+Target body name: Moon (301)                      {source: DE441}
+Center body name: Earth (399)                     {source: DE441}
+*******************************************************************************
+Reference frame : Ecliptic of J2000.0
+Output units    : AU-D
+*******************************************************************************
+            JDTDB,            Calendar Date (TDB),                      X,                      Y,                      Z,                     VX,                     VY,                     VZ,
+**************************************************************************************************************************************************************************************************
+$$SOE
+2451545.000000000, A.D. 2000-Jan-01 12:00:00.0000,  2.000000000000000E-03, -1.000000000000000E-03,  0.000000000000000E+00,  1.000000000000000E-04,  2.000000000000000E-04,  0.000000000000000E+00,
+$$EOE
+*******************************************************************************
+";
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() <= tolerance, "{actual} is not within {tolerance} of {expected}");
+    }
+
+    fn assert_vec_close(actual: Vec3, expected: Vec3, tolerance: f32) {
+        assert!((actual - expected).length() <= tolerance, "{actual} is not within {tolerance} of {expected}");
+    }
+
+    #[test]
+    fn parses_a_plain_table_in_km() {
+        let table = parse_vectors(EARTH_PLAIN).unwrap();
+
+        assert_eq!(table.target_name, "Earth");
+        assert_eq!(table.center_name, "Sun");
+        assert_eq!(table.states.len(), 2);
+        assert_close(table.states[0].julian_date, 2_451_545.0, 1e-9);
+        assert_eq!(table.states[0].position, DVec3::new(1.0e5, 2.0e5, 3.0e4));
+        assert_eq!(table.states[0].velocity, DVec3::new(1.0, 2.0, 0.5));
+        assert_close(table.states[1].julian_date, 2_451_546.0, 1e-9);
+        assert_eq!(table.states[1].position, DVec3::new(1.1e5, 2.1e5, 3.1e4));
+    }
+
+    #[test]
+    fn parses_a_csv_table_in_au_and_days() {
+        let table = parse_vectors(MOON_CSV).unwrap();
+
+        assert_eq!(table.target_name, "Moon");
+        assert_eq!(table.center_name, "Earth");
+        assert_eq!(table.states.len(), 1);
+        assert_close(table.states[0].julian_date, 2_451_545.0, 1e-9);
+
+        let state = table.states[0];
+        assert_close(state.position.x, 2.0e-3 * KM_PER_AU, 1e-6);
+        assert_close(state.position.y, -1.0e-3 * KM_PER_AU, 1e-6);
+        assert_close(state.velocity.x, 1.0e-4 * KM_PER_AU / SECONDS_PER_DAY, 1e-9);
+        assert_close(state.velocity.y, 2.0e-4 * KM_PER_AU / SECONDS_PER_DAY, 1e-9);
+    }
+
+    #[test]
+    fn rejects_a_table_without_states() {
+        let text = EARTH_PLAIN.replace("$$SOE", "").replace("$$EOE", "");
+        let text = text.lines().filter(|line| !line.contains('=')).collect::<Vec<_>>().join("\n");
+
+        assert!(matches!(parse_vectors(&text), Err(HorizonsError::NoStates(target)) if target == "Earth"));
+    }
+
+    #[test]
+    fn builds_the_system_in_simulation_units() {
+        let directory = std::env::temp_dir().join(format!("horizons_test_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("earth.txt"), EARTH_PLAIN).unwrap();
+        std::fs::write(directory.join("moon.txt"), MOON_CSV).unwrap();
+        std::fs::write(directory.join("system.json"), r#"{
+            "star": { "name": "Sun", "radius": 695700, "gravitational_parameter": 1.32712440041e11,
+                      "sidereal_rotation_period": 2192832, "color": { "red": 255, "green": 240, "blue": 200 } },
+            "bodies": [
+                { "file": "earth.txt", "radius": 6371, "gravitational_parameter": 398600.435,
+                  "sidereal_rotation_period": 86164, "color": { "red": 40, "green": 90, "blue": 200 } },
+                { "file": "moon.txt", "radius": 1737, "gravitational_parameter": 4902.8,
+                  "sidereal_rotation_period": 2360592, "color": { "red": 150, "green": 150, "blue": 150 } }
+            ]
+        }"#).unwrap();
+
+        let constants = PhysicalConstantsModel::default();
+        let system = load_horizons_system(directory.join("system.json").to_str().unwrap(), &constants);
+        std::fs::remove_dir_all(&directory).unwrap();
+        let system = system.unwrap();
+
+        let time_unit = constants.seconds_per_time_unit as f32;
+        assert_eq!(system.stars[0].name, "Sun");
+        assert_eq!(system.stars[0].radius, 695.7);
+
+        // km to Mm, km/s to Mm per time unit, and the ecliptic (x, y, z) becomes (x, z, -y)
+        let earth = &system.planets[0];
+        assert_eq!(earth.name, "Earth");
+        assert_eq!(earth.parent, None);
+        assert_vec_close(Vec3::from_array(earth.initial_position.unwrap()), Vec3::new(100.0, 30.0, -200.0), 1e-4);
+        assert_vec_close(Vec3::from_array(earth.initial_velocity.unwrap()), Vec3::new(1.0, 0.5, -2.0) * 1e-3 * time_unit, 1e-5);
+
+        // The Moon table is centred on the Earth, the Moon is placed relative to the Sun
+        let moon = &system.planets[1];
+        let offset = Vec3::new(2.0e-3, 0.0, 1.0e-3) * (KM_PER_AU / 1000.0) as f32;
+        let speed = Vec3::new(1.0e-4, 0.0, -2.0e-4) * (KM_PER_AU / SECONDS_PER_DAY / 1000.0) as f32 * time_unit;
+        assert_eq!(moon.name, "Moon");
+        assert_eq!(moon.parent.as_deref(), Some("Earth"));
+        assert_vec_close(Vec3::from_array(moon.initial_position.unwrap()), Vec3::new(100.0, 30.0, -200.0) + offset, 1e-2);
+        assert_vec_close(Vec3::from_array(moon.initial_velocity.unwrap()), Vec3::new(1.0, 0.5, -2.0) * 1e-3 * time_unit + speed, 1e-5);
+
+        assert_eq!(system.epoch.unwrap().date.as_deref(), Some("JD 2451545"));
+    }
+}
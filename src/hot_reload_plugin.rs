@@ -0,0 +1,249 @@
+use std::path::Path;
+
+use crate::{input_map::*, labels::*, physical_constant_models::*, planet_models::*, scenario_formats::*, scenario_validation::*, solar_system_plugin::*, startup_options::*, time_warp_plugin::*};
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+};
+use serde_json::Value;
+
+/// Watches the scenario and constants files and applies their changes while the simulation runs.
+/// Only files below the `assets` directory can be watched.
+pub struct HotReloadPlugin;
+
+impl Plugin for HotReloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<ConfigurationDocument>()
+            .init_asset_loader::<ConfigurationDocumentLoader>()
+            .init_resource::<HotReloadSettings>()
+            .add_startup_system(watch_configuration_files)
+            .add_system(apply_configuration_changes
+                // A new dv is picked up by the time warp in the same frame
+                .before(apply_time_warp)
+                .before(SystemTypes::SnapshotLabel))
+            .add_system(toggle_reset_on_reload);
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct HotReloadSettings {
+    /// Start the whole simulation over when the scenario changes, instead of only
+    /// respawning the changed bodies where they are
+    pub reset_on_reload: bool,
+}
+
+/// A scenario or constants file in any of the scenario formats, read as a JSON value,
+/// or the reason it could not be parsed.
+#[derive(TypeUuid, Debug)]
+#[uuid = "56a81c99-07c5-4808-b4e9-a23a2cd3484d"]
+pub struct ConfigurationDocument(pub Result<Value, String>);
+
+#[derive(Default)]
+pub struct ConfigurationDocumentLoader;
+
+impl AssetLoader for ConfigurationDocumentLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            // Syntax errors are reported with the other problems of the file, not by the asset server
+            let format = ScenarioFormat::from_path(load_context.path());
+            let document = std::str::from_utf8(bytes)
+                .map_err(|err| format!("invalid {format}: {err}"))
+                .and_then(|text| format.parse(text));
+            load_context.set_default_asset(LoadedAsset::new(ConfigurationDocument(document)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &ScenarioFormat::EXTENSIONS
+    }
+}
+
+/// Handles of the watched files, with the paths they were given with on the command line.
+#[derive(Resource)]
+struct WatchedConfiguration {
+    scenario: Option<(String, Handle<ConfigurationDocument>)>,
+    constants: Option<(String, Handle<ConfigurationDocument>)>,
+    /// Options of the command line that take precedence over the constants file
+    dv: Option<f32>,
+    integrator: Option<Integrator>,
+}
+
+// Path of a file relative to the asset directory, if it is inside of it
+fn asset_path(path: &str) -> Option<String> {
+    let root = asset_root().canonicalize().ok()?;
+    let path = Path::new(path).canonicalize().ok()?;
+    path.strip_prefix(root).ok().map(|relative| relative.to_string_lossy().replace('\\', "/"))
+}
+
+fn watch_configuration_files(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    options: Option<Res<StartupOptions>>,
+) {
+    let options = options.map(|options| options.clone()).unwrap_or_default();
+
+    let mut watch = |path: &str| match asset_path(path) {
+        Some(relative) => Some((path.to_string(), asset_server.load(relative))),
+        None => {
+            info!("{path} is outside of the assets directory, changes to it are not applied");
+            None
+        }
+    };
+
+    // Imported systems have no scenario file to follow
+    let scenario_is_file = options.horizons_path.is_none() && options.kopernicus_path.is_none();
+
+    commands.insert_resource(WatchedConfiguration {
+        scenario: if scenario_is_file { watch(&options.scenario_path) } else { None },
+        constants: watch(&options.constants_path),
+        dv: options.dv,
+        integrator: options.integrator,
+    });
+}
+
+// Replaces the problems found earlier in `file` by the new ones, and tells whether any is an error
+fn replace_problems(problems: &mut ScenarioProblems, file: &str, issues: Vec<ScenarioIssue>) -> bool {
+    problems.0.retain(|issue| issue.file != file);
+    let has_errors = issues.iter().any(|issue| issue.severity == IssueSeverity::Error);
+    problems.0.extend(issues);
+    has_errors
+}
+
+// Serialized form of a body, to find the ones that changed
+fn body_values<T: serde::Serialize>(bodies: &[T], name: impl Fn(&T) -> &str) -> Vec<(String, Value)> {
+    bodies.iter()
+        .map(|body| (name(body).to_string(), serde_json::to_value(body).unwrap_or_default()))
+        .collect()
+}
+
+fn changed_bodies(old: &SolarSystemModel, new: &SolarSystemModel) -> Vec<String> {
+    let old_bodies = [
+        body_values(&old.stars, |star| &star.name),
+        body_values(&old.planets, |planet| &planet.name),
+    ].concat();
+    let new_bodies = [
+        body_values(&new.stars, |star| &star.name),
+        body_values(&new.planets, |planet| &planet.name),
+    ].concat();
+
+    // Bodies that were added or modified, then the ones that were removed
+    let mut names: Vec<String> = new_bodies.iter()
+        .filter(|body| !old_bodies.contains(body))
+        .map(|(name, _)| name.clone())
+        .collect();
+    names.extend(old_bodies.iter()
+        .filter(|(name, _)| !new_bodies.iter().any(|(new_name, _)| new_name == name))
+        .map(|(name, _)| name.clone()));
+
+    names
+}
+
+#[allow(clippy::too_many_arguments)]
+fn apply_configuration_changes(
+    mut asset_evr: EventReader<AssetEvent<ConfigurationDocument>>,
+    documents: Res<Assets<ConfigurationDocument>>,
+    watched: Option<Res<WatchedConfiguration>>,
+    settings: Res<HotReloadSettings>,
+    mut config: ResMut<SolarSystemConfiguration>,
+    mut problems: ResMut<ScenarioProblems>,
+    mut respawn_evw: EventWriter<RespawnSolarSystem>,
+    mut respawn_bodies_evw: EventWriter<RespawnBodies>,
+) {
+    let Some(watched) = watched else {
+        return;
+    };
+
+    for ev in asset_evr.iter() {
+        // The first load only repeats what was read at startup
+        let AssetEvent::Modified { handle } = ev else {
+            continue;
+        };
+        let Some(document) = documents.get(handle) else {
+            continue;
+        };
+
+        if let Some((path, _)) = watched.constants.as_ref().filter(|(_, watched)| watched == handle) {
+            let mut issues = Vec::new();
+            let constants: Option<PhysicalConstantsModel> = match &document.0 {
+                Ok(value) => {
+                    issues.extend(validate_constants(path, value));
+                    serde_json::from_value(value.clone()).map_err(|err| issues.push(ScenarioIssue::file(path, err))).ok()
+                }
+                Err(message) => {
+                    issues.push(ScenarioIssue::file(path, message));
+                    None
+                }
+            };
+
+            if !replace_problems(&mut problems, path, issues) {
+                if let Some(mut constants) = constants {
+                    constants.dv = watched.dv.unwrap_or(constants.dv);
+                    constants.integrator = watched.integrator.unwrap_or(constants.integrator);
+                    config.physical_constants = constants;
+                    info!("Applied the new physical constants from {path}");
+                }
+            }
+        }
+
+        if let Some((path, _)) = watched.scenario.as_ref().filter(|(_, watched)| watched == handle) {
+            let mut issues = Vec::new();
+            let solar_system: Option<SolarSystemModel> = match &document.0 {
+                Ok(value) => {
+                    issues.extend(validate_scenario(path, value, &asset_root()));
+                    serde_json::from_value(value.clone()).map_err(|err| issues.push(ScenarioIssue::file(path, err))).ok()
+                }
+                Err(message) => {
+                    issues.push(ScenarioIssue::file(path, message));
+                    None
+                }
+            };
+
+            // Keep simulating the last valid version until the file is fixed
+            if replace_problems(&mut problems, path, issues) {
+                continue;
+            }
+            let Some(solar_system) = solar_system else {
+                continue;
+            };
+
+            let names = changed_bodies(&config.solar_system, &solar_system);
+            let populations_changed = serde_json::to_value(&config.solar_system.populations).ok()
+                != serde_json::to_value(&solar_system.populations).ok();
+            config.solar_system = solar_system;
+
+            if settings.reset_on_reload {
+                respawn_evw.send(RespawnSolarSystem);
+                info!("Reloaded {path} and started over");
+            } else if !names.is_empty() || populations_changed {
+                info!("Reloaded {path}, respawning {}", names.join(", "));
+                respawn_bodies_evw.send(RespawnBodies {
+                    names,
+                    keep_state: true,
+                    populations: populations_changed,
+                });
+            }
+        }
+    }
+}
+
+/// F8 switches between respawning only the changed bodies and starting over on reload.
+fn toggle_reset_on_reload(
+    actions: Res<ActionState>,
+    mut settings: ResMut<HotReloadSettings>,
+) {
+    if actions.just_pressed(Action::ResetOnReload) {
+        settings.reset_on_reload = !settings.reset_on_reload;
+
+        if settings.reset_on_reload {
+            info!("Scenario changes now restart the simulation");
+        } else {
+            info!("Scenario changes now only respawn the changed bodies");
+        }
+    }
+}
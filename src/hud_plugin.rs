@@ -0,0 +1,76 @@
+use crate::{simulation_clock::*, time_warp_plugin::*};
+use bevy::prelude::*;
+
+/// Shows the state of the simulation itself, independently of the debug overlay.
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(setup_clock_text)
+            .add_system(update_clock_text);
+    }
+}
+
+#[derive(Component)]
+struct ClockText;
+
+fn setup_clock_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/LcdRoundedRegular.ttf");
+
+    commands.spawn((
+        TextBundle::from_sections([
+            TextSection::from_style(TextStyle {
+                font: font.clone(),
+                font_size: 24.0,
+                color: Color::GOLD,
+            }),
+            TextSection::from_style(TextStyle {
+                font: font.clone(),
+                font_size: 24.0,
+                color: Color::WHITE,
+            }),
+            TextSection::from_style(TextStyle {
+                font: font.clone(),
+                font_size: 18.0,
+                color: Color::ALICE_BLUE,
+            }),
+        ])
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                bottom: Val::Px(5.0),
+                right: Val::Px(5.0),
+                ..default()
+            },
+            ..default()
+        }),
+        ClockText,
+    ));
+}
+
+fn update_clock_text(
+    clock: Res<SimulationClock>,
+    warp: Res<TimeWarp>,
+    mut texts: Query<&mut Text, With<ClockText>>,
+) {
+    if !clock.is_changed() && !warp.is_changed() {
+        return;
+    }
+
+    let warp = if warp.paused {
+        "PAUSED  ".to_string()
+    } else {
+        format!("{}×  ", warp.multiplier())
+    };
+    let date = clock.format_now();
+    let details = match clock.julian_date() {
+        Some(julian_date) => format!("\nJD {julian_date:.5}"),
+        None => format!("\nT+{:.2} time units", clock.elapsed),
+    };
+
+    for mut text in texts.iter_mut() {
+        text.sections[0].value = warp.clone();
+        text.sections[1].value = date.clone();
+        text.sections[2].value = details.clone();
+    }
+}
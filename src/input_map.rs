@@ -0,0 +1,505 @@
+// Every key, mouse button and gamepad input the simulator reacts to goes through one action map.
+// The defaults can be replaced per action from a file, in any of the scenario formats:
+//
+//     focus_next = ["Tab", "Gamepad:DPadRight"]
+//     zoom = ["Mouse:Wheel", "Gamepad:RightTrigger2", "-Gamepad:LeftTrigger2"]
+//
+// A binding is a key name, optionally after `Shift+`, `Mouse:` and a button or `Wheel`, or
+// `Gamepad:` and a button or axis. A leading `-` inverts it.
+
+use std::{collections::{BTreeMap, HashMap, HashSet}, fmt, path::Path};
+
+use bevy::{prelude::*, input::{mouse::MouseWheel, InputSystem}};
+
+use crate::{focus_plugin::*, scenario_formats::*, startup_options::*, labels::*};
+
+pub struct InputMapPlugin;
+
+impl Plugin for InputMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InputMap>()
+            .init_resource::<ActionState>()
+            .add_system_to_stage(CoreStage::PreUpdate, update_action_state
+                .label(SystemTypes::InputMapLabel)
+                .after(InputSystem));
+    }
+}
+
+/// Gamepad sticks, triggers and keys bound to an analog action move it by this many wheel
+/// notches per second when fully pressed.
+pub const ANALOG_NOTCHES_PER_SECOND: f32 = 8.0;
+
+macro_rules! actions {
+    ($($action:ident => $name:literal: [$($binding:literal),*],)*) => {
+        /// Something the user can do, whatever it is bound to.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub enum Action {
+            $($action,)*
+        }
+
+        impl Action {
+            pub const ALL: &'static [Action] = &[$(Action::$action,)*];
+
+            /// Name of the action in the input map file.
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Action::$action => $name,)*
+                }
+            }
+
+            pub fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    $($name => Some(Action::$action),)*
+                    _ => None,
+                }
+            }
+
+            fn default_bindings(&self) -> &'static [&'static str] {
+                match self {
+                    $(Action::$action => &[$($binding),*],)*
+                }
+            }
+        }
+    };
+}
+
+actions! {
+    FocusNext => "focus_next": ["Tab", "Gamepad:DPadRight"],
+    FocusPrevious => "focus_previous": ["Shift+Tab", "Gamepad:DPadLeft"],
+    Focus1 => "focus_1": ["Key1"],
+    Focus2 => "focus_2": ["Key2"],
+    Focus3 => "focus_3": ["Key3"],
+    Focus4 => "focus_4": ["Key4"],
+    Focus5 => "focus_5": ["Key5"],
+    Focus6 => "focus_6": ["Key6"],
+    Focus7 => "focus_7": ["Key7"],
+    Focus8 => "focus_8": ["Key8"],
+    Focus9 => "focus_9": ["Key9"],
+    Focus10 => "focus_10": ["Key0"],
+    OpenFocusSearch => "open_focus_search": ["F"],
+    Pick => "pick": ["Mouse:Left"],
+    Rotate => "rotate": ["Mouse:Right"],
+    RotateHorizontal => "rotate_horizontal": ["Gamepad:RightStickX"],
+    RotateVertical => "rotate_vertical": ["-Gamepad:RightStickY"],
+    Zoom => "zoom": ["Mouse:Wheel", "Gamepad:RightTrigger2", "-Gamepad:LeftTrigger2"],
+    CameraLock => "camera_lock": ["L", "Gamepad:North"],
+    FreeFlight => "free_flight": ["V", "Gamepad:RightThumb"],
+    MapView => "map_view": ["M", "Gamepad:Select"],
+    SaveBookmark => "save_bookmark": ["B"],
+    Bookmark1 => "bookmark_1": ["Shift+Key1"],
+    Bookmark2 => "bookmark_2": ["Shift+Key2"],
+    Bookmark3 => "bookmark_3": ["Shift+Key3"],
+    Bookmark4 => "bookmark_4": ["Shift+Key4"],
+    Bookmark5 => "bookmark_5": ["Shift+Key5"],
+    Bookmark6 => "bookmark_6": ["Shift+Key6"],
+    Bookmark7 => "bookmark_7": ["Shift+Key7"],
+    Bookmark8 => "bookmark_8": ["Shift+Key8"],
+    Bookmark9 => "bookmark_9": ["Shift+Key9"],
+    RecordKeyframe => "record_keyframe": ["K"],
+    ClearCameraPath => "clear_camera_path": ["Shift+K"],
+    PlayCameraPath => "play_camera_path": ["J"],
+    PictureInPicture => "picture_in_picture": ["I"],
+    PinPictureInPicture => "pin_picture_in_picture": ["O"],
+    PictureInPictureNext => "picture_in_picture_next": ["RBracket"],
+    PictureInPicturePrevious => "picture_in_picture_previous": ["LBracket"],
+    PictureInPictureTopDown => "picture_in_picture_top_down": ["T"],
+    FlyForward => "fly_forward": ["W", "Gamepad:LeftStickY"],
+    FlyBackward => "fly_backward": ["S", "-Gamepad:LeftStickY"],
+    FlyLeft => "fly_left": ["A", "-Gamepad:LeftStickX"],
+    FlyRight => "fly_right": ["D", "Gamepad:LeftStickX"],
+    FlyDown => "fly_down": ["Q", "Gamepad:DPadDown"],
+    FlyUp => "fly_up": ["E", "Gamepad:DPadUp"],
+    RollLeft => "roll_left": ["Z", "Gamepad:West"],
+    RollRight => "roll_right": ["C", "Gamepad:East"],
+    WarpFaster => "warp_faster": ["Period", "Gamepad:RightTrigger"],
+    WarpSlower => "warp_slower": ["Comma", "Gamepad:LeftTrigger"],
+    StopWarp => "stop_warp": ["Slash", "Gamepad:LeftThumb"],
+    Pause => "pause": ["P", "Gamepad:Start"],
+    SingleStep => "single_step": ["N"],
+    PreviousEclipse => "previous_eclipse": ["PageUp"],
+    NextEclipse => "next_eclipse": ["PageDown"],
+    FocusEclipseObserver => "focus_eclipse_observer": ["Return"],
+    RecordOem => "record_oem": ["R"],
+    ExportOem => "export_oem": ["F7"],
+    ExportKopernicus => "export_kopernicus": ["F6"],
+    ResetOnReload => "reset_on_reload": ["F8"],
+}
+
+macro_rules! names {
+    ($type:ident, $fn_name:ident, $fn_from_name:ident, [$($variant:ident),* $(,)?]) => {
+        fn $fn_name(value: $type) -> Option<&'static str> {
+            match value {
+                $($type::$variant => Some(stringify!($variant)),)*
+                #[allow(unreachable_patterns)]
+                _ => None,
+            }
+        }
+
+        fn $fn_from_name(name: &str) -> Option<$type> {
+            match name {
+                $(stringify!($variant) => Some($type::$variant),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+names!(KeyCode, key_name, key_from_name, [
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Insert, Home, Delete, End, PageDown, PageUp, Left, Up, Right, Down,
+    Back, Return, Space, Tab,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    NumpadAdd, NumpadSubtract, NumpadMultiply, NumpadDivide, NumpadDecimal, NumpadEnter,
+    Apostrophe, Asterisk, Plus, Backslash, Colon, Comma, Equals, Grave, Minus, Period,
+    Semicolon, Slash, LBracket, RBracket,
+    LAlt, LControl, LShift, RAlt, RControl, RShift,
+]);
+
+names!(MouseButton, mouse_button_name, mouse_button_from_name, [Left, Right, Middle]);
+
+names!(GamepadButtonType, gamepad_button_name, gamepad_button_from_name, [
+    South, East, North, West, C, Z,
+    LeftTrigger, LeftTrigger2, RightTrigger, RightTrigger2,
+    Select, Start, Mode, LeftThumb, RightThumb,
+    DPadUp, DPadDown, DPadLeft, DPadRight,
+]);
+
+names!(GamepadAxisType, gamepad_axis_name, gamepad_axis_from_name, [
+    LeftStickX, LeftStickY, LeftZ, RightStickX, RightStickY, RightZ,
+]);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InputSource {
+    Key { key: KeyCode, shift: bool },
+    Mouse(MouseButton),
+    MouseWheel,
+    GamepadButton(GamepadButtonType),
+    GamepadAxis(GamepadAxisType),
+}
+
+/// One input bound to an action.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Binding {
+    pub source: InputSource,
+    /// Counts the wrong way, for the other half of a stick or a zoom out trigger
+    pub inverted: bool,
+}
+
+impl Binding {
+    pub fn parse(text: &str) -> Option<Self> {
+        let (inverted, text) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+
+        let source = if let Some(button) = text.strip_prefix("Mouse:") {
+            match button {
+                "Wheel" => InputSource::MouseWheel,
+                _ => InputSource::Mouse(mouse_button_from_name(button)?),
+            }
+        } else if let Some(input) = text.strip_prefix("Gamepad:") {
+            gamepad_button_from_name(input).map(InputSource::GamepadButton)
+                .or_else(|| gamepad_axis_from_name(input).map(InputSource::GamepadAxis))?
+        } else if let Some(key) = text.strip_prefix("Shift+") {
+            InputSource::Key { key: key_from_name(key)?, shift: true }
+        } else {
+            InputSource::Key { key: key_from_name(text)?, shift: false }
+        };
+
+        Some(Binding { source, inverted })
+    }
+
+    fn is_keyboard(&self) -> bool {
+        matches!(self.source, InputSource::Key { .. })
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.inverted {
+            write!(f, "-")?;
+        }
+
+        match self.source {
+            InputSource::Key { key, shift } => write!(f, "{}{}", if shift { "Shift+" } else { "" }, key_name(key).unwrap_or("?")),
+            InputSource::Mouse(button) => write!(f, "Mouse:{}", mouse_button_name(button).unwrap_or("?")),
+            InputSource::MouseWheel => write!(f, "Mouse:Wheel"),
+            InputSource::GamepadButton(button) => write!(f, "Gamepad:{}", gamepad_button_name(button).unwrap_or("?")),
+            InputSource::GamepadAxis(axis) => write!(f, "Gamepad:{}", gamepad_axis_name(axis).unwrap_or("?")),
+        }
+    }
+}
+
+/// What every action is bound to.
+#[derive(Resource, Debug, Clone)]
+pub struct InputMap {
+    pub bindings: BTreeMap<Action, Vec<Binding>>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let bindings = Action::ALL.iter()
+            .map(|action| {
+                let bindings = action.default_bindings().iter()
+                    .map(|text| Binding::parse(text).expect("the default bindings are valid"))
+                    .collect();
+                (*action, bindings)
+            })
+            .collect();
+
+        InputMap { bindings }
+    }
+}
+
+impl InputMap {
+    /// Replaces the defaults of the actions listed in the file, warning about what it cannot read.
+    pub fn load(path: &str) -> Self {
+        let mut map = InputMap::default();
+
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) => {
+                warn!("Could not read the input map {path}: {err}");
+                return map;
+            }
+        };
+
+        let value = match ScenarioFormat::from_path(path).parse(&text) {
+            Ok(value) => value,
+            Err(err) => {
+                warn!("Could not read the input map {path}: {err}");
+                return map;
+            }
+        };
+
+        let entries: BTreeMap<String, Vec<String>> = match serde_json::from_value(value) {
+            Ok(entries) => entries,
+            Err(err) => {
+                warn!("{path}: expected lists of bindings by action name: {err}");
+                return map;
+            }
+        };
+
+        for (name, texts) in entries {
+            let Some(action) = Action::from_name(&name) else {
+                warn!("{path}: there is no action called {name}");
+                continue;
+            };
+
+            let bindings = texts.iter()
+                .filter_map(|text| {
+                    let binding = Binding::parse(text);
+                    if binding.is_none() {
+                        warn!("{path}: {name}: unknown input {text}");
+                    }
+                    binding
+                })
+                .collect();
+            map.bindings.insert(action, bindings);
+        }
+
+        map
+    }
+
+    /// Pairs of actions bound to the same input, which would both happen when it is pressed.
+    pub fn conflicts(&self) -> Vec<(Binding, Action, Action)> {
+        let mut owners: HashMap<Binding, Action> = HashMap::new();
+        let mut conflicts = Vec::new();
+
+        for (action, bindings) in self.bindings.iter() {
+            for binding in bindings.iter() {
+                match owners.get(binding) {
+                    Some(owner) if owner != action => conflicts.push((*binding, *owner, *action)),
+                    _ => {
+                        owners.insert(*binding, *action);
+                    }
+                }
+            }
+        }
+
+        conflicts
+    }
+
+    // Whether some action is bound to the key with Shift held, so the key alone must not also
+    // trigger the actions bound to it without Shift
+    fn has_shift_binding(&self, key: KeyCode) -> bool {
+        self.bindings.values()
+            .flatten()
+            .any(|binding| binding.source == InputSource::Key { key, shift: true })
+    }
+}
+
+impl FromWorld for InputMap {
+    fn from_world(world: &mut World) -> Self {
+        let path = world.get_resource::<StartupOptions>()
+            .map(|options| options.input_map_path.clone())
+            .unwrap_or_default();
+
+        // Without a file of their own everyone gets the defaults
+        let map = if Path::new(&path).is_file() {
+            info!("Reading the input map from {path}");
+            InputMap::load(&path)
+        } else {
+            InputMap::default()
+        };
+
+        for (binding, first, second) in map.conflicts() {
+            warn!("{binding} is bound to both {} and {}", first.name(), second.name());
+        }
+
+        map
+    }
+}
+
+/// State of every action this frame, read by the systems instead of the raw inputs.
+#[derive(Resource, Default, Debug)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    just_released: HashSet<Action>,
+    values: HashMap<Action, f32>,
+    consumed: HashSet<Action>,
+    // Actions whose keys went down while typing, their keys are ignored until released
+    typed: HashSet<Action>,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action) && !self.consumed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action) && !self.consumed.contains(&action)
+    }
+
+    pub fn just_released(&self, action: Action) -> bool {
+        self.just_released.contains(&action) && !self.consumed.contains(&action)
+    }
+
+    /// How far an analog action moved this frame, in wheel notches.
+    pub fn value(&self, action: Action) -> f32 {
+        if self.consumed.contains(&action) {
+            return 0.0;
+        }
+        self.values.get(&action).copied().unwrap_or_default()
+    }
+
+    /// Hides the action from the systems running after this one, until the next frame.
+    pub fn consume(&mut self, action: Action) {
+        self.consumed.insert(action);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_action_state(
+    time: Res<Time>,
+    map: Res<InputMap>,
+    search: Option<Res<FocusSearch>>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    mut wheel_evr: EventReader<MouseWheel>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_button_axes: Res<Axis<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    mut state: ResMut<ActionState>,
+) {
+    let wheel: f32 = wheel_evr.iter().map(|ev| ev.y).sum();
+    let shift = keys.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+    // Keys typed into the focus search are not shortcuts
+    let typing = search.is_some_and(|search| search.is_open());
+    let dt = time.delta_seconds();
+
+    let key_down = |key: KeyCode, needs_shift: bool| {
+        keys.pressed(key) && (needs_shift == shift || (!needs_shift && !map.has_shift_binding(key)))
+    };
+
+    // Strongest input of every gamepad, between -1 and 1
+    let gamepad_button = |button: GamepadButtonType| {
+        gamepads.iter()
+            .map(|gamepad| {
+                let button = GamepadButton::new(gamepad, button);
+                gamepad_button_axes.get(button)
+                    .unwrap_or(if gamepad_buttons.pressed(button) { 1.0 } else { 0.0 })
+            })
+            .fold(0.0_f32, |strongest, value| if value.abs() > strongest.abs() { value } else { strongest })
+    };
+    let gamepad_axis = |axis: GamepadAxisType| {
+        gamepads.iter()
+            .filter_map(|gamepad| gamepad_axes.get(GamepadAxis::new(gamepad, axis)))
+            .fold(0.0_f32, |strongest, value| if value.abs() > strongest.abs() { value } else { strongest })
+    };
+
+    let previously_pressed = std::mem::take(&mut state.pressed);
+    state.just_pressed.clear();
+    state.just_released.clear();
+    state.values.clear();
+    state.consumed.clear();
+
+    for (action, bindings) in map.bindings.iter() {
+        let mut pressed = false;
+        let mut value = 0.0;
+
+        // Keys held from the search, like the Return accepting it, must not fire once it closes
+        let keys_down = bindings.iter().any(|binding| match binding.source {
+            InputSource::Key { key, shift: needs_shift } => key_down(key, needs_shift),
+            _ => false,
+        });
+        if typing && keys_down {
+            state.typed.insert(*action);
+        } else if !keys_down {
+            state.typed.remove(action);
+        }
+        let ignore_keys = state.typed.contains(action);
+
+        for binding in bindings.iter() {
+            if ignore_keys && binding.is_keyboard() {
+                continue;
+            }
+
+            // Buttons are pressed or not, analog inputs count as pressed past half way
+            let (binding_pressed, binding_value) = match binding.source {
+                InputSource::Key { key, shift: needs_shift } => {
+                    let down = key_down(key, needs_shift);
+                    (down, if down { dt * ANALOG_NOTCHES_PER_SECOND } else { 0.0 })
+                }
+                InputSource::Mouse(button) => {
+                    let down = mouse_buttons.pressed(button);
+                    (down, if down { dt * ANALOG_NOTCHES_PER_SECOND } else { 0.0 })
+                }
+                InputSource::MouseWheel => (false, wheel),
+                InputSource::GamepadButton(button) => {
+                    let amount = gamepad_button(button);
+                    (amount > 0.5, amount * dt * ANALOG_NOTCHES_PER_SECOND)
+                }
+                InputSource::GamepadAxis(axis) => {
+                    let amount = gamepad_axis(axis);
+                    let amount = if binding.inverted { -amount } else { amount };
+                    (amount > 0.5, amount * dt * ANALOG_NOTCHES_PER_SECOND)
+                }
+            };
+
+            pressed |= binding_pressed;
+            // Axes are already inverted for the pressed state
+            value += match (binding.inverted, binding.source) {
+                (true, InputSource::GamepadAxis(_)) => binding_value,
+                (true, _) => -binding_value,
+                (false, _) => binding_value,
+            };
+        }
+
+        if pressed {
+            state.pressed.insert(*action);
+            if !previously_pressed.contains(action) {
+                state.just_pressed.insert(*action);
+            }
+        } else if previously_pressed.contains(action) {
+            state.just_released.insert(*action);
+        }
+        if value != 0.0 {
+            state.values.insert(*action, value);
+        }
+    }
+}
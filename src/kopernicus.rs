@@ -0,0 +1,395 @@
+// Mapping between the Body nodes of Kopernicus planet packs and the scenario models.
+// Kopernicus gives distances in m, gravitational parameters in m³/s², periods in s,
+// angles in degrees and the mean anomaly at epoch in radians.
+
+use std::{collections::HashMap, path::Path};
+
+use crate::{config_node::*, input_map::*, labels::*, physics::*, physical_constant_models::*, planet_models::*, solar_system_plugin::*};
+use bevy::prelude::*;
+
+pub struct KopernicusPlugin;
+
+impl Plugin for KopernicusPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(export_kopernicus_pack
+            .before(SystemTypes::CameraLabel));
+    }
+}
+
+// Gravitational constant and standard gravity used by KSP
+const KSP_GRAVITATIONAL_CONSTANT: f64 = 6.674e-11;
+const KSP_STANDARD_GRAVITY: f64 = 9.80665;
+
+#[derive(Debug)]
+pub enum KopernicusError {
+    Io { path: String, source: std::io::Error },
+    Syntax { path: String, source: ConfigNodeError },
+    MissingValue { body: String, key: &'static str },
+    UnknownReferenceBody { body: String, reference: String },
+    NoStar,
+}
+
+impl std::fmt::Display for KopernicusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KopernicusError::Io { path, source } => write!(f, "could not read {path}: {source}"),
+            KopernicusError::Syntax { path, source } => write!(f, "{path}, {source}"),
+            KopernicusError::MissingValue { body, key } => write!(f, "{body} does not give its {key}"),
+            KopernicusError::UnknownReferenceBody { body, reference } => {
+                write!(f, "{body} orbits {reference}, which is not in the pack")
+            }
+            KopernicusError::NoStar => write!(f, "every body has an orbit, one of them has to be the star"),
+        }
+    }
+}
+
+impl std::error::Error for KopernicusError {}
+
+/// A body of the pack, in Kopernicus units.
+struct PackBody {
+    name: String,
+    radius: f64,
+    gravitational_parameter: f64,
+    rotation_period: f64,
+    color: PlanetColor,
+    orbit: Option<PackOrbit>,
+}
+
+struct PackOrbit {
+    reference_body: String,
+    semi_major_axis: f64,
+    eccentricity: f64,
+    inclination: f64,
+    longitude_of_ascending_node: f64,
+    argument_of_periapsis: f64,
+    mean_anomaly_at_epoch: f64,
+}
+
+fn read_body(node: &ConfigNode) -> Result<PackBody, KopernicusError> {
+    let name = node.value("name").unwrap_or("unnamed body").to_string();
+    let properties = node.node("Properties");
+    let property = |key: &str| properties.and_then(|properties| properties.number(key));
+    let missing = |key: &'static str| KopernicusError::MissingValue { body: name.clone(), key };
+
+    let radius = property("radius").ok_or_else(|| missing("radius"))?;
+    // Packs give the strength of gravity in one of three ways
+    let gravitational_parameter = property("gravParameter")
+        .or_else(|| property("mass").map(|mass| mass * KSP_GRAVITATIONAL_CONSTANT))
+        .or_else(|| property("geeASL").map(|gee| gee * KSP_STANDARD_GRAVITY * radius * radius))
+        .ok_or_else(|| missing("gravParameter"))?;
+
+    let orbit = match node.node("Orbit") {
+        Some(orbit) => {
+            let number = |key: &'static str| orbit.number(key).ok_or_else(|| missing(key));
+
+            Some(PackOrbit {
+                reference_body: orbit.value("referenceBody").ok_or_else(|| missing("referenceBody"))?.trim().to_string(),
+                semi_major_axis: number("semiMajorAxis")?,
+                eccentricity: orbit.number("eccentricity").unwrap_or(0.0),
+                inclination: orbit.number("inclination").unwrap_or(0.0),
+                longitude_of_ascending_node: orbit.number("longitudeOfAscendingNode").unwrap_or(0.0),
+                argument_of_periapsis: orbit.number("argumentOfPeriapsis").unwrap_or(0.0),
+                mean_anomaly_at_epoch: orbit.number("meanAnomalyAtEpoch")
+                    .or_else(|| orbit.number("meanAnomalyAtEpochD").map(f64::to_radians))
+                    .unwrap_or(0.0),
+            })
+        }
+        None => None,
+    };
+
+    // Tidally locked bodies turn once per orbit, whatever the pack says
+    let tidally_locked = properties.and_then(|properties| properties.value("tidallyLocked")).map(str::trim) == Some("true");
+    let rotation_period = property("rotationPeriod").unwrap_or(0.0);
+
+    // Planets are drawn in the color of their orbit line, stars in the one of their light
+    let color = node.node("Orbit").and_then(|orbit| orbit.value("color"))
+        .or_else(|| node.node("ScaledVersion")
+            .and_then(|scaled| scaled.node("Light"))
+            .and_then(|light| light.value("sunlightColor")))
+        .and_then(parse_color)
+        .unwrap_or(PlanetColor { red: 255, green: 255, blue: 255 });
+
+    Ok(PackBody {
+        name,
+        radius,
+        gravitational_parameter,
+        rotation_period: if tidally_locked { 0.0 } else { rotation_period },
+        color,
+        orbit,
+    })
+}
+
+// "0.2,0.4,1,1", "RGBA(51,102,255,255)" or "#3366FF"
+fn parse_color(value: &str) -> Option<PlanetColor> {
+    let value = value.trim();
+
+    if let Some(hex) = value.strip_prefix('#') {
+        let channel = |index: usize| hex.get(index..index + 2).and_then(|digits| u8::from_str_radix(digits, 16).ok());
+        return Some(PlanetColor { red: channel(0)?, green: channel(2)?, blue: channel(4)? });
+    }
+
+    let value = value.trim_start_matches("RGBA(").trim_start_matches("RGB(").trim_end_matches(')');
+    let channels: Vec<f32> = value.split(',').filter_map(|channel| channel.trim().parse().ok()).collect();
+    if channels.len() < 3 {
+        return None;
+    }
+
+    // Colors are given as fractions unless one of the channels goes above 1
+    let scale = if channels.iter().take(3).any(|channel| *channel > 1.0) { 1.0 } else { 255.0 };
+    let channel = |index: usize| (channels[index] * scale).round().clamp(0.0, 255.0) as u8;
+    Some(PlanetColor { red: channel(0), green: channel(1), blue: channel(2) })
+}
+
+// Splits the Body nodes into the ones defining a body and the `@Body[Name]`-like patches
+fn collect_bodies(nodes: &[ConfigNode], bodies: &mut Vec<ConfigNode>, patches: &mut Vec<ConfigNode>) {
+    for node in nodes {
+        match node.base_name() {
+            "Body" if node.operator().is_none() => bodies.push(node.clone()),
+            "Body" => patches.push(node.clone()),
+            "Kopernicus" => collect_bodies(&node.nodes, bodies, patches),
+            _ => {}
+        }
+    }
+}
+
+/// Applies the patches to the bodies of the pack they name, in the order they were read.
+/// Patches of bodies from outside the pack, like the stock planets, are skipped.
+fn apply_body_patches(bodies: &mut Vec<ConfigNode>, patches: &[ConfigNode]) {
+    for patch in patches {
+        let target = patch.name_filter()
+            .and_then(|name| bodies.iter().position(|body| body.value("name").map(str::trim) == Some(name)));
+
+        match (patch.operator(), target) {
+            (Some('@' | '%'), Some(index)) => bodies[index].apply_patch(patch),
+            (Some('!' | '-'), Some(index)) => {
+                bodies.remove(index);
+            }
+            (Some('+' | '$'), Some(index)) => {
+                let mut copy = bodies[index].clone();
+                copy.apply_patch(patch);
+                bodies.push(copy);
+            }
+            _ => warn!("Skipping {}, the body it patches is not in the pack", patch.name),
+        }
+    }
+}
+
+fn read_config_files(path: &Path, files: &mut Vec<(String, String)>) -> Result<(), KopernicusError> {
+    let io_error = |source| KopernicusError::Io { path: path.display().to_string(), source };
+
+    if path.is_dir() {
+        let mut entries = std::fs::read_dir(path).map_err(io_error)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect::<Vec<_>>();
+        entries.sort();
+
+        for entry in entries {
+            if entry.is_dir() || entry.extension().is_some_and(|extension| extension == "cfg") {
+                read_config_files(&entry, files)?;
+            }
+        }
+    } else {
+        files.push((path.display().to_string(), std::fs::read_to_string(path).map_err(io_error)?));
+    }
+
+    Ok(())
+}
+
+/// Reads the Body nodes of a config file, or of every `.cfg` file below a directory, and
+/// builds a system in the simulator's units. Bodies without an orbit are stars, moons
+/// start from their place around their planet.
+pub fn load_kopernicus_system(path: &str, constants: &PhysicalConstantsModel) -> Result<SolarSystemModel, KopernicusError> {
+    let mut files = Vec::new();
+    read_config_files(Path::new(path), &mut files)?;
+
+    let mut nodes = Vec::new();
+    let mut patches = Vec::new();
+    for (file, text) in files.iter() {
+        let parsed = parse_config_nodes(text).map_err(|source| KopernicusError::Syntax { path: file.clone(), source })?;
+        collect_bodies(&parsed, &mut nodes, &mut patches);
+    }
+    apply_body_patches(&mut nodes, &patches);
+
+    let bodies = nodes.iter().map(read_body).collect::<Result<Vec<_>, _>>()?;
+    let by_name: HashMap<&str, &PackBody> = bodies.iter().map(|body| (body.name.as_str(), body)).collect();
+
+    let seconds_per_time_unit = constants.seconds_per_time_unit;
+    // m³/s² to Mm³ per time unit²
+    let to_gravitational_parameter = |mu: f64| (mu * 1e-18 * seconds_per_time_unit * seconds_per_time_unit) as f32;
+
+    let stars: Vec<StarModel> = bodies.iter()
+        .filter(|body| body.orbit.is_none())
+        .map(|body| {
+            let gravitational_parameter = to_gravitational_parameter(body.gravitational_parameter);
+            StarModel {
+                name: body.name.clone(),
+                radius: (body.radius * 1e-6) as f32,
+                mass: gravitational_parameter / constants.gravitational_constant,
+                gravitational_parameter,
+                sidereal_rotation_period: body.rotation_period as f32,
+                color: body.color,
+            }
+        })
+        .collect();
+
+    if stars.is_empty() {
+        return Err(KopernicusError::NoStar);
+    }
+
+    // Position and velocity of a body relative to the star, following its reference bodies
+    let absolute_state = |body: &PackBody| -> Result<(Vec3, Vec3), KopernicusError> {
+        let mut position = Vec3::ZERO;
+        let mut velocity = Vec3::ZERO;
+        let mut current = body;
+
+        for _ in 0..bodies.len() {
+            let Some(orbit) = current.orbit.as_ref() else {
+                break;
+            };
+            let reference = by_name.get(orbit.reference_body.as_str()).ok_or_else(|| KopernicusError::UnknownReferenceBody {
+                body: current.name.clone(),
+                reference: orbit.reference_body.clone(),
+            })?;
+
+            let elements = OrbitalElements {
+                semi_major_axis: (orbit.semi_major_axis * 1e-6) as f32,
+                eccentricity: orbit.eccentricity as f32,
+                inclination: (orbit.inclination as f32).to_radians(),
+                longitude_of_ascending_node: (orbit.longitude_of_ascending_node as f32).to_radians(),
+                argument_of_periapsis: (orbit.argument_of_periapsis as f32).to_radians(),
+                mean_anomaly: orbit.mean_anomaly_at_epoch as f32,
+            };
+            let (relative_position, relative_velocity) =
+                state_from_elements(to_gravitational_parameter(reference.gravitational_parameter), &elements);
+
+            position += relative_position;
+            velocity += relative_velocity;
+            current = reference;
+        }
+
+        Ok((position, velocity))
+    };
+
+    let mut planets = Vec::new();
+    for body in bodies.iter() {
+        let Some(orbit) = body.orbit.as_ref() else {
+            continue;
+        };
+
+        let (position, velocity) = absolute_state(body)?;
+        let reference = by_name[orbit.reference_body.as_str()];
+        let reference_mu = to_gravitational_parameter(reference.gravitational_parameter);
+        let gravitational_parameter = to_gravitational_parameter(body.gravitational_parameter);
+        let semi_major_axis = (orbit.semi_major_axis * 1e-6) as f32;
+        let periapsis = semi_major_axis * (1.0 - orbit.eccentricity as f32);
+
+        planets.push(PlanetModel {
+            name: body.name.clone(),
+            parent: reference.orbit.as_ref().map(|_| reference.name.clone()),
+            color_texture: String::new(),
+            normal_texture: String::new(),
+            radius: (body.radius * 1e-6) as f32,
+            mass: gravitational_parameter / constants.gravitational_constant,
+            gravitational_parameter,
+            sidereal_rotation_period: if body.rotation_period > 0.0 {
+                body.rotation_period as f32
+            } else {
+                // Tidally locked, one turn per orbit
+                orbital_period(reference_mu, semi_major_axis) * seconds_per_time_unit as f32
+            },
+            inclination: orbit.inclination as f32,
+            periapsis: periapsis - (reference.radius * 1e-6) as f32,
+            orbital_velocity_pe: (reference_mu * (1.0 + orbit.eccentricity as f32) / periapsis).sqrt(),
+            color: body.color,
+            initial_position: Some(position.to_array()),
+            initial_velocity: Some(velocity.to_array()),
+        });
+    }
+
+    Ok(SolarSystemModel {
+        stars,
+        planets,
+        populations: Vec::new(),
+        epoch: None,
+    })
+}
+
+/// Writes the system as a Kopernicus pack, with every body's orbit taken from its initial state.
+pub fn export_kopernicus_config(solar_system: &SolarSystemModel, constants: &PhysicalConstantsModel) -> String {
+    let seconds_per_time_unit = constants.seconds_per_time_unit;
+    // Mm³ per time unit² to m³/s²
+    let to_gravitational_parameter = |mu: f32| mu as f64 * 1e18 / (seconds_per_time_unit * seconds_per_time_unit);
+
+    let mut pack = ConfigNode::new("@Kopernicus:AFTER[Kopernicus]");
+
+    for star in solar_system.stars.iter() {
+        pack.nodes.push(ConfigNode::new("Body")
+            .with_value("name", &star.name)
+            .with_node(ConfigNode::new("Properties")
+                .with_value("radius", star.radius * 1e6)
+                .with_value("gravParameter", to_gravitational_parameter(star.gravitational_parameter))
+                .with_value("rotationPeriod", star.sidereal_rotation_period)));
+    }
+
+    let Some(sun) = solar_system.stars.first() else {
+        return write_config_nodes(&[pack]);
+    };
+
+    let find_planet = |name: &str| solar_system.planets.iter().find(|planet| planet.name == name);
+
+    for planet in solar_system.planets.iter() {
+        let (mut position, mut velocity) = system_initial_state(planet, sun, &solar_system.planets);
+        let mut reference_name = sun.name.clone();
+        let mut reference_mu = sun.gravitational_parameter;
+
+        if let Some(parent) = planet.parent.as_deref().and_then(find_planet) {
+            let (parent_position, parent_velocity) = system_initial_state(parent, sun, &solar_system.planets);
+            position -= parent_position;
+            velocity -= parent_velocity;
+            reference_name = parent.name.clone();
+            reference_mu = parent.gravitational_parameter;
+        }
+
+        let elements = elements_from_state(reference_mu, position, velocity);
+        let color = planet.color;
+
+        pack.nodes.push(ConfigNode::new("Body")
+            .with_value("name", &planet.name)
+            .with_node(ConfigNode::new("Properties")
+                .with_value("radius", planet.radius * 1e6)
+                .with_value("gravParameter", to_gravitational_parameter(planet.gravitational_parameter))
+                .with_value("rotationPeriod", planet.sidereal_rotation_period))
+            .with_node(ConfigNode::new("Orbit")
+                .with_value("referenceBody", reference_name)
+                .with_value("semiMajorAxis", elements.semi_major_axis * 1e6)
+                .with_value("eccentricity", elements.eccentricity)
+                .with_value("inclination", elements.inclination.to_degrees())
+                .with_value("longitudeOfAscendingNode", elements.longitude_of_ascending_node.to_degrees())
+                .with_value("argumentOfPeriapsis", elements.argument_of_periapsis.to_degrees())
+                .with_value("meanAnomalyAtEpoch", elements.mean_anomaly)
+                .with_value("epoch", 0)
+                .with_value("color", format!(
+                    "{:.3},{:.3},{:.3},1",
+                    color.red as f32 / 255.0,
+                    color.green as f32 / 255.0,
+                    color.blue as f32 / 255.0
+                ))));
+    }
+
+    write_config_nodes(&[pack])
+}
+
+/// F6 writes the current system as a Kopernicus pack next to the scenarios.
+fn export_kopernicus_pack(
+    actions: Res<ActionState>,
+    config: Res<SolarSystemConfiguration>,
+) {
+    if actions.just_pressed(Action::ExportKopernicus) {
+        let path = "assets/planets/kopernicus_export.cfg";
+
+        match std::fs::write(path, export_kopernicus_config(&config.solar_system, &config.physical_constants)) {
+            Ok(()) => info!("Exported the system as a Kopernicus pack to {path}"),
+            Err(err) => error!("Could not export the system to {path}: {err}"),
+        }
+    }
+}
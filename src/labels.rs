@@ -4,5 +4,8 @@ use bevy::prelude::*;
 #[derive(SystemLabel)]
 pub enum SystemTypes {
     CameraLabel = 0,
-    PhysicsLabel
+    PhysicsLabel,
+    SnapshotLabel,
+    EventDetectionLabel,
+    InputMapLabel
 }
\ No newline at end of file
@@ -7,18 +7,180 @@ mod planet_models;
 mod camera_plugin;
 mod debug_information_plugin;
 mod labels;
+mod eclipse_plugin;
+mod orbital_events_plugin;
+mod physics;
+mod instanced_rendering;
+mod particle_population_plugin;
+mod startup_options;
+mod headless_plugin;
+mod calendar;
+mod oem;
+mod oem_plugin;
+mod horizons;
+mod config_node;
+mod kopernicus;
+mod scenario_validation;
+mod scenario_formats;
+mod simulation_clock;
+mod hud_plugin;
+mod time_warp_plugin;
+mod hot_reload_plugin;
+mod picking_plugin;
+mod focus_plugin;
+mod free_flight_plugin;
+mod map_view_plugin;
+mod input_map;
+mod camera_views_plugin;
+mod picture_in_picture_plugin;
 
 use solar_system_plugin::*;
 use camera_plugin::*;
 use debug_information_plugin::*;
+use eclipse_plugin::*;
+use orbital_events_plugin::*;
+use particle_population_plugin::*;
+use startup_options::*;
+use headless_plugin::*;
+use oem_plugin::*;
+use kopernicus::*;
+use scenario_validation::*;
+use scenario_formats::*;
+use hud_plugin::*;
+use time_warp_plugin::*;
+use hot_reload_plugin::*;
+use picking_plugin::*;
+use focus_plugin::*;
+use free_flight_plugin::*;
+use map_view_plugin::*;
+use input_map::*;
+use camera_views_plugin::*;
+use picture_in_picture_plugin::*;
+use planet_models::*;
 
 fn main() {
-    App::new()
+    let options = StartupOptions::from_args();
+
+    if let Some((input, output)) = &options.convert {
+        convert(input, output);
+    }
+
+    if options.validate {
+        validate(options);
+    }
+
+    if let Some(headless) = options.headless.clone() {
+        run_headless(options, headless);
+        return;
+    }
+
+    let (width, height) = options.window_size;
+    let debug_overlay = options.debug_overlay;
+
+    let mut app = App::new();
+    app.insert_resource(options)
         .insert_resource(ClearColor(Color::rgb(0.0, 0.0, 0.0)))
         .insert_resource(AmbientLight { color: Color::Rgba { red: 0.01, green: 0.01, blue: 0.01, alpha: 1.0 }, brightness: 500.0})
-        .add_plugins(DefaultPlugins)
+        .add_plugins(DefaultPlugins
+            .set(WindowPlugin {
+                window: WindowDescriptor {
+                    width,
+                    height,
+                    ..default()
+                },
+                ..default()
+            })
+            // Lets the hot reload plugin follow the scenario and constants files
+            .set(AssetPlugin {
+                watch_for_changes: true,
+                ..default()
+            }))
+        .add_plugin(InputMapPlugin)
+        .add_plugin(ScenarioValidationPlugin)
         .add_plugin(SolarSystemPlugin)
+        .add_plugin(TimeWarpPlugin)
         .add_plugin(CameraPlugin)
-        .add_plugin(DebugInformationPlugin)
+        .add_plugin(FocusPlugin)
+        .add_plugin(FreeFlightPlugin)
+        .add_plugin(MapViewPlugin)
+        .add_plugin(CameraViewsPlugin)
+        .add_plugin(PictureInPicturePlugin)
+        .add_plugin(PickingPlugin)
+        .add_plugin(HudPlugin)
+        .add_plugin(EclipsePlugin)
+        .add_plugin(OrbitalEventsPlugin)
+        .add_plugin(ParticlePopulationPlugin)
+        .add_plugin(OemPlugin)
+        .add_plugin(KopernicusPlugin)
+        .add_plugin(HotReloadPlugin);
+
+    if debug_overlay {
+        app.add_plugin(DebugInformationPlugin);
+    }
+
+    app.run();
+}
+
+/// Loads the scenario like a normal start would, lists the problems found and exits,
+/// with a failure status if any of them keeps the scenario from loading.
+fn validate(options: StartupOptions) -> ! {
+    let mut world = World::new();
+    world.insert_resource(options);
+    let config = SolarSystemConfiguration::from_world(&mut world);
+
+    let problems = world.resource::<ScenarioProblems>();
+    for issue in problems.0.iter() {
+        eprintln!("{issue}");
+    }
+
+    if problems.has_errors() {
+        std::process::exit(1);
+    }
+
+    println!(
+        "Valid: {} stars, {} planets, {} populations",
+        config.solar_system.stars.len(),
+        config.solar_system.planets.len(),
+        config.solar_system.populations.len(),
+    );
+    std::process::exit(0);
+}
+
+/// Writes a scenario or constants file in the format of the output extension and exits.
+fn convert(input: &str, output: &str) -> ! {
+    match convert_scenario_file(input, output) {
+        Ok(issues) => {
+            for issue in issues.iter() {
+                eprintln!("{issue}");
+            }
+            println!("Wrote {output}");
+            std::process::exit(0);
+        }
+        Err(issues) => {
+            for issue in issues.iter() {
+                eprintln!("{issue}");
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Runs only the physics, without a window or a GPU, and writes the ephemerides to a file.
+fn run_headless(options: StartupOptions, headless: HeadlessOptions) {
+    let mut app = App::new();
+    app.insert_resource(options)
+        .add_plugins(MinimalPlugins)
+        .add_plugin(SolarSystemPhysicsPlugin);
+
+    // There is no screen to show the problems on, so list them and stop
+    let problems = app.world.resource::<ScenarioProblems>();
+    for issue in problems.0.iter() {
+        eprintln!("{issue}");
+    }
+    if problems.has_errors() {
+        std::process::exit(1);
+    }
+
+    app.add_plugin(HeadlessPlugin { options: headless })
         .run();
 }
\ No newline at end of file
@@ -0,0 +1,196 @@
+use crate::{camera_plugin::*, input_map::*, planet_components::*, planet_models::*, labels::*};
+use bevy::{prelude::*, render::{camera::ScalingMode, view::RenderLayers}};
+
+/// M switches to a top-down orthographic map of the ecliptic, centered on the focused body,
+/// and back to the 3D camera as it was left. The wheel zooms the map.
+pub struct MapViewPlugin;
+
+impl Plugin for MapViewPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MapView>()
+            .add_startup_system(spawn_map_camera)
+            .add_system(toggle_map_view.before(SystemTypes::CameraLabel))
+            .add_system(follow_map_focus
+                .label(SystemTypes::CameraLabel)
+                .after(toggle_map_view))
+            .add_system(update_map_icons.after(follow_map_focus));
+    }
+}
+
+/// Bodies smaller than this on the map are drawn as icons of this radius, in logical pixels.
+pub const MIN_ICON_RADIUS: f32 = 4.0;
+
+/// Height of the map camera above the focus, in Mm. Everything in the scenario is much closer
+/// to the ecliptic than that, the clipping planes are then moved around the bodies.
+const MAP_CAMERA_HEIGHT: f32 = 1.0e6;
+
+/// Height of the icons above the center of their body, in radii, clear of the top of the sphere.
+const ICON_HEIGHT: f32 = 1.01;
+
+/// Layer of the icons, only the map camera draws them.
+const MAP_ICON_LAYER: u8 = 1;
+
+#[derive(Resource, Default, Debug)]
+pub struct MapView {
+    pub active: bool,
+}
+
+/// The camera of the map view.
+#[derive(Component, Debug, Default)]
+pub struct MapCamera {
+    /// Point of the ecliptic at the center of the map
+    pub focus: Vec3,
+    // Fits the map to the whole scenario the first time it is opened
+    fitted: bool,
+}
+
+// Disc drawn over a body on the map
+#[derive(Component)]
+struct MapIcon {
+    body: Entity,
+}
+
+fn spawn_map_camera(mut commands: Commands) {
+    commands.spawn((
+        Camera3dBundle {
+            camera: Camera {
+                is_active: false,
+                priority: 1,
+                ..default()
+            },
+            projection: Projection::Orthographic(OrthographicProjection {
+                near: 0.0,
+                far: 2.0 * MAP_CAMERA_HEIGHT,
+                scaling_mode: ScalingMode::WindowSize,
+                ..default()
+            }),
+            transform: Transform::from_xyz(0.0, MAP_CAMERA_HEIGHT, 0.0).looking_at(Vec3::ZERO, Vec3::NEG_Z),
+            ..default()
+        },
+        RenderLayers::from_layers(&[0, MAP_ICON_LAYER]),
+        MapCamera::default(),
+    ));
+}
+
+fn toggle_map_view(
+    actions: Res<ActionState>,
+    mut map: ResMut<MapView>,
+    mut cameras: Query<(&mut Camera, Option<&MapCamera>), Or<(With<PanOrbitCamera>, With<MapCamera>)>>,
+) {
+    if !actions.just_pressed(Action::MapView) {
+        return;
+    }
+
+    map.active = !map.active;
+
+    // The 3D camera is only switched off, so it comes back exactly as it was
+    for (mut camera, map_camera) in cameras.iter_mut() {
+        camera.is_active = map_camera.is_some() == map.active;
+    }
+}
+
+/// Keeps the focused body in the middle of the map, the wheel zooms it.
+fn follow_map_focus(
+    windows: Res<Windows>,
+    map: Res<MapView>,
+    actions: Res<ActionState>,
+    mut cameras: Query<(&mut MapCamera, &mut Transform, &mut Projection)>,
+    bodies: Query<(&Transform, &FocusableEntity), Without<MapCamera>>,
+) {
+    let scroll = actions.value(Action::Zoom);
+
+    if !map.active {
+        return;
+    }
+
+    let focus = bodies.iter().find(|(_, focus)| focus.is_focused).map(|(transform, _)| transform.translation);
+    let half_height = windows.get_primary().map_or(360.0, |window| window.height() / 2.0);
+
+    for (mut map_camera, mut transform, mut projection) in cameras.iter_mut() {
+        let Projection::Orthographic(orthographic) = &mut *projection else {
+            continue;
+        };
+
+        if !map_camera.fitted {
+            // Mm per pixel to see the body farthest from the star
+            let extent = bodies.iter().map(|(transform, _)| transform.translation.length()).fold(0.0, f32::max);
+            orthographic.scale = (1.2 * extent / half_height).max(0.001);
+            map_camera.fitted = true;
+        }
+
+        if scroll != 0.0 {
+            orthographic.scale = (orthographic.scale * 0.85_f32.powf(scroll)).clamp(0.001, 1.0e4);
+        }
+
+        if let Some(focus) = focus {
+            map_camera.focus = focus;
+        }
+        transform.translation = map_camera.focus + Vec3::Y * MAP_CAMERA_HEIGHT;
+    }
+}
+
+/// Draws a disc over every body, at least `MIN_ICON_RADIUS` pixels wide.
+fn update_map_icons(
+    mut commands: Commands,
+    map: Res<MapView>,
+    config: Res<SolarSystemConfiguration>,
+    cameras: Query<&Projection, With<MapCamera>>,
+    bodies: Query<(Entity, &Transform, &CelestialBody), Without<MapIcon>>,
+    mut icons: Query<(Entity, &MapIcon, &mut Transform, &mut Visibility)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let Some(Projection::Orthographic(orthographic)) = cameras.iter().next() else {
+        return;
+    };
+
+    // Icons of bodies that are gone go with them
+    for (icon, map_icon, _, _) in icons.iter() {
+        if bodies.get(map_icon.body).is_err() {
+            commands.entity(icon).despawn();
+        }
+    }
+
+    for (entity, transform, body) in bodies.iter() {
+        if icons.iter().any(|(_, map_icon, _, _)| map_icon.body == entity) {
+            continue;
+        }
+
+        let color = config.solar_system.stars.iter().find(|star| star.name == body.name).map(|star| star.color)
+            .or_else(|| config.solar_system.planets.iter().find(|planet| planet.name == body.name).map(|planet| planet.color))
+            .map_or(Color::WHITE, |color| *color.to_color().set_a(1.0));
+
+        commands.spawn((
+            PbrBundle {
+                mesh: meshes.add(Mesh::from(shape::Circle::new(1.0))),
+                material: materials.add(StandardMaterial {
+                    base_color: color,
+                    unlit: true,
+                    ..default()
+                }),
+                // Facing up, just above the top of the body
+                transform: Transform::from_translation(transform.translation + Vec3::Y * body.radius * ICON_HEIGHT)
+                    .with_rotation(Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2)),
+                ..default()
+            },
+            RenderLayers::layer(MAP_ICON_LAYER),
+            MapIcon { body: entity },
+        ));
+    }
+
+    for (_, map_icon, mut icon_transform, mut visibility) in icons.iter_mut() {
+        if visibility.is_visible != map.active {
+            visibility.is_visible = map.active;
+        }
+        if !map.active {
+            continue;
+        }
+
+        let Ok((_, transform, body)) = bodies.get(map_icon.body) else {
+            continue;
+        };
+
+        icon_transform.translation = transform.translation + Vec3::Y * body.radius * ICON_HEIGHT;
+        icon_transform.scale = Vec3::splat(body.radius.max(MIN_ICON_RADIUS * orthographic.scale));
+    }
+}
@@ -0,0 +1,622 @@
+// Reading and writing CCSDS Orbit Ephemeris Messages (CCSDS 502.0-B-2),
+// in both the keyword = value (KVN) and the XML variants.
+
+use std::fmt::Write;
+
+use crate::calendar::*;
+
+/// Reference frames the simulator can express its states in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OemFrame {
+    /// Mean ecliptic and equinox of J2000, the natural frame of the simulation
+    EclipticJ2000,
+    /// Equatorial frame, rotated from the ecliptic by the obliquity of J2000
+    Icrf,
+}
+
+// Obliquity of the ecliptic at J2000, in degrees
+const J2000_OBLIQUITY: f64 = 23.439_291_1;
+
+impl OemFrame {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OemFrame::EclipticJ2000 => "ECLIPJ2000",
+            OemFrame::Icrf => "ICRF",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim() {
+            "ECLIPJ2000" => Some(OemFrame::EclipticJ2000),
+            "ICRF" | "EME2000" | "GCRF" => Some(OemFrame::Icrf),
+            _ => None,
+        }
+    }
+
+    /// Converts a vector from ecliptic coordinates to this frame.
+    pub fn rotate_from_ecliptic(self, v: [f64; 3]) -> [f64; 3] {
+        match self {
+            OemFrame::EclipticJ2000 => v,
+            OemFrame::Icrf => {
+                let (sin, cos) = J2000_OBLIQUITY.to_radians().sin_cos();
+                [v[0], cos * v[1] - sin * v[2], sin * v[1] + cos * v[2]]
+            }
+        }
+    }
+
+    /// Converts a vector from this frame to ecliptic coordinates.
+    pub fn rotate_to_ecliptic(self, v: [f64; 3]) -> [f64; 3] {
+        match self {
+            OemFrame::EclipticJ2000 => v,
+            OemFrame::Icrf => {
+                let (sin, cos) = J2000_OBLIQUITY.to_radians().sin_cos();
+                [v[0], cos * v[1] + sin * v[2], -sin * v[1] + cos * v[2]]
+            }
+        }
+    }
+}
+
+/// Time system the epochs are written in, for a scenario shown in `calendar`. Kerbin times have
+/// no counterpart on Earth, so they are written as mission elapsed time from Year 1, Day 1.
+pub fn time_system_for(calendar: Calendar) -> &'static str {
+    match calendar {
+        Calendar::Kerbin => "MET",
+        Calendar::Earth => "UTC",
+    }
+}
+
+/// Whether epochs in `time_system` count from the origin of `calendar`, like the simulation clock.
+pub fn time_system_matches(time_system: &str, calendar: Calendar) -> bool {
+    is_relative(time_system) == (calendar == Calendar::Kerbin)
+}
+
+// Mission elapsed and relative times are durations, written as DDDThh:mm:ss
+fn is_relative(time_system: &str) -> bool {
+    matches!(time_system.trim(), "MET" | "MRT")
+}
+
+fn format_relative(seconds: f64) -> String {
+    let sign = if seconds < 0.0 { "-" } else { "" };
+    let milliseconds = (seconds.abs() * 1000.0).round() as u64;
+    let (days, millisecond_of_day) = (milliseconds / 86_400_000, milliseconds % 86_400_000);
+    let (hour, minute) = (millisecond_of_day / 3_600_000, millisecond_of_day / 60_000 % 60);
+    let second = (millisecond_of_day % 60_000) as f64 / 1000.0;
+
+    format!("{sign}{days:03}T{hour:02}:{minute:02}:{second:06.3}")
+}
+
+fn parse_relative(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let (sign, text) = match text.strip_prefix('-') {
+        Some(rest) => (-1.0, rest),
+        None => (1.0, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let (days, time) = text.split_once('T')?;
+
+    let fields = time.split(':').map(|field| field.parse::<f64>().ok()).collect::<Option<Vec<_>>>()?;
+    let [hour, minute, second] = fields.as_slice() else {
+        return None;
+    };
+
+    Some(sign * (days.parse::<f64>().ok()? * 86400.0 + hour * 3600.0 + minute * 60.0 + second))
+}
+
+// Formats an epoch, in seconds since the origin of the calendar, the way `time_system` expects it
+fn format_epoch(seconds: f64, time_system: &str) -> String {
+    if is_relative(time_system) {
+        format_relative(seconds)
+    } else {
+        format_iso8601(seconds)
+    }
+}
+
+/// One line of ephemeris data.
+#[derive(Clone, Debug)]
+pub struct OemState {
+    /// Seconds since J2000, or since Year 1, Day 1 for mission elapsed time
+    pub epoch: f64,
+    /// km
+    pub position: [f64; 3],
+    /// km/s
+    pub velocity: [f64; 3],
+}
+
+#[derive(Clone, Debug)]
+pub struct OemSegment {
+    pub object_name: String,
+    pub object_id: String,
+    pub center_name: String,
+    pub ref_frame: String,
+    pub time_system: String,
+    pub states: Vec<OemState>,
+}
+
+#[derive(Clone, Debug)]
+pub struct OemMessage {
+    pub originator: String,
+    /// Seconds since J2000, in UTC
+    pub creation_date: f64,
+    pub segments: Vec<OemSegment>,
+}
+
+#[derive(Debug)]
+pub enum OemError {
+    Syntax { line: usize, message: String },
+    MissingKeyword(String),
+}
+
+impl std::fmt::Display for OemError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OemError::Syntax { line, message } => write!(f, "line {line}: {message}"),
+            OemError::MissingKeyword(keyword) => write!(f, "missing {keyword}"),
+        }
+    }
+}
+
+impl std::error::Error for OemError {}
+
+fn segment_bounds(segment: &OemSegment) -> (f64, f64) {
+    let start = segment.states.first().map(|state| state.epoch).unwrap_or_default();
+    let stop = segment.states.last().map(|state| state.epoch).unwrap_or(start);
+    (start, stop)
+}
+
+pub fn write_kvn(message: &OemMessage) -> String {
+    let mut out = String::new();
+
+    // Writing to a String cannot fail
+    let _ = writeln!(out, "CCSDS_OEM_VERS = 2.0");
+    let _ = writeln!(out, "CREATION_DATE = {}", format_iso8601(message.creation_date));
+    let _ = writeln!(out, "ORIGINATOR = {}", message.originator);
+
+    for segment in message.segments.iter() {
+        let (start, stop) = segment_bounds(segment);
+
+        let _ = writeln!(out);
+        let _ = writeln!(out, "META_START");
+        let _ = writeln!(out, "OBJECT_NAME = {}", segment.object_name);
+        let _ = writeln!(out, "OBJECT_ID = {}", segment.object_id);
+        let _ = writeln!(out, "CENTER_NAME = {}", segment.center_name);
+        let _ = writeln!(out, "REF_FRAME = {}", segment.ref_frame);
+        let _ = writeln!(out, "TIME_SYSTEM = {}", segment.time_system);
+        let _ = writeln!(out, "START_TIME = {}", format_epoch(start, &segment.time_system));
+        let _ = writeln!(out, "STOP_TIME = {}", format_epoch(stop, &segment.time_system));
+        let _ = writeln!(out, "META_STOP");
+        let _ = writeln!(out);
+
+        for state in segment.states.iter() {
+            let p = state.position;
+            let v = state.velocity;
+            let _ = writeln!(
+                out,
+                "{} {:.6} {:.6} {:.6} {:.9} {:.9} {:.9}",
+                format_epoch(state.epoch, &segment.time_system), p[0], p[1], p[2], v[0], v[1], v[2]
+            );
+        }
+    }
+
+    out
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&")
+}
+
+pub fn write_xml(message: &OemMessage) -> String {
+    let mut out = String::new();
+
+    let _ = writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(out, r#"<oem xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance""#);
+    let _ = writeln!(out, r#"     xsi:noNamespaceSchemaLocation="http://sanaregistry.org/r/ndmxml/ndmxml-2.0.0-master-2.0.xsd""#);
+    let _ = writeln!(out, r#"     id="CCSDS_OEM_VERS" version="2.0">"#);
+    let _ = writeln!(out, "  <header>");
+    let _ = writeln!(out, "    <CREATION_DATE>{}</CREATION_DATE>", format_iso8601(message.creation_date));
+    let _ = writeln!(out, "    <ORIGINATOR>{}</ORIGINATOR>", escape_xml(&message.originator));
+    let _ = writeln!(out, "  </header>");
+    let _ = writeln!(out, "  <body>");
+
+    for segment in message.segments.iter() {
+        let (start, stop) = segment_bounds(segment);
+
+        let _ = writeln!(out, "    <segment>");
+        let _ = writeln!(out, "      <metadata>");
+        let _ = writeln!(out, "        <OBJECT_NAME>{}</OBJECT_NAME>", escape_xml(&segment.object_name));
+        let _ = writeln!(out, "        <OBJECT_ID>{}</OBJECT_ID>", escape_xml(&segment.object_id));
+        let _ = writeln!(out, "        <CENTER_NAME>{}</CENTER_NAME>", escape_xml(&segment.center_name));
+        let _ = writeln!(out, "        <REF_FRAME>{}</REF_FRAME>", escape_xml(&segment.ref_frame));
+        let _ = writeln!(out, "        <TIME_SYSTEM>{}</TIME_SYSTEM>", escape_xml(&segment.time_system));
+        let _ = writeln!(out, "        <START_TIME>{}</START_TIME>", format_epoch(start, &segment.time_system));
+        let _ = writeln!(out, "        <STOP_TIME>{}</STOP_TIME>", format_epoch(stop, &segment.time_system));
+        let _ = writeln!(out, "      </metadata>");
+        let _ = writeln!(out, "      <data>");
+
+        for state in segment.states.iter() {
+            let p = state.position;
+            let v = state.velocity;
+            let _ = writeln!(out, "        <stateVector>");
+            let _ = writeln!(out, "          <EPOCH>{}</EPOCH>", format_epoch(state.epoch, &segment.time_system));
+            let _ = writeln!(out, "          <X>{:.6}</X>", p[0]);
+            let _ = writeln!(out, "          <Y>{:.6}</Y>", p[1]);
+            let _ = writeln!(out, "          <Z>{:.6}</Z>", p[2]);
+            let _ = writeln!(out, "          <X_DOT>{:.9}</X_DOT>", v[0]);
+            let _ = writeln!(out, "          <Y_DOT>{:.9}</Y_DOT>", v[1]);
+            let _ = writeln!(out, "          <Z_DOT>{:.9}</Z_DOT>", v[2]);
+            let _ = writeln!(out, "        </stateVector>");
+        }
+
+        let _ = writeln!(out, "      </data>");
+        let _ = writeln!(out, "    </segment>");
+    }
+
+    let _ = writeln!(out, "  </body>");
+    let _ = writeln!(out, "</oem>");
+
+    out
+}
+
+/// Parses an OEM, in the XML variant if the text starts with a tag, in KVN otherwise.
+pub fn parse_oem(text: &str) -> Result<OemMessage, OemError> {
+    if text.trim_start().starts_with('<') {
+        parse_xml(text)
+    } else {
+        parse_kvn(text)
+    }
+}
+
+fn parse_numbers<const N: usize>(values: &[&str], line: usize) -> Result<[f64; N], OemError> {
+    let mut numbers = [0.0; N];
+    for (number, value) in numbers.iter_mut().zip(values.iter()) {
+        *number = value.parse().map_err(|_| OemError::Syntax {
+            line,
+            message: format!("{value} is not a number"),
+        })?;
+    }
+    Ok(numbers)
+}
+
+fn parse_epoch(value: &str, time_system: &str, line: usize) -> Result<f64, OemError> {
+    let epoch = if is_relative(time_system) { parse_relative(value) } else { parse_iso8601(value) };
+    epoch.ok_or_else(|| OemError::Syntax {
+        line,
+        message: format!("{value} is not a valid epoch"),
+    })
+}
+
+fn parse_kvn(text: &str) -> Result<OemMessage, OemError> {
+    let mut message = OemMessage {
+        originator: String::new(),
+        creation_date: 0.0,
+        segments: Vec::new(),
+    };
+
+    let mut in_metadata = false;
+    let mut in_covariance = false;
+
+    for (index, raw_line) in text.lines().enumerate() {
+        let line = index + 1;
+        let content = raw_line.trim();
+
+        if content.is_empty() || content.starts_with("COMMENT") {
+            continue;
+        }
+
+        match content {
+            "META_START" => {
+                in_metadata = true;
+                message.segments.push(OemSegment {
+                    object_name: String::new(),
+                    object_id: String::new(),
+                    center_name: String::new(),
+                    ref_frame: String::new(),
+                    time_system: String::new(),
+                    states: Vec::new(),
+                });
+                continue;
+            }
+            "META_STOP" => {
+                in_metadata = false;
+                continue;
+            }
+            "COVARIANCE_START" => {
+                in_covariance = true;
+                continue;
+            }
+            "COVARIANCE_STOP" => {
+                in_covariance = false;
+                continue;
+            }
+            _ => {}
+        }
+
+        if in_covariance {
+            continue;
+        }
+
+        if let Some((key, value)) = content.split_once('=') {
+            let key = key.trim();
+            let value = value.trim().to_string();
+
+            if in_metadata {
+                // A segment was pushed when META_START was read
+                let segment = message.segments.last_mut().unwrap();
+                match key {
+                    "OBJECT_NAME" => segment.object_name = value,
+                    "OBJECT_ID" => segment.object_id = value,
+                    "CENTER_NAME" => segment.center_name = value,
+                    "REF_FRAME" => segment.ref_frame = value,
+                    "TIME_SYSTEM" => segment.time_system = value,
+                    _ => {}
+                }
+            } else {
+                match key {
+                    "CREATION_DATE" => message.creation_date = parse_epoch(&value, "UTC", line)?,
+                    "ORIGINATOR" => message.originator = value,
+                    _ => {}
+                }
+            }
+            continue;
+        }
+
+        // Anything else is a line of ephemeris data, possibly followed by accelerations
+        let values: Vec<&str> = content.split_whitespace().collect();
+        if values.len() < 7 {
+            return Err(OemError::Syntax {
+                line,
+                message: "expected an epoch followed by a position and a velocity".to_string(),
+            });
+        }
+
+        let segment = message.segments.last_mut().ok_or_else(|| OemError::Syntax {
+            line,
+            message: "ephemeris data before META_START".to_string(),
+        })?;
+
+        let [x, y, z, vx, vy, vz] = parse_numbers::<6>(&values[1..7], line)?;
+        segment.states.push(OemState {
+            epoch: parse_epoch(values[0], &segment.time_system, line)?,
+            position: [x, y, z],
+            velocity: [vx, vy, vz],
+        });
+    }
+
+    if message.segments.is_empty() {
+        return Err(OemError::MissingKeyword("META_START".to_string()));
+    }
+
+    Ok(message)
+}
+
+/// Returns the content of every `<tag ...>...</tag>` element directly found in `xml`.
+fn xml_elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{tag}");
+    let close = format!("</{tag}>");
+    let mut elements = Vec::new();
+    let mut rest = xml;
+
+    while let Some(start) = rest.find(&open) {
+        let after_name = &rest[start + open.len()..];
+
+        // Skip longer tags sharing the same prefix, like X_DOT when looking for X
+        if !after_name.starts_with(|c: char| c == '>' || c.is_whitespace()) {
+            rest = after_name;
+            continue;
+        }
+
+        let content_start = match after_name.find('>') {
+            Some(end) => end + 1,
+            None => break,
+        };
+        let content = &after_name[content_start..];
+
+        match content.find(&close) {
+            Some(end) => {
+                elements.push(&content[..end]);
+                rest = &content[end + close.len()..];
+            }
+            None => break,
+        }
+    }
+
+    elements
+}
+
+fn xml_value(xml: &str, tag: &str) -> Option<String> {
+    xml_elements(xml, tag).first().map(|value| unescape_xml(value.trim()))
+}
+
+fn xml_required(xml: &str, tag: &str) -> Result<String, OemError> {
+    xml_value(xml, tag).ok_or_else(|| OemError::MissingKeyword(tag.to_string()))
+}
+
+fn parse_xml(text: &str) -> Result<OemMessage, OemError> {
+    let header = xml_elements(text, "header").first().copied().unwrap_or_default();
+
+    let mut message = OemMessage {
+        originator: xml_value(header, "ORIGINATOR").unwrap_or_default(),
+        creation_date: match xml_value(header, "CREATION_DATE") {
+            Some(date) => parse_epoch(&date, "UTC", 0)?,
+            None => 0.0,
+        },
+        segments: Vec::new(),
+    };
+
+    for segment_xml in xml_elements(text, "segment") {
+        let metadata = xml_elements(segment_xml, "metadata")
+            .first()
+            .copied()
+            .ok_or_else(|| OemError::MissingKeyword("metadata".to_string()))?;
+
+        let mut segment = OemSegment {
+            object_name: xml_required(metadata, "OBJECT_NAME")?,
+            object_id: xml_value(metadata, "OBJECT_ID").unwrap_or_default(),
+            center_name: xml_required(metadata, "CENTER_NAME")?,
+            ref_frame: xml_required(metadata, "REF_FRAME")?,
+            time_system: xml_required(metadata, "TIME_SYSTEM")?,
+            states: Vec::new(),
+        };
+
+        for state_xml in xml_elements(segment_xml, "stateVector") {
+            let mut values = [0.0; 6];
+            for (value, tag) in values.iter_mut().zip(["X", "Y", "Z", "X_DOT", "Y_DOT", "Z_DOT"]) {
+                let text = xml_required(state_xml, tag)?;
+                *value = text.parse().map_err(|_| OemError::Syntax {
+                    line: 0,
+                    message: format!("{tag} {text} is not a number"),
+                })?;
+            }
+
+            segment.states.push(OemState {
+                epoch: parse_epoch(&xml_required(state_xml, "EPOCH")?, &segment.time_system, 0)?,
+                position: [values[0], values[1], values[2]],
+                velocity: [values[3], values[4], values[5]],
+            });
+        }
+
+        message.segments.push(segment);
+    }
+
+    if message.segments.is_empty() {
+        return Err(OemError::MissingKeyword("segment".to_string()));
+    }
+
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() <= tolerance, "{actual} is not within {tolerance} of {expected}");
+    }
+
+    fn sample_message(time_system: &str) -> OemMessage {
+        let state = |epoch: f64, scale: f64| OemState {
+            epoch,
+            position: [13_599_840.256 * scale, -1_234.5, 42.125],
+            velocity: [-0.5 * scale, 9.284_512_3, 0.000_001],
+        };
+
+        OemMessage {
+            originator: "R&D <Kerbal>".to_string(),
+            creation_date: 86_400.5,
+            segments: vec![
+                OemSegment {
+                    object_name: "Kerbin".to_string(),
+                    object_id: "KERBIN".to_string(),
+                    center_name: "Kerbol".to_string(),
+                    ref_frame: OemFrame::EclipticJ2000.name().to_string(),
+                    time_system: time_system.to_string(),
+                    states: vec![state(0.0, 1.0), state(3_600.25, -1.0), state(9_203_545.0, 0.5)],
+                },
+                OemSegment {
+                    object_name: "Mun".to_string(),
+                    object_id: "MUN".to_string(),
+                    center_name: "Kerbin".to_string(),
+                    ref_frame: OemFrame::Icrf.name().to_string(),
+                    time_system: time_system.to_string(),
+                    states: vec![state(-21_600.0, 2.0)],
+                },
+            ],
+        }
+    }
+
+    fn assert_same_message(actual: &OemMessage, expected: &OemMessage) {
+        assert_eq!(actual.originator, expected.originator);
+        assert_close(actual.creation_date, expected.creation_date, 1e-3);
+        assert_eq!(actual.segments.len(), expected.segments.len());
+
+        for (actual, expected) in actual.segments.iter().zip(expected.segments.iter()) {
+            assert_eq!(actual.object_name, expected.object_name);
+            assert_eq!(actual.object_id, expected.object_id);
+            assert_eq!(actual.center_name, expected.center_name);
+            assert_eq!(actual.ref_frame, expected.ref_frame);
+            assert_eq!(actual.time_system, expected.time_system);
+            assert_eq!(actual.states.len(), expected.states.len());
+
+            for (actual, expected) in actual.states.iter().zip(expected.states.iter()) {
+                assert_close(actual.epoch, expected.epoch, 1e-3);
+                for axis in 0..3 {
+                    assert_close(actual.position[axis], expected.position[axis], 1e-6);
+                    assert_close(actual.velocity[axis], expected.velocity[axis], 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn kvn_survives_a_round_trip() {
+        for time_system in ["UTC", "MET"] {
+            let message = sample_message(time_system);
+            let kvn = write_kvn(&message);
+
+            assert!(kvn.starts_with("CCSDS_OEM_VERS = 2.0"));
+            assert_same_message(&parse_oem(&kvn).unwrap(), &message);
+        }
+    }
+
+    #[test]
+    fn xml_survives_a_round_trip() {
+        for time_system in ["UTC", "MET"] {
+            let message = sample_message(time_system);
+            let xml = write_xml(&message);
+
+            assert!(xml.contains("<ORIGINATOR>R&amp;D &lt;Kerbal&gt;</ORIGINATOR>"));
+            assert_same_message(&parse_oem(&xml).unwrap(), &message);
+        }
+    }
+
+    #[test]
+    fn epochs_follow_the_time_system() {
+        let kvn = write_kvn(&sample_message("UTC"));
+        assert!(kvn.contains("START_TIME = 2000-01-01T12:00:00.000"));
+        assert!(kvn.contains("\n2000-01-01T13:00:00.250 "));
+
+        // Mission elapsed days are Earth days of 24 hours, whatever the calendar
+        let kvn = write_kvn(&sample_message("MET"));
+        assert!(kvn.contains("STOP_TIME = 106T12:32:25.000"));
+        assert!(kvn.contains("\n-000T06:00:00.000 "));
+    }
+
+    #[test]
+    fn time_system_matches_the_calendar() {
+        assert!(time_system_matches(time_system_for(Calendar::Kerbin), Calendar::Kerbin));
+        assert!(time_system_matches(time_system_for(Calendar::Earth), Calendar::Earth));
+        assert!(time_system_matches("TDB", Calendar::Earth));
+        assert!(!time_system_matches("UTC", Calendar::Kerbin));
+        assert!(!time_system_matches("MET", Calendar::Earth));
+    }
+
+    #[test]
+    fn icrf_is_the_ecliptic_tilted_by_the_obliquity() {
+        let (sin, cos) = J2000_OBLIQUITY.to_radians().sin_cos();
+
+        // The equinox is shared, the pole of the ecliptic leans towards the solstice
+        let equinox = OemFrame::Icrf.rotate_from_ecliptic([1.0, 0.0, 0.0]);
+        let pole = OemFrame::Icrf.rotate_from_ecliptic([0.0, 0.0, 1.0]);
+        for (actual, expected) in equinox.iter().chain(pole.iter()).zip([1.0, 0.0, 0.0, 0.0, -sin, cos]) {
+            assert_close(*actual, expected, 1e-12);
+        }
+
+        let v = [1_000.0, -2_000.0, 3_000.0];
+        let back = OemFrame::Icrf.rotate_to_ecliptic(OemFrame::Icrf.rotate_from_ecliptic(v));
+        for axis in 0..3 {
+            assert_close(back[axis], v[axis], 1e-9);
+        }
+        assert_eq!(OemFrame::EclipticJ2000.rotate_from_ecliptic(v), v);
+    }
+
+    #[test]
+    fn frame_names_are_read_back() {
+        for frame in [OemFrame::EclipticJ2000, OemFrame::Icrf] {
+            assert_eq!(OemFrame::from_name(frame.name()), Some(frame));
+        }
+        assert_eq!(OemFrame::from_name("EME2000"), Some(OemFrame::Icrf));
+        assert_eq!(OemFrame::from_name("TEME"), None);
+    }
+}
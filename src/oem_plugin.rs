@@ -0,0 +1,275 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{input_map::*, planet_components::*, planet_models::*, simulation_clock::*, startup_options::*, time_warp_plugin::*, labels::*, oem::*, physics::*};
+use bevy::prelude::*;
+
+/// Records the trajectories of chosen bodies as CCSDS Orbit Ephemeris Messages and
+/// replays the ones given with `--oem` on the bodies of the same name.
+pub struct OemPlugin;
+
+impl Plugin for OemPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<OemSettings>()
+            .init_resource::<OemRecorder>()
+            .add_startup_system_to_stage(StartupStage::PostStartup, load_oem_trajectories)
+            .add_system(follow_oem_trajectories
+                .with_run_criteria(simulation_running)
+                .after(SystemTypes::PhysicsLabel)
+                .before(SystemTypes::EventDetectionLabel)
+                .before(SystemTypes::CameraLabel))
+            .add_system(record_oem_states
+                .with_run_criteria(simulation_running)
+                .after(SystemTypes::PhysicsLabel))
+            .add_system(toggle_oem_recording)
+            .add_system(export_oem);
+    }
+}
+
+#[derive(Resource)]
+pub struct OemSettings {
+    /// Frame the exported states are expressed in
+    pub frame: OemFrame,
+    /// Only record the states every this many physics steps
+    pub sample_every: u32,
+    /// Exported files are written as `<output_path>.oem` and `<output_path>.xml`
+    pub output_path: String,
+}
+
+impl Default for OemSettings {
+    fn default() -> Self {
+        OemSettings {
+            frame: OemFrame::EclipticJ2000,
+            sample_every: 10,
+            output_path: "oem/trajectories".to_string(),
+        }
+    }
+}
+
+/// States recorded so far, by body, in the order the recording started.
+#[derive(Resource, Default)]
+pub struct OemRecorder {
+    pub recordings: Vec<(Entity, Vec<OemState>)>,
+    steps: u64,
+}
+
+// Seconds between the Unix epoch and J2000
+const J2000_UNIX_SECONDS: f64 = 946_728_000.0;
+
+/// R starts or stops recording the focused body.
+fn toggle_oem_recording(
+    actions: Res<ActionState>,
+    mut recorder: ResMut<OemRecorder>,
+    bodies: Query<(Entity, &CelestialBody, &FocusableEntity)>,
+) {
+    if actions.just_pressed(Action::RecordOem) {
+        let Some((entity, body, _)) = bodies.iter().find(|(_, _, focus)| focus.is_focused) else {
+            return;
+        };
+
+        if let Some(index) = recorder.recordings.iter().position(|(recorded, _)| *recorded == entity) {
+            recorder.recordings.remove(index);
+            info!("Stopped recording {}", body.name);
+        } else {
+            recorder.recordings.push((entity, Vec::new()));
+            info!("Recording {}", body.name);
+        }
+    }
+}
+
+fn record_oem_states(
+    mut recorder: ResMut<OemRecorder>,
+    settings: Res<OemSettings>,
+    clock: Res<SimulationClock>,
+    config: Res<SolarSystemConfiguration>,
+    bodies: Query<(&Transform, &CelestialBody)>,
+    stars: Query<&Transform, With<Star>>,
+) {
+    recorder.steps += 1;
+    if recorder.steps % settings.sample_every.max(1) as u64 != 0 {
+        return;
+    }
+
+    let seconds_per_time_unit = config.physical_constants.seconds_per_time_unit;
+    let center = stars.iter().next().map(|star| star.translation).unwrap_or_default();
+
+    for (entity, states) in recorder.recordings.iter_mut() {
+        let Ok((pos, body)) = bodies.get(*entity) else {
+            continue;
+        };
+
+        // Mm to km, and Mm per time unit to km/s
+        let position = world_to_ecliptic(pos.translation - center).as_dvec3() * 1000.0;
+        let velocity = world_to_ecliptic(body.vel.vector).as_dvec3() * 1000.0 / seconds_per_time_unit;
+
+        states.push(OemState {
+            epoch: clock.now(),
+            position: settings.frame.rotate_from_ecliptic(position.to_array()),
+            velocity: settings.frame.rotate_from_ecliptic(velocity.to_array()),
+        });
+    }
+}
+
+/// F7 writes everything recorded so far, in both the KVN and the XML variants.
+fn export_oem(
+    actions: Res<ActionState>,
+    recorder: Res<OemRecorder>,
+    settings: Res<OemSettings>,
+    config: Res<SolarSystemConfiguration>,
+    clock: Res<SimulationClock>,
+    bodies: Query<&CelestialBody>,
+) {
+    if actions.just_pressed(Action::ExportOem) {
+        let center_name = config.solar_system.stars.first().map(|star| star.name.clone()).unwrap_or_default();
+        let creation_date = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs_f64() - J2000_UNIX_SECONDS)
+            .unwrap_or_default();
+
+        let segments = recorder.recordings.iter()
+            .filter(|(_, states)| !states.is_empty())
+            .filter_map(|(entity, states)| {
+                let body = bodies.get(*entity).ok()?;
+                Some(OemSegment {
+                    object_name: body.name.clone(),
+                    object_id: body.name.clone(),
+                    center_name: center_name.clone(),
+                    ref_frame: settings.frame.name().to_string(),
+                    time_system: time_system_for(clock.calendar).to_string(),
+                    states: states.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if segments.is_empty() {
+            warn!("Nothing recorded yet, press R to record the focused body");
+            return;
+        }
+
+        let message = OemMessage {
+            originator: "SOLAR SYSTEM SIMULATOR".to_string(),
+            creation_date,
+            segments,
+        };
+
+        let kvn_path = format!("{}.oem", settings.output_path);
+        let xml_path = format!("{}.xml", settings.output_path);
+        let result = std::path::Path::new(&kvn_path).parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&kvn_path, write_kvn(&message)))
+            .and_then(|_| std::fs::write(&xml_path, write_xml(&message)));
+
+        match result {
+            Ok(()) => info!("Exported the recorded trajectories to {kvn_path} and {xml_path}"),
+            Err(err) => error!("Could not export the recorded trajectories: {err}"),
+        }
+    }
+}
+
+/// Attaches the trajectories read from the files given with `--oem` to the bodies they describe.
+fn load_oem_trajectories(
+    mut commands: Commands,
+    options: Option<Res<StartupOptions>>,
+    config: Res<SolarSystemConfiguration>,
+    clock: Res<SimulationClock>,
+    bodies: Query<(Entity, &CelestialBody)>,
+) {
+    let Some(options) = options else {
+        return;
+    };
+
+    let seconds_per_time_unit = config.physical_constants.seconds_per_time_unit;
+
+    for path in options.oem_inputs.iter() {
+        let message = match std::fs::read_to_string(path) {
+            Ok(text) => parse_oem(&text),
+            Err(err) => {
+                error!("Could not read {path}: {err}");
+                continue;
+            }
+        };
+
+        let message = match message {
+            Ok(message) => message,
+            Err(err) => {
+                error!("Could not parse {path}: {err}");
+                continue;
+            }
+        };
+
+        for segment in message.segments {
+            let Some(frame) = OemFrame::from_name(&segment.ref_frame) else {
+                warn!("{path}: unsupported frame {} for {}", segment.ref_frame, segment.object_name);
+                continue;
+            };
+            if !time_system_matches(&segment.time_system, clock.calendar) {
+                warn!("{path}: {} epochs of {} do not fit the {} calendar", segment.time_system, segment.object_name, clock.calendar.name());
+                continue;
+            }
+
+            let find_body = |name: &str| bodies.iter()
+                .find(|(_, body)| body.name.eq_ignore_ascii_case(name))
+                .map(|(entity, _)| entity);
+
+            let Some(entity) = find_body(&segment.object_name) else {
+                warn!("{path}: no body called {}", segment.object_name);
+                continue;
+            };
+
+            // km to Mm, and km/s to Mm per time unit
+            let mut states = segment.states.iter()
+                .map(|state| {
+                    let position = Vec3::from_array(frame.rotate_to_ecliptic(state.position).map(|x| (x / 1000.0) as f32));
+                    let velocity = Vec3::from_array(frame.rotate_to_ecliptic(state.velocity)
+                        .map(|x| (x / 1000.0 * seconds_per_time_unit) as f32));
+
+                    (clock.time_at(state.epoch), ecliptic_to_world(position), ecliptic_to_world(velocity))
+                })
+                .collect::<Vec<_>>();
+            states.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            info!("{} follows {} states from {path}", segment.object_name, states.len());
+
+            commands.entity(entity).insert(OemTrajectory {
+                center: find_body(&segment.center_name),
+                states,
+            });
+        }
+    }
+}
+
+/// Puts the bodies driven by an ephemeris where it says they are, interpolating between its states.
+fn follow_oem_trajectories(
+    clock: Res<SimulationClock>,
+    mut followers: Query<(&mut Transform, &mut CelestialBody, &OemTrajectory)>,
+    centers: Query<(&Transform, &CelestialBody), Without<OemTrajectory>>,
+) {
+    for (mut pos, mut body, trajectory) in followers.iter_mut() {
+        let (Some(first), Some(last)) = (trajectory.states.first(), trajectory.states.last()) else {
+            continue;
+        };
+
+        // Hold the first or last state outside of the covered span
+        let (position, velocity) = if clock.elapsed <= first.0 {
+            (first.1, first.2)
+        } else if clock.elapsed >= last.0 {
+            (last.1, last.2)
+        } else {
+            let index = trajectory.states.partition_point(|state| state.0 <= clock.elapsed);
+            let (t0, p0, v0) = trajectory.states[index - 1];
+            let (t1, p1, v1) = trajectory.states[index];
+            let dt = (t1 - t0) as f32;
+            let s = ((clock.elapsed - t0) / (t1 - t0)) as f32;
+            let start = PreviousState { position: p0, velocity: v0 };
+
+            (start.interpolate(p1, v1, dt, s), start.interpolate_velocity(p1, v1, dt, s))
+        };
+
+        let (center_position, center_velocity) = trajectory.center
+            .and_then(|center| centers.get(center).ok())
+            .map(|(center_pos, center_body)| (center_pos.translation, center_body.vel.vector))
+            .unwrap_or_default();
+
+        pos.translation = center_position + position;
+        body.vel.vector = center_velocity + velocity;
+    }
+}
@@ -0,0 +1,430 @@
+use std::collections::VecDeque;
+
+use crate::{planet_components::*, planet_models::*, simulation_clock::*, startup_options::*, time_warp_plugin::*, labels::*};
+use bevy::prelude::*;
+
+pub struct OrbitalEventsPlugin;
+
+impl Plugin for OrbitalEventsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<OrbitalEventSettings>()
+            .init_resource::<OrbitalEventLog>()
+            .add_event::<PeriapsisPassage>()
+            .add_event::<ApoapsisPassage>()
+            .add_event::<AscendingNodeCrossing>()
+            .add_event::<DescendingNodeCrossing>()
+            .add_event::<ClosestApproach>()
+            .add_event::<Conjunction>()
+            .add_event::<Opposition>()
+            .add_startup_system(setup_orbital_event_text)
+            .add_system(detect_orbital_events
+                .with_run_criteria(simulation_running)
+                .label(SystemTypes::EventDetectionLabel)
+                .after(SystemTypes::PhysicsLabel)
+                .before(SystemTypes::CameraLabel))
+            .add_system(log_orbital_events
+                .after(SystemTypes::EventDetectionLabel))
+            .add_system(update_orbital_event_text
+                .after(SystemTypes::EventDetectionLabel));
+    }
+}
+
+#[derive(Resource)]
+pub struct OrbitalEventSettings {
+    /// Event times are refined until they are known to within this many time units
+    pub time_tolerance: f32,
+    /// Pairs of body names for which closest approaches are reported
+    pub closest_approach_pairs: Vec<(String, String)>,
+    /// Name of the body from which conjunctions and oppositions are seen
+    pub conjunction_observer: Option<String>,
+    /// How many entries the on-screen log keeps
+    pub log_length: usize,
+}
+
+/// The pairs and the observer come from the command line, there are none by default.
+impl FromWorld for OrbitalEventSettings {
+    fn from_world(world: &mut World) -> Self {
+        let options = world.get_resource::<StartupOptions>().cloned().unwrap_or_default();
+
+        OrbitalEventSettings {
+            time_tolerance: 0.01,
+            closest_approach_pairs: options.closest_approach_pairs,
+            conjunction_observer: options.conjunction_observer,
+            log_length: 8,
+        }
+    }
+}
+
+pub struct PeriapsisPassage {
+    pub body: Entity,
+    pub time: f64,
+    pub distance: f32,
+}
+
+pub struct ApoapsisPassage {
+    pub body: Entity,
+    pub time: f64,
+    pub distance: f32,
+}
+
+pub struct AscendingNodeCrossing {
+    pub body: Entity,
+    pub time: f64,
+}
+
+pub struct DescendingNodeCrossing {
+    pub body: Entity,
+    pub time: f64,
+}
+
+pub struct ClosestApproach {
+    pub first: Entity,
+    pub second: Entity,
+    pub time: f64,
+    pub distance: f32,
+}
+
+/// The body lines up with the star as seen from the observer.
+pub struct Conjunction {
+    pub observer: Entity,
+    pub body: Entity,
+    pub time: f64,
+}
+
+/// The body is on the opposite side of the sky from the star as seen from the observer.
+pub struct Opposition {
+    pub observer: Entity,
+    pub body: Entity,
+    pub time: f64,
+}
+
+/// Human readable history of the latest orbital events.
+#[derive(Resource, Default)]
+pub struct OrbitalEventLog {
+    pub entries: VecDeque<String>,
+}
+
+#[derive(Component)]
+struct OrbitalEventText;
+
+/// State of a body at both ends of the last physics step.
+struct Track {
+    entity: Entity,
+    name: String,
+    gravitational_parameter: f32,
+    is_star: bool,
+    previous: PreviousState,
+    position: Vec3,
+    velocity: Vec3,
+}
+
+impl Track {
+    fn position_at(&self, dv: f32, s: f32) -> Vec3 {
+        self.previous.interpolate(self.position, self.velocity, dv, s)
+    }
+
+    fn velocity_at(&self, dv: f32, s: f32) -> Vec3 {
+        self.previous.interpolate_velocity(self.position, self.velocity, dv, s)
+    }
+}
+
+/// Finds where `f` changes sign inside the step by bisection, given that
+/// it does between `s = 0` and `s = 1`. Returns the step fraction of the root.
+fn bisect_root(f: impl Fn(f32) -> f32, tolerance: f32) -> f32 {
+    let mut low = 0.0;
+    let mut high = 1.0;
+    let low_sign = f(low).is_sign_negative();
+
+    let mut iterations = 0;
+    while high - low > tolerance && iterations < 64 {
+        let mid = 0.5 * (low + high);
+        if f(mid).is_sign_negative() == low_sign {
+            low = mid;
+        } else {
+            high = mid;
+        }
+        iterations += 1;
+    }
+
+    0.5 * (low + high)
+}
+
+/// Returns the direction of the sign change of `f` over the step, if any:
+/// `Some(true)` when it goes from negative to positive.
+fn sign_change(f: &impl Fn(f32) -> f32) -> Option<bool> {
+    let start = f(0.0);
+    let end = f(1.0);
+
+    if start < 0.0 && end >= 0.0 {
+        Some(true)
+    } else if start >= 0.0 && end < 0.0 {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn detect_orbital_events(
+    bodies: Query<(Entity, &Transform, &CelestialBody, &PreviousState, Option<&Star>)>,
+    clock: Res<SimulationClock>,
+    config: Res<SolarSystemConfiguration>,
+    settings: Res<OrbitalEventSettings>,
+    mut periapsis_evw: EventWriter<PeriapsisPassage>,
+    mut apoapsis_evw: EventWriter<ApoapsisPassage>,
+    mut ascending_evw: EventWriter<AscendingNodeCrossing>,
+    mut descending_evw: EventWriter<DescendingNodeCrossing>,
+    mut approach_evw: EventWriter<ClosestApproach>,
+    mut conjunction_evw: EventWriter<Conjunction>,
+    mut opposition_evw: EventWriter<Opposition>,
+) {
+    let dv = config.physical_constants.dv;
+    if dv <= 0.0 {
+        return;
+    }
+
+    let step_start = clock.elapsed - dv as f64;
+    let tolerance = settings.time_tolerance / dv;
+    let time_at = |s: f32| step_start + (s * dv) as f64;
+
+    let tracks: Vec<Track> = bodies
+        .iter()
+        .map(|(entity, pos, body, previous, star)| Track {
+            entity,
+            name: body.name.clone(),
+            gravitational_parameter: body.gravitational_parameter,
+            is_star: star.is_some(),
+            previous: PreviousState {
+                position: previous.position,
+                velocity: previous.velocity,
+            },
+            position: pos.translation,
+            velocity: body.vel.vector,
+        })
+        .collect();
+
+    // Conjunctions are seen against the first star
+    let star = match tracks.iter().find(|track| track.is_star) {
+        Some(star) => star,
+        None => return,
+    };
+
+    for track in tracks.iter().filter(|track| !track.is_star) {
+        // Orbits are measured around the parent of the body, or the first star
+        let parent = config.solar_system.planets
+            .iter()
+            .find(|planet| planet.name == track.name)
+            .and_then(|planet| planet.parent.as_ref())
+            .and_then(|name| tracks.iter().find(|other| &other.name == name))
+            .unwrap_or(star);
+
+        let relative_position = |s: f32| track.position_at(dv, s) - parent.position_at(dv, s);
+        let relative_velocity = |s: f32| track.velocity_at(dv, s) - parent.velocity_at(dv, s);
+
+        // Apsides: the radial velocity changes sign, only on a bound orbit
+        let energy = 0.5 * relative_velocity(1.0).length_squared() - parent.gravitational_parameter / relative_position(1.0).length();
+        let radial_velocity = |s: f32| relative_position(s).dot(relative_velocity(s));
+        if let Some(rising) = sign_change(&radial_velocity).filter(|_| energy < 0.0) {
+            let s = bisect_root(radial_velocity, tolerance);
+            let distance = relative_position(s).length();
+
+            if rising {
+                periapsis_evw.send(PeriapsisPassage { body: track.entity, time: time_at(s), distance });
+            } else {
+                apoapsis_evw.send(ApoapsisPassage { body: track.entity, time: time_at(s), distance });
+            }
+        }
+
+        // Nodes: the body crosses the reference plane through its parent, whose normal is the Y axis
+        let height = |s: f32| relative_position(s).y;
+        if let Some(rising) = sign_change(&height) {
+            let s = bisect_root(height, tolerance);
+
+            if rising {
+                ascending_evw.send(AscendingNodeCrossing { body: track.entity, time: time_at(s) });
+            } else {
+                descending_evw.send(DescendingNodeCrossing { body: track.entity, time: time_at(s) });
+            }
+        }
+    }
+
+    let find = |name: &String| tracks.iter().find(|track| &track.name == name);
+
+    // Closest approach: the range rate goes from closing to opening
+    for (first_name, second_name) in settings.closest_approach_pairs.iter() {
+        if let (Some(first), Some(second)) = (find(first_name), find(second_name)) {
+            let range_rate = |s: f32| {
+                (first.position_at(dv, s) - second.position_at(dv, s))
+                    .dot(first.velocity_at(dv, s) - second.velocity_at(dv, s))
+            };
+
+            if sign_change(&range_rate) == Some(true) {
+                let s = bisect_root(range_rate, tolerance);
+                let distance = (first.position_at(dv, s) - second.position_at(dv, s)).length();
+
+                approach_evw.send(ClosestApproach {
+                    first: first.entity,
+                    second: second.entity,
+                    time: time_at(s),
+                    distance,
+                });
+            }
+        }
+    }
+
+    // Conjunctions and oppositions: the body crosses the observer-star line
+    if let Some(observer) = settings.conjunction_observer.as_ref().and_then(find) {
+        for track in tracks.iter().filter(|track| !track.is_star && track.entity != observer.entity) {
+            let side = |s: f32| {
+                let origin = observer.position_at(dv, s);
+                (star.position_at(dv, s) - origin).cross(track.position_at(dv, s) - origin).y
+            };
+
+            if sign_change(&side).is_some() {
+                let s = bisect_root(side, tolerance);
+                let origin = observer.position_at(dv, s);
+                let aligned = (star.position_at(dv, s) - origin).dot(track.position_at(dv, s) - origin) > 0.0;
+
+                if aligned {
+                    conjunction_evw.send(Conjunction { observer: observer.entity, body: track.entity, time: time_at(s) });
+                } else {
+                    opposition_evw.send(Opposition { observer: observer.entity, body: track.entity, time: time_at(s) });
+                }
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn log_orbital_events(
+    bodies: Query<&CelestialBody>,
+    clock: Res<SimulationClock>,
+    settings: Res<OrbitalEventSettings>,
+    mut log: ResMut<OrbitalEventLog>,
+    mut periapsis_evr: EventReader<PeriapsisPassage>,
+    mut apoapsis_evr: EventReader<ApoapsisPassage>,
+    mut ascending_evr: EventReader<AscendingNodeCrossing>,
+    mut descending_evr: EventReader<DescendingNodeCrossing>,
+    mut approach_evr: EventReader<ClosestApproach>,
+    mut conjunction_evr: EventReader<Conjunction>,
+    mut opposition_evr: EventReader<Opposition>,
+) {
+    let name = |entity: Entity| bodies.get(entity).map(|body| body.name.clone()).unwrap_or_default();
+    let mut entries = Vec::new();
+
+    for ev in periapsis_evr.iter() {
+        entries.push((ev.time, format!("{} at periapsis, {:.3} Mm", name(ev.body), ev.distance)));
+    }
+    for ev in apoapsis_evr.iter() {
+        entries.push((ev.time, format!("{} at apoapsis, {:.3} Mm", name(ev.body), ev.distance)));
+    }
+    for ev in ascending_evr.iter() {
+        entries.push((ev.time, format!("{} at ascending node", name(ev.body))));
+    }
+    for ev in descending_evr.iter() {
+        entries.push((ev.time, format!("{} at descending node", name(ev.body))));
+    }
+    for ev in approach_evr.iter() {
+        entries.push((ev.time, format!("{} closest to {}, {:.3} Mm", name(ev.first), name(ev.second), ev.distance)));
+    }
+    for ev in conjunction_evr.iter() {
+        entries.push((ev.time, format!("{} in conjunction from {}", name(ev.body), name(ev.observer))));
+    }
+    for ev in opposition_evr.iter() {
+        entries.push((ev.time, format!("{} in opposition from {}", name(ev.body), name(ev.observer))));
+    }
+
+    // Several events can happen in the same step, keep them in chronological order
+    entries.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    for (time, entry) in entries {
+        log.entries.push_back(format!("[{}] {entry}", clock.format_at(time)));
+        while log.entries.len() > settings.log_length {
+            log.entries.pop_front();
+        }
+    }
+}
+
+fn setup_orbital_event_text(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/LcdRoundedRegular.ttf");
+
+    commands.spawn((
+        TextBundle::from_sections([
+            TextSection::new(
+                "Orbital events:\n",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 20.0,
+                    color: Color::WHITE,
+                },
+            ),
+            TextSection::from_style(TextStyle {
+                font: font.clone(),
+                font_size: 20.0,
+                color: Color::ALICE_BLUE,
+            }),
+        ])
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                top: Val::Px(5.0),
+                right: Val::Px(5.0),
+                ..default()
+            },
+            ..default()
+        }),
+        OrbitalEventText,
+    ));
+}
+
+fn update_orbital_event_text(
+    log: Res<OrbitalEventLog>,
+    mut texts: Query<&mut Text, With<OrbitalEventText>>,
+) {
+    if !log.is_changed() {
+        return;
+    }
+
+    let lines: Vec<&str> = log.entries.iter().map(|entry| entry.as_str()).collect();
+    for mut text in texts.iter_mut() {
+        text.sections[1].value = lines.join("\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!((actual - expected).abs() <= tolerance, "{actual} is not within {tolerance} of {expected}");
+    }
+
+    #[test]
+    fn sign_change_gives_the_direction() {
+        assert_eq!(sign_change(&|s: f32| s - 0.5), Some(true));
+        assert_eq!(sign_change(&|s: f32| 0.5 - s), Some(false));
+        assert_eq!(sign_change(&|s: f32| s + 1.0), None);
+        assert_eq!(sign_change(&|s: f32| -s - 1.0), None);
+    }
+
+    #[test]
+    fn sign_change_counts_zero_as_positive() {
+        // A root at the end of the step belongs to this step, one at the start to the previous one
+        assert_eq!(sign_change(&|s: f32| s - 1.0), Some(true));
+        assert_eq!(sign_change(&|s: f32| s), None);
+    }
+
+    #[test]
+    fn bisect_root_finds_the_root_within_tolerance() {
+        assert_close(bisect_root(|s| s - 0.3, 1e-4), 0.3, 1e-4);
+        assert_close(bisect_root(|s| 0.7 - s, 1e-4), 0.7, 1e-4);
+        assert_close(bisect_root(|s| (s * std::f32::consts::PI).cos(), 1e-4), 0.5, 1e-4);
+    }
+
+    #[test]
+    fn bisect_root_stops_at_the_tolerance() {
+        let root = bisect_root(|s| s - 0.3, 0.25);
+        assert_close(root, 0.3, 0.25);
+        assert_ne!(root, 0.3);
+    }
+}
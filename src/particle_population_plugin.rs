@@ -0,0 +1,229 @@
+use std::f32::consts::{PI, TAU};
+
+use crate::{planet_components::*, planet_models::*, physics::*, instanced_rendering::*, solar_system_plugin::*, time_warp_plugin::*, labels::*};
+use bevy::{prelude::*, render::view::NoFrustumCulling};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+pub struct ParticlePopulationPlugin;
+
+impl Plugin for ParticlePopulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(InstancedRenderingPlugin)
+            .add_startup_system(spawn_populations)
+            .add_system(respawn_populations
+                .before(SystemTypes::SnapshotLabel))
+            .add_system(move_particles
+                .with_run_criteria(simulation_running)
+                .label(SystemTypes::PhysicsLabel)
+                .before(SystemTypes::CameraLabel))
+            .add_system(update_particle_instances
+                .after(SystemTypes::PhysicsLabel));
+    }
+}
+
+/// Massless test particles: they are pulled by the celestial bodies but do not pull anything.
+#[derive(Component)]
+pub struct ParticlePopulation {
+    pub name: String,
+    pub positions: Vec<Vec3>,
+    pub velocities: Vec<Vec3>,
+}
+
+fn sample(rng: &mut StdRng, range: [f32; 2]) -> f32 {
+    if range[1] > range[0] {
+        rng.gen_range(range[0]..range[1])
+    } else {
+        range[0]
+    }
+}
+
+/// Generates the initial positions and velocities, relative to the star, of a population.
+/// Returns `None` if the population refers to a planet that does not exist.
+fn generate_population(population: &PopulationModel, sun: &StarModel, planets: &[PlanetModel]) -> Option<Vec<(Vec3, Vec3)>> {
+    let mut rng = StdRng::seed_from_u64(population.seed);
+    let mu = sun.gravitational_parameter;
+
+    let random_orientation = |rng: &mut StdRng, eccentricity: [f32; 2], inclination: [f32; 2]| OrbitalElements {
+        eccentricity: sample(rng, eccentricity),
+        inclination: sample(rng, inclination).to_radians(),
+        longitude_of_ascending_node: rng.gen_range(0.0..TAU),
+        argument_of_periapsis: rng.gen_range(0.0..TAU),
+        mean_anomaly: rng.gen_range(0.0..TAU),
+        ..default()
+    };
+
+    let states = match &population.distribution {
+        PopulationDistribution::Belt { semi_major_axis, eccentricity, inclination } => {
+            (0..population.count)
+                .map(|_| {
+                    let elements = OrbitalElements {
+                        semi_major_axis: sample(&mut rng, *semi_major_axis),
+                        ..random_orientation(&mut rng, *eccentricity, *inclination)
+                    };
+                    state_from_elements(mu, &elements)
+                })
+                .collect()
+        }
+        PopulationDistribution::ScatteredDisk { periapsis, eccentricity, inclination } => {
+            (0..population.count)
+                .map(|_| {
+                    let mut elements = random_orientation(&mut rng, *eccentricity, *inclination);
+                    elements.semi_major_axis = sample(&mut rng, *periapsis) / (1.0 - elements.eccentricity);
+                    state_from_elements(mu, &elements)
+                })
+                .collect()
+        }
+        PopulationDistribution::Trojans { planet, libration_amplitude, eccentricity, inclination } => {
+            let planet = planets.iter().find(|x| &x.name == planet)?;
+
+            // Same initial state as the one the planet is spawned with, relative to its parent
+            let (parent_position, parent_velocity) = planet.parent.as_ref()
+                .and_then(|name| planets.iter().find(|x| &x.name == name))
+                .map_or((Vec3::ZERO, Vec3::ZERO), |parent| system_initial_state(parent, sun, planets));
+            let (position, velocity) = system_initial_state(planet, sun, planets);
+            let position = position - parent_position;
+            let velocity = velocity - parent_velocity;
+            let normal = position.cross(velocity).normalize();
+
+            (0..population.count)
+                .map(|i| {
+                    // Alternate between L4, leading the planet by 60 degrees, and L5, trailing it
+                    let lagrange_point = if i % 2 == 0 { PI / 3.0 } else { -PI / 3.0 };
+                    let libration = sample(&mut rng, [-*libration_amplitude, *libration_amplitude]).to_radians();
+                    let along_orbit = Quat::from_axis_angle(normal, lagrange_point + libration);
+
+                    let tilt = Quat::from_axis_angle(
+                        (along_orbit * position).normalize(),
+                        sample(&mut rng, *inclination).to_radians() * if rng.gen_bool(0.5) { 1.0 } else { -1.0 },
+                    );
+
+                    // Speeding up or slowing down turns the point into the periapsis or the apoapsis
+                    let e = sample(&mut rng, *eccentricity);
+                    let speed_factor = if rng.gen_bool(0.5) { (1.0 + e).sqrt() } else { (1.0 - e).sqrt() };
+
+                    (parent_position + along_orbit * position, parent_velocity + tilt * along_orbit * velocity * speed_factor)
+                })
+                .collect()
+        }
+    };
+
+    Some(states)
+}
+
+fn spawn_populations(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    config: Res<SolarSystemConfiguration>,
+) {
+    spawn_configured_populations(&mut commands, &mut meshes, &config);
+}
+
+fn respawn_populations(
+    mut commands: Commands,
+    mut respawn_evr: EventReader<RespawnSolarSystem>,
+    mut respawn_bodies_evr: EventReader<RespawnBodies>,
+    populations: Query<Entity, With<ParticlePopulation>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    config: Res<SolarSystemConfiguration>,
+) {
+    let respawn_all = respawn_evr.iter().count() > 0;
+    let populations_changed = respawn_bodies_evr.iter().any(|ev| ev.populations);
+    if !respawn_all && !populations_changed {
+        return;
+    }
+
+    for entity in populations.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    spawn_configured_populations(&mut commands, &mut meshes, &config);
+}
+
+fn spawn_configured_populations(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    config: &SolarSystemConfiguration,
+) {
+    let sun = match config.solar_system.stars.iter().next() {
+        Some(sun) => sun,
+        None => return,
+    };
+
+    // All particles share a small sphere, scaled per instance
+    let mesh = meshes.add(Mesh::from(shape::Icosphere {
+        radius: 1.0,
+        subdivisions: 1,
+    }));
+
+    for population in config.solar_system.populations.iter() {
+        if population.count == 0 {
+            continue;
+        }
+
+        let states = match generate_population(population, sun, &config.solar_system.planets) {
+            Some(states) => states,
+            None => {
+                warn!("Skipping population {}: unknown planet", population.name);
+                continue;
+            }
+        };
+
+        let color = population.color.to_color().as_rgba_f32();
+        let instances = states
+            .iter()
+            .map(|(position, _)| InstanceData {
+                position: *position,
+                scale: population.display_radius,
+                color: [color[0], color[1], color[2], 1.0],
+            })
+            .collect();
+
+        commands.spawn((
+            mesh.clone(),
+            SpatialBundle::VISIBLE_IDENTITY,
+            InstanceMaterialData(instances),
+            // The instances are spread across the whole system, far outside the bounding box of the mesh
+            NoFrustumCulling,
+            ParticlePopulation {
+                name: population.name.clone(),
+                positions: states.iter().map(|x| x.0).collect(),
+                velocities: states.iter().map(|x| x.1).collect(),
+            },
+        ));
+    }
+}
+
+fn move_particles(
+    bodies: Query<(&Transform, &CelestialBody)>,
+    mut populations: Query<&mut ParticlePopulation>,
+    constants: Res<SolarSystemConfiguration>,
+) {
+    let dv = constants.physical_constants.dv;
+
+    // Only the bodies with a known gravitational parameter pull the particles
+    let attractors: Vec<Attractor> = bodies
+        .iter()
+        .filter(|(_, body)| body.gravitational_parameter > 0.0)
+        .map(|(pos, body)| Attractor {
+            index: None,
+            position: pos.translation,
+            gravitational_parameter: body.gravitational_parameter,
+        })
+        .collect();
+
+    for mut population in populations.iter_mut() {
+        let population = &mut *population;
+
+        step_particles(&mut population.positions, &mut population.velocities, &attractors, dv);
+    }
+}
+
+fn update_particle_instances(
+    mut populations: Query<(&ParticlePopulation, &mut InstanceMaterialData)>,
+) {
+    for (population, mut instances) in populations.iter_mut() {
+        for (instance, position) in instances.iter_mut().zip(population.positions.iter()) {
+            instance.position = *position;
+        }
+    }
+}
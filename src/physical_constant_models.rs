@@ -1,7 +1,68 @@
+use clap::ValueEnum;
 use serde_derive::{Deserialize, Serialize};
 
+use crate::physics::*;
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct PhysicalConstantsModel {
     pub gravitational_constant: f32,
-    pub dv: f32
-}
\ No newline at end of file
+    pub dv: f32,
+    /// Length of the simulation time unit in seconds, used when exchanging data with other tools
+    #[serde(default = "default_seconds_per_time_unit")]
+    pub seconds_per_time_unit: f64,
+    #[serde(default)]
+    pub integrator: Integrator,
+}
+
+/// How `move_planets` advances the planets by one step.
+/// Written as a string with the same name as on the command line, so it reads the same in every
+/// scenario format.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+#[serde(into = "String", try_from = "String")]
+pub enum Integrator {
+    /// Velocity first, then position, with one gravity evaluation per step
+    #[default]
+    SemiImplicitEuler,
+    /// Kick-drift-kick leapfrog, keeps the energy of long orbits from drifting
+    Leapfrog,
+}
+
+impl From<Integrator> for String {
+    fn from(integrator: Integrator) -> Self {
+        integrator.to_possible_value()
+            .map(|value| value.get_name().to_string())
+            .unwrap_or_default()
+    }
+}
+
+impl TryFrom<String> for Integrator {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        Integrator::from_str(&name, false)
+    }
+}
+
+impl From<Integrator> for IntegrationMethod {
+    fn from(integrator: Integrator) -> Self {
+        match integrator {
+            Integrator::SemiImplicitEuler => IntegrationMethod::SemiImplicitEuler,
+            Integrator::Leapfrog => IntegrationMethod::Leapfrog,
+        }
+    }
+}
+
+fn default_seconds_per_time_unit() -> f64 {
+    115.7427
+}
+
+impl Default for PhysicalConstantsModel {
+    fn default() -> Self {
+        PhysicalConstantsModel {
+            gravitational_constant: 0.8940838263E-21,
+            dv: 1.0,
+            seconds_per_time_unit: default_seconds_per_time_unit(),
+            integrator: Integrator::default(),
+        }
+    }
+}
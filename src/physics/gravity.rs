@@ -0,0 +1,84 @@
+use glam::Vec3;
+
+/// A body as the physics sees it. Distances are in Mm and times in time units.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Body {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    /// Gravitational acceleration at the end of the last step
+    pub acceleration: Vec3,
+    /// Massless bodies feel gravity without pulling on anything
+    pub gravitational_parameter: f32,
+    /// Pulls on the other bodies but is not moved by the integrator, like the star at the origin
+    /// or a body following an ephemeris
+    pub fixed: bool,
+}
+
+impl Body {
+    pub fn new(position: Vec3, velocity: Vec3, gravitational_parameter: f32) -> Self {
+        Body {
+            position,
+            velocity,
+            gravitational_parameter,
+            ..Default::default()
+        }
+    }
+}
+
+/// Position and gravitational parameter of a body that pulls on the others.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Attractor {
+    /// Index of the body in the system, so it does not pull on itself
+    pub index: Option<usize>,
+    pub position: Vec3,
+    pub gravitational_parameter: f32,
+}
+
+/// The bodies with a gravitational parameter, the only ones that pull on the others.
+pub fn attractors(bodies: &[Body]) -> Vec<Attractor> {
+    bodies.iter()
+        .enumerate()
+        .filter(|(_, body)| body.gravitational_parameter > 0.0)
+        .map(|(index, body)| Attractor {
+            index: Some(index),
+            position: body.position,
+            gravitational_parameter: body.gravitational_parameter,
+        })
+        .collect()
+}
+
+/// Acceleration at `position` from every attractor but the body at index `skip`.
+pub fn gravity_acceleration(position: Vec3, attractors: &[Attractor], skip: Option<usize>) -> Vec3 {
+    let mut acc = Vec3::ZERO;
+
+    for attractor in attractors.iter() {
+        if skip.is_some() && attractor.index == skip {
+            continue;
+        }
+
+        let r_vector = attractor.position - position;
+        let distance = r_vector.length();
+
+        acc += (attractor.gravitational_parameter * r_vector) / (distance * distance * distance);
+    }
+
+    acc
+}
+
+/// Total energy of the bodies divided by G, their masses being their gravitational parameters.
+/// Only changes through integration errors when nothing is fixed except at the origin.
+pub fn total_energy(bodies: &[Body]) -> f64 {
+    let kinetic: f64 = bodies.iter()
+        .map(|body| 0.5 * body.gravitational_parameter as f64 * body.velocity.length_squared() as f64)
+        .sum();
+
+    let mut potential = 0.0;
+    for (index, first) in bodies.iter().enumerate() {
+        for second in bodies.iter().skip(index + 1) {
+            let distance = (first.position - second.position).length() as f64;
+            potential -= first.gravitational_parameter as f64 * second.gravitational_parameter as f64 / distance;
+        }
+    }
+
+    kinetic + potential
+}
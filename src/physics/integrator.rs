@@ -0,0 +1,174 @@
+use crate::physics::gravity::*;
+
+/// How the bodies are advanced by one step.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IntegrationMethod {
+    /// Velocity first, then position, with one gravity evaluation per step
+    #[default]
+    SemiImplicitEuler,
+    /// Kick-drift-kick leapfrog, keeps the energy of long orbits from drifting
+    Leapfrog,
+}
+
+/// Advances every body that is not fixed by `dv` time units, all of them pulling on each other.
+pub fn step(bodies: &mut [Body], method: IntegrationMethod, dv: f32) {
+    match method {
+        IntegrationMethod::SemiImplicitEuler => {
+            let attractors = attractors(bodies);
+
+            for (index, body) in bodies.iter_mut().enumerate().filter(|(_, body)| !body.fixed) {
+                let acc = gravity_acceleration(body.position, &attractors, Some(index));
+
+                body.acceleration = acc;
+                body.velocity += acc * dv;
+                body.position += body.velocity * dv;
+            }
+        }
+        IntegrationMethod::Leapfrog => {
+            // Half a kick and a full drift with the gravity at the start of the step...
+            let attractors_before = attractors(bodies);
+
+            for (index, body) in bodies.iter_mut().enumerate().filter(|(_, body)| !body.fixed) {
+                let acc = gravity_acceleration(body.position, &attractors_before, Some(index));
+
+                body.velocity += acc * (0.5 * dv);
+                body.position += body.velocity * dv;
+            }
+
+            // ...then the other half kick with the gravity where every body ended up
+            let attractors_after = attractors(bodies);
+
+            for (index, body) in bodies.iter_mut().enumerate().filter(|(_, body)| !body.fixed) {
+                let acc = gravity_acceleration(body.position, &attractors_after, Some(index));
+
+                body.acceleration = acc;
+                body.velocity += acc * (0.5 * dv);
+            }
+        }
+    }
+}
+
+/// Advances massless particles by `dv` time units, pulled by the attractors but not by each other.
+pub fn step_particles(positions: &mut [glam::Vec3], velocities: &mut [glam::Vec3], attractors: &[Attractor], dv: f32) {
+    for (position, velocity) in positions.iter_mut().zip(velocities.iter_mut()) {
+        let acc = gravity_acceleration(*position, attractors, None);
+
+        *velocity += acc * dv;
+        *position += *velocity * dv;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+    use crate::physics::orbit::*;
+
+    // Kerbol and Kerbin in Mm and time units of 115.7427 s, as in the default scenario
+    const KERBOL_MU: f32 = 15_704.993;
+    const KERBIN_ORBIT: f32 = 13_599.84;
+
+    // Integrates the bodies for `duration` in `steps` equal steps
+    fn integrate(bodies: &mut [Body], method: IntegrationMethod, duration: f32, steps: u32) {
+        let dv = duration / steps as f32;
+        for _ in 0..steps {
+            step(bodies, method, dv);
+        }
+    }
+
+    fn fixed(position: Vec3, gravitational_parameter: f32) -> Body {
+        Body { fixed: true, ..Body::new(position, Vec3::ZERO, gravitational_parameter) }
+    }
+
+    fn circular_orbit(mu: f32, radius: f32) -> Vec<Body> {
+        vec![
+            fixed(Vec3::ZERO, mu),
+            Body::new(Vec3::new(radius, 0.0, 0.0), Vec3::new(0.0, 0.0, -(mu / radius).sqrt()), 0.0),
+        ]
+    }
+
+    #[test]
+    fn circular_orbit_closes_after_one_period() {
+        let period = orbital_period(KERBOL_MU, KERBIN_ORBIT);
+
+        for (method, tolerance) in [(IntegrationMethod::Leapfrog, 1e-4), (IntegrationMethod::SemiImplicitEuler, 1e-2)] {
+            let mut bodies = circular_orbit(KERBOL_MU, KERBIN_ORBIT);
+            let start = bodies[1];
+
+            integrate(&mut bodies, method, period, 4000);
+
+            let error = (bodies[1].position - start.position).length() / KERBIN_ORBIT;
+            assert!(error < tolerance, "{method:?} ended {error} orbit radii away from the start");
+            assert!((bodies[1].position.length() - KERBIN_ORBIT).abs() / KERBIN_ORBIT < tolerance);
+        }
+    }
+
+    #[test]
+    fn eccentric_orbit_keeps_its_shape_and_period() {
+        let elements = OrbitalElements {
+            semi_major_axis: 20_000.0,
+            eccentricity: 0.4,
+            inclination: 0.2,
+            ..Default::default()
+        };
+        let (position, velocity) = state_from_elements(KERBOL_MU, &elements);
+        let mut bodies = vec![fixed(Vec3::ZERO, KERBOL_MU), Body::new(position, velocity, 0.0)];
+
+        integrate(&mut bodies, IntegrationMethod::Leapfrog, orbital_period(KERBOL_MU, 20_000.0), 20_000);
+
+        let after = elements_from_state(KERBOL_MU, bodies[1].position, bodies[1].velocity);
+        assert!((after.semi_major_axis - 20_000.0).abs() < 20.0, "semi-major axis {}", after.semi_major_axis);
+        assert!((after.eccentricity - 0.4).abs() < 1e-3, "eccentricity {}", after.eccentricity);
+        assert!((bodies[1].position - position).length() / 20_000.0 < 1e-3);
+    }
+
+    #[test]
+    fn binary_orbits_its_barycenter_with_the_summed_gravitational_parameter() {
+        // Two equal bodies on a circular orbit around their barycenter, 2 apart
+        let mu: f32 = 1.0;
+        let separation: f32 = 2.0;
+        let speed = (mu / (2.0 * separation)).sqrt();
+        let mut bodies = vec![
+            Body::new(Vec3::new(-1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, speed), mu),
+            Body::new(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -speed), mu),
+        ];
+        let energy = total_energy(&bodies);
+
+        // The relative orbit is a Kepler orbit around mu1 + mu2
+        let period = orbital_period(2.0 * mu, separation);
+        integrate(&mut bodies, IntegrationMethod::Leapfrog, period, 10_000);
+
+        assert!((bodies[0].position - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-3);
+        assert!((bodies[1].position - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-3);
+        assert!((bodies[0].position + bodies[1].position).length() < 1e-4, "the barycenter moved");
+        assert!(((total_energy(&bodies) - energy) / energy).abs() < 1e-5);
+    }
+
+    #[test]
+    fn fixed_bodies_do_not_move() {
+        let mut bodies = circular_orbit(KERBOL_MU, KERBIN_ORBIT);
+        bodies[1].gravitational_parameter = 0.047_3;
+
+        integrate(&mut bodies, IntegrationMethod::SemiImplicitEuler, 100.0, 100);
+
+        assert_eq!(bodies[0].position, Vec3::ZERO);
+        assert_eq!(bodies[0].velocity, Vec3::ZERO);
+    }
+
+    #[test]
+    fn particles_follow_the_same_orbits_as_massless_bodies() {
+        let bodies = circular_orbit(KERBOL_MU, KERBIN_ORBIT);
+        let mut positions = vec![bodies[1].position];
+        let mut velocities = vec![bodies[1].velocity];
+        let mut reference = bodies.clone();
+
+        for _ in 0..100 {
+            step_particles(&mut positions, &mut velocities, &attractors(&bodies), 10.0);
+            step(&mut reference, IntegrationMethod::SemiImplicitEuler, 10.0);
+        }
+
+        assert_eq!(positions[0], reference[1].position);
+        assert_eq!(velocities[0], reference[1].velocity);
+    }
+}
@@ -0,0 +1,10 @@
+// Gravity, integration and orbit maths on plain data, without anything from Bevy, so they can be
+// tested and reused on their own. The Bevy systems copy the bodies in, step them and copy them back.
+
+mod gravity;
+mod integrator;
+mod orbit;
+
+pub use gravity::*;
+pub use integrator::*;
+pub use orbit::*;
@@ -0,0 +1,165 @@
+use glam::{Quat, Vec3};
+
+/// Classical Keplerian elements of an orbit. Angles are in radians.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OrbitalElements {
+    pub semi_major_axis: f32,
+    pub eccentricity: f32,
+    pub inclination: f32,
+    pub longitude_of_ascending_node: f32,
+    pub argument_of_periapsis: f32,
+    pub mean_anomaly: f32,
+}
+
+/// The simulation uses the XZ plane as the reference plane with +Y pointing north,
+/// while ecliptic coordinates use XY with +Z pointing north.
+pub fn ecliptic_to_world(v: Vec3) -> Vec3 {
+    Vec3::new(v.x, v.z, -v.y)
+}
+
+pub fn world_to_ecliptic(v: Vec3) -> Vec3 {
+    Vec3::new(v.x, -v.z, v.y)
+}
+
+/// Solves Kepler's equation `M = E - e sin E` for the eccentric anomaly with Newton's method.
+pub fn eccentric_anomaly(mean_anomaly: f32, eccentricity: f32) -> f32 {
+    let mut anomaly = if eccentricity > 0.8 { std::f32::consts::PI } else { mean_anomaly };
+
+    for _ in 0..50 {
+        let delta = (anomaly - eccentricity * anomaly.sin() - mean_anomaly) / (1.0 - eccentricity * anomaly.cos());
+        anomaly -= delta;
+
+        if delta.abs() < 1e-7 {
+            break;
+        }
+    }
+
+    anomaly
+}
+
+/// Position and velocity, relative to the central body of gravitational parameter `mu`,
+/// of a body on the given elliptic orbit, in world coordinates.
+pub fn state_from_elements(mu: f32, elements: &OrbitalElements) -> (Vec3, Vec3) {
+    let a = elements.semi_major_axis;
+    let e = elements.eccentricity;
+
+    let anomaly = eccentric_anomaly(elements.mean_anomaly, e);
+    let true_anomaly = 2.0 * ((1.0 + e).sqrt() * (anomaly / 2.0).sin()).atan2((1.0 - e).sqrt() * (anomaly / 2.0).cos());
+    let distance = a * (1.0 - e * anomaly.cos());
+    let semi_latus_rectum = a * (1.0 - e * e);
+
+    // Position and velocity in the plane of the orbit, with X pointing to the periapsis
+    let position = Vec3::new(distance * true_anomaly.cos(), distance * true_anomaly.sin(), 0.0);
+    let speed = (mu / semi_latus_rectum).sqrt();
+    let velocity = Vec3::new(-speed * true_anomaly.sin(), speed * (e + true_anomaly.cos()), 0.0);
+
+    let rotation = Quat::from_rotation_z(elements.longitude_of_ascending_node)
+        * Quat::from_rotation_x(elements.inclination)
+        * Quat::from_rotation_z(elements.argument_of_periapsis);
+
+    (
+        ecliptic_to_world(rotation * position),
+        ecliptic_to_world(rotation * velocity),
+    )
+}
+
+/// Elements of the elliptic orbit followed by a body with the given position and velocity,
+/// in world coordinates, relative to a central body of gravitational parameter `mu`.
+pub fn elements_from_state(mu: f32, position: Vec3, velocity: Vec3) -> OrbitalElements {
+    let r = world_to_ecliptic(position);
+    let v = world_to_ecliptic(velocity);
+    let distance = r.length();
+
+    let angular_momentum = r.cross(v);
+    let eccentricity_vector = ((v.length_squared() - mu / distance) * r - r.dot(v) * v) / mu;
+    let eccentricity = eccentricity_vector.length();
+    let semi_major_axis = 1.0 / (2.0 / distance - v.length_squared() / mu);
+
+    let inclination = (angular_momentum.z / angular_momentum.length()).clamp(-1.0, 1.0).acos();
+    // The ascending node is undefined for equatorial orbits, take the X axis then
+    let node = Vec3::Z.cross(angular_momentum);
+    let longitude_of_ascending_node = if node.length() > 1e-6 * angular_momentum.length() {
+        node.y.atan2(node.x)
+    } else {
+        0.0
+    };
+
+    // Bring the vectors into the plane of the orbit, with X pointing to the ascending node
+    let to_plane = Quat::from_rotation_x(-inclination) * Quat::from_rotation_z(-longitude_of_ascending_node);
+    let planar_eccentricity = to_plane * eccentricity_vector;
+    let planar_position = to_plane * r;
+
+    // The periapsis is undefined for circular orbits, take the ascending node then
+    let argument_of_periapsis = if eccentricity > 1e-6 {
+        planar_eccentricity.y.atan2(planar_eccentricity.x)
+    } else {
+        0.0
+    };
+    let true_anomaly = planar_position.y.atan2(planar_position.x) - argument_of_periapsis;
+    let anomaly = ((1.0 - eccentricity * eccentricity).sqrt() * true_anomaly.sin()).atan2(eccentricity + true_anomaly.cos());
+
+    OrbitalElements {
+        semi_major_axis,
+        eccentricity,
+        inclination,
+        longitude_of_ascending_node,
+        argument_of_periapsis,
+        mean_anomaly: (anomaly - eccentricity * anomaly.sin()).rem_euclid(std::f32::consts::TAU),
+    }
+}
+
+/// Time to go once around an elliptic orbit of the given semi-major axis, in the time unit of `mu`.
+pub fn orbital_period(mu: f32, semi_major_axis: f32) -> f32 {
+    std::f32::consts::TAU * (semi_major_axis.powi(3) / mu).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!((actual - expected).abs() <= tolerance, "{actual} is not within {tolerance} of {expected}");
+    }
+
+    #[test]
+    fn kerbin_period_matches_ksp() {
+        // Kerbol's gravitational parameter in Mm³/s², Kerbin's semi-major axis in Mm
+        let period = orbital_period(1.1723328, 13_599.84);
+        assert_close(period, 9_203_545.0, 10.0);
+    }
+
+    #[test]
+    fn elements_survive_a_round_trip_through_the_state() {
+        let elements = OrbitalElements {
+            semi_major_axis: 5_000.0,
+            eccentricity: 0.3,
+            inclination: 0.4,
+            longitude_of_ascending_node: 1.2,
+            argument_of_periapsis: 2.0,
+            mean_anomaly: 0.7,
+        };
+
+        let (position, velocity) = state_from_elements(15_704.993, &elements);
+        let back = elements_from_state(15_704.993, position, velocity);
+
+        assert_close(back.semi_major_axis, elements.semi_major_axis, 0.5);
+        assert_close(back.eccentricity, elements.eccentricity, 1e-4);
+        assert_close(back.inclination, elements.inclination, 1e-4);
+        assert_close(back.longitude_of_ascending_node, elements.longitude_of_ascending_node, 1e-4);
+        assert_close(back.argument_of_periapsis, elements.argument_of_periapsis, 1e-3);
+        assert_close(back.mean_anomaly, elements.mean_anomaly, 1e-3);
+    }
+
+    #[test]
+    fn periapsis_state_of_an_ellipse() {
+        let mu = 10.0;
+        let elements = OrbitalElements { semi_major_axis: 100.0, eccentricity: 0.5, ..Default::default() };
+
+        let (position, velocity) = state_from_elements(mu, &elements);
+
+        // Vis-viva at the periapsis, r = a (1 - e)
+        assert_close(position.length(), 50.0, 1e-3);
+        assert_close(velocity.length(), (mu * (2.0 / 50.0 - 1.0 / 100.0)).sqrt(), 1e-5);
+        assert_close(position.dot(velocity), 0.0, 1e-3);
+    }
+}
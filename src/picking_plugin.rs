@@ -0,0 +1,199 @@
+use crate::{camera_plugin::*, input_map::*, map_view_plugin::*, planet_components::*, labels::*};
+use bevy::prelude::*;
+
+/// Left click on a body to focus it, hovering it highlights it and shows its name.
+pub struct PickingPlugin;
+
+impl Plugin for PickingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HoveredBody>()
+            .add_startup_system(setup_hover_label)
+            .add_system(pick_bodies.before(SystemTypes::CameraLabel))
+            .add_system(highlight_hovered_body.after(pick_bodies))
+            .add_system(update_hover_label.after(pick_bodies));
+    }
+}
+
+/// Bodies smaller than this on screen are picked as if they were this big, in logical pixels.
+pub const MIN_PICK_RADIUS: f32 = 8.0;
+
+/// Body under the mouse cursor, if any.
+#[derive(Resource, Default, Debug)]
+pub struct HoveredBody(pub Option<Entity>);
+
+#[derive(Component)]
+struct HoverLabel;
+
+/// Origin and direction of the ray through the cursor, from the near plane into the scene.
+fn cursor_ray(
+    cursor: Vec2,
+    window_size: Vec2,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+) -> Option<(Vec3, Vec3)> {
+    let ndc = (cursor / window_size) * 2.0 - Vec2::ONE;
+    let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix().inverse();
+
+    // The projection is reversed and infinite, the near plane is at depth 1
+    let near = ndc_to_world.project_point3(ndc.extend(1.0));
+    let far = ndc_to_world.project_point3(ndc.extend(f32::EPSILON));
+    let direction = (far - near).try_normalize()?;
+
+    Some((near, direction))
+}
+
+/// Distance along the ray to the first intersection with the sphere, if the ray hits it.
+fn ray_sphere_intersection(origin: Vec3, direction: Vec3, center: Vec3, radius: f32) -> Option<f32> {
+    let to_center = center - origin;
+    let along = to_center.dot(direction);
+    let miss_squared = to_center.length_squared() - along * along;
+
+    if miss_squared > radius * radius {
+        return None;
+    }
+
+    let half_chord = (radius * radius - miss_squared).sqrt();
+    let distance = if along - half_chord >= 0.0 { along - half_chord } else { along + half_chord };
+
+    (distance >= 0.0).then_some(distance)
+}
+
+fn pick_bodies(
+    windows: Res<Windows>,
+    actions: Res<ActionState>,
+    cameras: Query<(&Camera, &GlobalTransform, &Projection), Or<(With<PanOrbitCamera>, With<MapCamera>)>>,
+    mut bodies: Query<(Entity, &GlobalTransform, &CelestialBody, &mut FocusableEntity)>,
+    mut hovered: ResMut<HoveredBody>,
+) {
+    let Some(window) = windows.get_primary() else {
+        return;
+    };
+    let window_size = Vec2::new(window.width(), window.height());
+
+    // Whichever of the 3D and the map camera is showing
+    let camera = cameras.iter().find(|(camera, _, _)| camera.is_active);
+    let ray = window.cursor_position().zip(camera).and_then(|(cursor, (camera, transform, projection))| {
+        cursor_ray(cursor, window_size, camera, transform).map(|ray| (ray, projection))
+    });
+
+    let picked = ray.and_then(|((origin, direction), projection)| {
+        // Size of a logical pixel at a distance of one, to keep far bodies big enough to click
+        let pixel_size = match projection {
+            Projection::Perspective(perspective) => 2.0 * (perspective.fov / 2.0).tan() / window_size.y,
+            Projection::Orthographic(orthographic) => orthographic.scale,
+        };
+
+        bodies.iter()
+            .filter_map(|(entity, transform, body, _)| {
+                let center = transform.translation();
+                let distance = match projection {
+                    Projection::Perspective(_) => (center - origin).length(),
+                    Projection::Orthographic(_) => 1.0,
+                };
+                let radius = body.radius.max(MIN_PICK_RADIUS * pixel_size * distance);
+
+                ray_sphere_intersection(origin, direction, center, radius).map(|hit| (entity, hit))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(entity, _)| entity)
+    });
+
+    if hovered.0 != picked {
+        hovered.0 = picked;
+    }
+
+    let Some(picked) = picked else {
+        return;
+    };
+
+    if actions.just_pressed(Action::Pick) {
+        for (entity, _, _, mut focus) in bodies.iter_mut() {
+            let is_focused = entity == picked;
+            if focus.is_focused != is_focused {
+                focus.is_focused = is_focused;
+            }
+        }
+    }
+}
+
+/// Makes the hovered body glow, stars have their material on a child.
+fn highlight_hovered_body(
+    hovered: Res<HoveredBody>,
+    children: Query<&Children>,
+    handles: Query<&Handle<StandardMaterial>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut highlighted: Local<Option<(Handle<StandardMaterial>, Color)>>,
+) {
+    if !hovered.is_changed() {
+        return;
+    }
+
+    // Give the previous body its own emissive color back
+    if let Some((handle, emissive)) = highlighted.take() {
+        if let Some(material) = materials.get_mut(&handle) {
+            material.emissive = emissive;
+        }
+    }
+
+    let Some(entity) = hovered.0 else {
+        return;
+    };
+
+    let handle = handles.get(entity).ok().or_else(|| {
+        children.get(entity).ok()
+            .and_then(|children| children.iter().find_map(|child| handles.get(*child).ok()))
+    });
+
+    if let Some(handle) = handle {
+        if let Some(material) = materials.get_mut(handle) {
+            *highlighted = Some((handle.clone(), material.emissive));
+            material.emissive = material.emissive + Color::rgb(0.15, 0.15, 0.15);
+        }
+    }
+}
+
+fn setup_hover_label(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/LcdRoundedRegular.ttf");
+
+    commands.spawn((
+        TextBundle::from_section("", TextStyle {
+            font,
+            font_size: 18.0,
+            color: Color::WHITE,
+        })
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            ..default()
+        }),
+        HoverLabel,
+    ));
+}
+
+/// Writes the name of the hovered body next to the cursor.
+fn update_hover_label(
+    windows: Res<Windows>,
+    hovered: Res<HoveredBody>,
+    bodies: Query<&CelestialBody>,
+    mut labels: Query<(&mut Text, &mut Style, &mut Visibility), With<HoverLabel>>,
+) {
+    let cursor = windows.get_primary().and_then(|window| window.cursor_position());
+    let name = hovered.0.and_then(|entity| bodies.get(entity).ok()).map(|body| body.name.clone());
+
+    for (mut text, mut style, mut visibility) in labels.iter_mut() {
+        match (cursor, &name) {
+            (Some(cursor), Some(name)) => {
+                visibility.is_visible = true;
+                style.position.left = Val::Px(cursor.x + 12.0);
+                style.position.bottom = Val::Px(cursor.y + 12.0);
+                if text.sections[0].value != *name {
+                    text.sections[0].value = name.clone();
+                }
+            }
+            _ => {
+                if visibility.is_visible {
+                    visibility.is_visible = false;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,440 @@
+use crate::{camera_plugin::*, focus_plugin::*, input_map::*, planet_components::*, planet_models::*, labels::*};
+use bevy::{
+    input::mouse::MouseMotion,
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        render_resource::{Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages},
+    },
+};
+
+/// A second camera with a focus of its own, shown in an inset over the main view. I shows or
+/// hides it, O pins it to the focused body, [ and ] go through the bodies and T switches between
+/// orbiting the body and looking down on it. With the mouse over the inset, right drag orbits,
+/// the wheel zooms, left drag moves the inset and dragging its bottom right corner resizes it.
+pub struct PictureInPicturePlugin;
+
+impl Plugin for PictureInPicturePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PictureInPicture>()
+            .add_startup_system(spawn_picture_in_picture)
+            .add_system_to_stage(CoreStage::PreUpdate, drag_picture_in_picture.after(SystemTypes::InputMapLabel))
+            .add_system(change_picture_in_picture_focus.before(SystemTypes::CameraLabel))
+            .add_system(follow_picture_in_picture_focus
+                .label(SystemTypes::CameraLabel)
+                .after(change_picture_in_picture_focus))
+            .add_system(update_picture_in_picture_inset.after(follow_picture_in_picture_focus));
+    }
+}
+
+/// The inset is never made smaller than this, in logical pixels.
+pub const MIN_INSET_SIZE: Vec2 = Vec2::new(160.0, 90.0);
+
+/// Dragging this close to the bottom right corner resizes the inset, in logical pixels.
+const RESIZE_HANDLE_SIZE: f32 = 16.0;
+
+/// Width of the frame around the inset, in logical pixels.
+const INSET_BORDER: f32 = 2.0;
+
+/// Room between the inset and the corner of the window it starts in, in logical pixels.
+const INSET_MARGIN: f32 = 20.0;
+
+/// Where the inset is in the window.
+#[derive(Resource, Debug)]
+pub struct PictureInPicture {
+    pub visible: bool,
+    /// Top left corner of the inset, from the top left of the window, in logical pixels
+    pub position: Vec2,
+    pub size: Vec2,
+    // What the mouse does with the inset since a button went down over it
+    drag: Option<InsetDrag>,
+    // The inset starts in the bottom right corner, once the size of the window is known
+    placed: bool,
+}
+
+impl Default for PictureInPicture {
+    fn default() -> Self {
+        PictureInPicture {
+            visible: false,
+            position: Vec2::ZERO,
+            size: Vec2::new(384.0, 216.0),
+            drag: None,
+            placed: false,
+        }
+    }
+}
+
+impl PictureInPicture {
+    /// Whether a point given from the top left of the window is on the inset.
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.visible && point.cmpge(self.position).all() && point.cmple(self.position + self.size).all()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum InsetDrag {
+    Orbit,
+    /// Cursor from the top left corner of the inset
+    Move { grab: Vec2 },
+    /// Cursor from the bottom right corner of the inset
+    Resize { grab: Vec2 },
+}
+
+/// The camera of the inset. It renders into an image shown by the interface.
+#[derive(Component, Debug)]
+pub struct InsetCamera {
+    /// Body the camera looks at, the first star until another one is chosen
+    pub focus: Option<Entity>,
+    /// Distance from the body, chosen from its size when zero
+    pub radius: f32,
+    /// Looks straight down on the ecliptic instead of orbiting the body
+    pub top_down: bool,
+    image: Handle<Image>,
+}
+
+#[derive(Component)]
+struct InsetFrame;
+
+#[derive(Component)]
+struct InsetLabel;
+
+fn spawn_picture_in_picture(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    inset: Res<PictureInPicture>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    // Resized to the inset as soon as it is shown
+    let size = Extent3d {
+        width: inset.size.x as u32,
+        height: inset.size.y as u32,
+        ..default()
+    };
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: None,
+            size,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Bgra8UnormSrgb,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST | TextureUsages::RENDER_ATTACHMENT,
+        },
+        ..default()
+    };
+    image.resize(size);
+    let image = images.add(image);
+
+    commands.spawn((
+        Camera3dBundle {
+            camera: Camera {
+                is_active: false,
+                priority: -1,
+                target: RenderTarget::Image(image.clone()),
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 1.0, 0.0).looking_at(Vec3::ZERO, Vec3::NEG_Z),
+            ..default()
+        },
+        // The interface is only drawn over the main view
+        UiCameraConfig { show_ui: false },
+        InsetCamera {
+            focus: None,
+            radius: 0.0,
+            top_down: true,
+            image: image.clone(),
+        },
+    ));
+
+    let font = asset_server.load("fonts/LcdRoundedRegular.ttf");
+
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                padding: UiRect::all(Val::Px(INSET_BORDER)),
+                ..default()
+            },
+            background_color: Color::GRAY.into(),
+            visibility: Visibility { is_visible: false },
+            ..default()
+        },
+        InsetFrame,
+    ))
+    .with_children(|frame| {
+        frame.spawn(ImageBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                ..default()
+            },
+            image: UiImage(image),
+            ..default()
+        });
+        frame.spawn((
+            TextBundle::from_section("", TextStyle {
+                font,
+                font_size: 18.0,
+                color: Color::WHITE,
+            })
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(6.0),
+                    top: Val::Px(4.0),
+                    ..default()
+                },
+                ..default()
+            }),
+            InsetLabel,
+        ));
+    });
+}
+
+/// The mouse over the inset works the inset, the main view does not see it. Drags started on the
+/// inset go on wherever the cursor goes.
+fn drag_picture_in_picture(
+    windows: Res<Windows>,
+    mut actions: ResMut<ActionState>,
+    mut ev_motion: EventReader<MouseMotion>,
+    mut inset: ResMut<PictureInPicture>,
+    mut cameras: Query<(&mut InsetCamera, &mut Transform)>,
+) {
+    let motion: Vec2 = ev_motion.iter().map(|ev| ev.delta).sum();
+
+    let Some(window) = windows.get_primary() else {
+        return;
+    };
+    // The interface counts from the top of the window, the cursor from the bottom
+    let cursor = window.cursor_position().map(|cursor| Vec2::new(cursor.x, window.height() - cursor.y));
+    let hovered = cursor.filter(|cursor| inset.contains(*cursor));
+
+    if let (Some(cursor), None) = (hovered, inset.drag) {
+        let corner = inset.position + inset.size;
+
+        if actions.just_pressed(Action::Rotate) {
+            inset.drag = Some(InsetDrag::Orbit);
+        } else if actions.just_pressed(Action::Pick) {
+            inset.drag = Some(if (corner - cursor).max_element() <= RESIZE_HANDLE_SIZE {
+                InsetDrag::Resize { grab: corner - cursor }
+            } else {
+                InsetDrag::Move { grab: cursor - inset.position }
+            });
+        }
+    }
+
+    let drag = inset.drag;
+    match drag {
+        Some(InsetDrag::Orbit) if actions.pressed(Action::Rotate) => {
+            // Same feel as the main view, half a turn for a drag across the inset
+            let yaw = Quat::from_rotation_y(-motion.x / inset.size.x * std::f32::consts::PI);
+            let pitch = Quat::from_rotation_x(-motion.y / inset.size.y * std::f32::consts::PI);
+
+            for (mut camera, mut transform) in cameras.iter_mut() {
+                camera.top_down = false;
+                transform.rotation = yaw * transform.rotation * pitch;
+            }
+            actions.consume(Action::Rotate);
+        }
+        Some(InsetDrag::Move { grab }) if actions.pressed(Action::Pick) => {
+            if let Some(cursor) = cursor {
+                inset.position = cursor - grab;
+            }
+            actions.consume(Action::Pick);
+        }
+        Some(InsetDrag::Resize { grab }) if actions.pressed(Action::Pick) => {
+            if let Some(cursor) = cursor {
+                inset.size = (cursor + grab - inset.position).max(MIN_INSET_SIZE);
+            }
+            actions.consume(Action::Pick);
+        }
+        Some(_) => inset.drag = None,
+        None => {}
+    }
+
+    if hovered.is_none() {
+        return;
+    }
+
+    let scroll = actions.value(Action::Zoom);
+    if scroll != 0.0 {
+        for (mut camera, _) in cameras.iter_mut() {
+            camera.radius -= scroll * camera.radius * 0.15;
+        }
+    }
+
+    // A drag of the main view going over the inset carries on, a click on it is not a pick
+    actions.consume(Action::Zoom);
+    actions.consume(Action::Pick);
+}
+
+/// Chooses the body of the inset, independently of the focus of the main view.
+fn change_picture_in_picture_focus(
+    actions: Res<ActionState>,
+    config: Res<SolarSystemConfiguration>,
+    mut inset: ResMut<PictureInPicture>,
+    mut cameras: Query<&mut InsetCamera>,
+    bodies: Query<(Entity, &CelestialBody, &FocusableEntity)>,
+) {
+    if actions.just_pressed(Action::PictureInPicture) {
+        inset.visible = !inset.visible;
+    }
+    if !inset.visible {
+        return;
+    }
+
+    let pin = actions.just_pressed(Action::PinPictureInPicture);
+    let next = actions.just_pressed(Action::PictureInPictureNext);
+    let previous = actions.just_pressed(Action::PictureInPicturePrevious);
+    let top_down = actions.just_pressed(Action::PictureInPictureTopDown);
+
+    if !pin && !next && !previous && !top_down {
+        return;
+    }
+
+    let order = focus_order(&config.solar_system, bodies.iter().map(|(entity, body, _)| (entity, body)));
+
+    for mut camera in cameras.iter_mut() {
+        let current = camera.focus.and_then(|focus| order.iter().position(|x| *x == focus));
+
+        let target = if pin {
+            bodies.iter().find(|(_, _, focus)| focus.is_focused).map(|(entity, _, _)| entity)
+        } else if (next || previous) && !order.is_empty() {
+            let index = match (current, previous) {
+                (Some(index), false) => (index + 1) % order.len(),
+                (Some(index), true) => (index + order.len() - 1) % order.len(),
+                (None, false) => 0,
+                (None, true) => order.len() - 1,
+            };
+            Some(order[index])
+        } else {
+            None
+        };
+
+        if let Some(target) = target {
+            camera.focus = Some(target);
+            camera.top_down = false;
+            camera.radius = 0.0;
+        }
+
+        if top_down {
+            camera.top_down = !camera.top_down;
+        }
+    }
+}
+
+/// Keeps the inset camera on its body, at a distance fitting the body or the whole system.
+fn follow_picture_in_picture_focus(
+    inset: Res<PictureInPicture>,
+    mut cameras: Query<(&mut InsetCamera, &mut Camera, &mut Transform)>,
+    bodies: Query<(Entity, &Transform, &CelestialBody, Option<&Star>), Without<InsetCamera>>,
+) {
+    for (mut inset_camera, mut camera, mut transform) in cameras.iter_mut() {
+        if camera.is_active != inset.visible {
+            camera.is_active = inset.visible;
+        }
+        if !inset.visible {
+            continue;
+        }
+
+        // Back to looking down on the star when the body is gone, after a reload
+        if inset_camera.focus.is_none_or(|focus| bodies.get(focus).is_err()) {
+            inset_camera.focus = bodies.iter().find(|(_, _, _, star)| star.is_some()).map(|(entity, _, _, _)| entity);
+            inset_camera.top_down = true;
+            inset_camera.radius = 0.0;
+        }
+
+        let Some((_, focus_transform, body, star)) = inset_camera.focus.and_then(|focus| bodies.get(focus).ok()) else {
+            continue;
+        };
+        let focus = focus_transform.translation;
+
+        if inset_camera.radius <= 0.0 {
+            inset_camera.radius = match (inset_camera.top_down, star) {
+                // The whole system from above its star
+                (true, Some(_)) => {
+                    let extent = bodies.iter()
+                        .map(|(_, transform, body, _)| transform.translation.distance(focus) + body.radius)
+                        .fold(0.0, f32::max);
+                    2.5 * extent
+                }
+                _ => body.radius * FOCUS_DISTANCE_IN_RADII,
+            };
+        }
+        inset_camera.radius = inset_camera.radius.max(body.radius + 0.2);
+
+        if inset_camera.top_down {
+            // North up the inset like on the map
+            transform.rotation = Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2);
+        }
+        transform.translation = focus + transform.rotation * Vec3::new(0.0, 0.0, inset_camera.radius);
+    }
+}
+
+/// Places the frame of the inset, keeps it in the window and renders the camera at its size.
+fn update_picture_in_picture_inset(
+    windows: Res<Windows>,
+    mut inset: ResMut<PictureInPicture>,
+    mut images: ResMut<Assets<Image>>,
+    cameras: Query<&InsetCamera>,
+    bodies: Query<&CelestialBody>,
+    mut frames: Query<(&mut Style, &mut Visibility), With<InsetFrame>>,
+    mut labels: Query<&mut Text, With<InsetLabel>>,
+) {
+    let Some(window) = windows.get_primary() else {
+        return;
+    };
+    let window_size = Vec2::new(window.width(), window.height());
+
+    if !inset.placed {
+        inset.position = window_size - inset.size - INSET_MARGIN;
+        inset.placed = true;
+    }
+
+    // Back inside after the window shrank or the inset was dragged out of it
+    let size = inset.size.min(window_size).max(MIN_INSET_SIZE);
+    let position = inset.position.min(window_size - size).max(Vec2::ZERO);
+    if inset.size != size || inset.position != position {
+        inset.size = size;
+        inset.position = position;
+    }
+
+    if inset.is_changed() {
+        for (mut style, mut visibility) in frames.iter_mut() {
+            visibility.is_visible = inset.visible;
+            style.position.left = Val::Px(position.x);
+            style.position.top = Val::Px(position.y);
+            style.size = Size::new(Val::Px(size.x), Val::Px(size.y));
+        }
+    }
+
+    if !inset.visible {
+        return;
+    }
+
+    for camera in cameras.iter() {
+        // The image has as many pixels as the screen inside the frame
+        let physical_size = ((size - 2.0 * INSET_BORDER) * window.scale_factor() as f32).max(Vec2::ONE).as_uvec2();
+        let resize = images.get(&camera.image).is_some_and(|image| image.size() != physical_size.as_vec2());
+        if resize {
+            if let Some(image) = images.get_mut(&camera.image) {
+                image.resize(Extent3d {
+                    width: physical_size.x,
+                    height: physical_size.y,
+                    ..default()
+                });
+            }
+        }
+
+        let name = match camera.focus.and_then(|focus| bodies.get(focus).ok()) {
+            Some(body) if camera.top_down => format!("{} from above", body.name),
+            Some(body) => body.name.clone(),
+            None => String::new(),
+        };
+
+        for mut text in labels.iter_mut() {
+            if text.sections[0].value != name {
+                text.sections[0].value = name.clone();
+            }
+        }
+    }
+}
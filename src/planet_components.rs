@@ -55,3 +55,50 @@ impl Acceleration {
         }
     }
 }
+
+/// Position and velocity of a body at the start of the current physics step,
+/// used to reconstruct its path inside the step.
+#[derive(Component, Default, Debug)]
+pub struct PreviousState {
+    pub position: Vec3,
+    pub velocity: Vec3,
+}
+
+impl PreviousState {
+    /// Cubic Hermite interpolation of the position between the previous state
+    /// and the current one, with `s` going from 0 (previous) to 1 (current)
+    /// over a step of length `dv`.
+    pub fn interpolate(&self, position: Vec3, velocity: Vec3, dv: f32, s: f32) -> Vec3 {
+        let s2 = s * s;
+        let s3 = s2 * s;
+
+        let h00 = 2.0 * s3 - 3.0 * s2 + 1.0;
+        let h10 = s3 - 2.0 * s2 + s;
+        let h01 = -2.0 * s3 + 3.0 * s2;
+        let h11 = s3 - s2;
+
+        self.position * h00 + self.velocity * (h10 * dv) + position * h01 + velocity * (h11 * dv)
+    }
+
+    /// Derivative of [`PreviousState::interpolate`] with respect to time.
+    pub fn interpolate_velocity(&self, position: Vec3, velocity: Vec3, dv: f32, s: f32) -> Vec3 {
+        let s2 = s * s;
+
+        let d00 = 6.0 * s2 - 6.0 * s;
+        let d10 = 3.0 * s2 - 4.0 * s + 1.0;
+        let d01 = -6.0 * s2 + 6.0 * s;
+        let d11 = 3.0 * s2 - 2.0 * s;
+
+        (self.position * d00 + position * d01) / dv + self.velocity * d10 + velocity * d11
+    }
+}
+
+/// Drives a body along a list of states, e.g. read from an ephemeris file,
+/// instead of integrating its motion.
+#[derive(Component)]
+pub struct OemTrajectory {
+    /// Body the states are relative to
+    pub center: Option<Entity>,
+    /// Time in time units, position in Mm and velocity in Mm per time unit, sorted by time
+    pub states: Vec<(f64, Vec3, Vec3)>,
+}
@@ -1,7 +1,7 @@
 use bevy::prelude::*;
 use serde_derive::{Deserialize, Serialize};
 
-use crate::physical_constant_models::*;
+use crate::{calendar::*, horizons::*, kopernicus::*, physical_constant_models::*, scenario_validation::*, startup_options::*};
 
 #[derive(Resource, Debug)]
 pub struct SolarSystemConfiguration {
@@ -10,26 +10,40 @@ pub struct SolarSystemConfiguration {
 }
 
 impl FromWorld for SolarSystemConfiguration {
-    fn from_world(_: &mut World) -> Self {
-        // You have full access to anything in the ECS from here.
-        // For instance, you can mutate other resources:
-        let solar_input_path = "assets/planets/planets.json";
+    fn from_world(world: &mut World) -> Self {
+        let options = world.get_resource::<StartupOptions>().cloned().unwrap_or_default();
+        let mut problems = Vec::new();
 
-        let solar_system = {
-            let planets_json_string = std::fs::read_to_string(&solar_input_path).unwrap();
+        let mut physical_constants = load_constants(&options.constants_path, &mut problems).unwrap_or_default();
+        if let Some(dv) = options.dv {
+            physical_constants.dv = dv;
+        }
+        if let Some(integrator) = options.integrator {
+            physical_constants.integrator = integrator;
+        }
 
-            // Load the planet and star data from the string into the models
-            serde_json::from_str::<SolarSystemModel>(&planets_json_string).unwrap()
+        let solar_system = match (&options.horizons_path, &options.kopernicus_path) {
+            (Some(path), _) => match load_horizons_system(path, &physical_constants) {
+                Ok(solar_system) => check_solar_system(path, solar_system, &mut problems),
+                Err(err) => {
+                    problems.push(ScenarioIssue::file(path, err));
+                    None
+                }
+            },
+            (None, Some(path)) => match load_kopernicus_system(path, &physical_constants) {
+                Ok(solar_system) => check_solar_system(path, solar_system, &mut problems),
+                Err(err) => {
+                    problems.push(ScenarioIssue::file(path, err));
+                    None
+                }
+            },
+            (None, None) => load_scenario(&options.scenario_path, &mut problems),
         };
 
-        let constants_input_path = "assets/planets/physical_constants.json";
+        // Keep running with nothing in it, the problems are shown instead
+        let solar_system = solar_system.unwrap_or_default();
 
-        let physical_constants = {
-            let constants_json_string = std::fs::read_to_string(&constants_input_path).unwrap();
-
-            // Load the constants data from the string into the models
-            serde_json::from_str::<PhysicalConstantsModel>(&constants_json_string).unwrap()
-        };
+        world.insert_resource(ScenarioProblems(problems));
 
         SolarSystemConfiguration {
             solar_system,
@@ -73,19 +87,80 @@ pub struct StarModel {
 #[derive(Deserialize, Serialize, Debug)]
 pub struct PlanetModel {
     pub name: String,
+    /// Planet this body orbits as a moon, the star when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
     pub color_texture: String,
     pub normal_texture: String,
     pub radius: f32,
     pub mass: f32,
+    #[serde(default)]
+    pub gravitational_parameter: f32,
     pub sidereal_rotation_period: f32,
     pub inclination: f32,
     pub periapsis: f32,
     pub orbital_velocity_pe: f32,
     pub color: PlanetColor,
+    /// Position relative to the star in Mm, replaces the placement at periapsis when set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initial_position: Option<[f32; 3]>,
+    /// Velocity relative to the star in Mm per time unit, used with `initial_position`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initial_velocity: Option<[f32; 3]>,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Default)]
 pub struct SolarSystemModel {
     pub stars: Vec<StarModel>,
     pub planets: Vec<PlanetModel>,
+    #[serde(default)]
+    pub populations: Vec<PopulationModel>,
+    /// Date the simulation starts at, Year 1, Day 1 of the Kerbin calendar when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epoch: Option<EpochModel>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct EpochModel {
+    pub calendar: Calendar,
+    /// In the format of the calendar, e.g. `Y1, D1, 0:00:00` or `2000-01-01T12:00:00`,
+    /// the origin of the calendar when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+}
+
+/// A group of massless test particles generated from a distribution of orbital elements
+/// around the first star. Ranges are given as `[min, max]`, angles in degrees.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct PopulationModel {
+    pub name: String,
+    pub count: usize,
+    pub seed: u64,
+    pub display_radius: f32,
+    pub color: PlanetColor,
+    pub distribution: PopulationDistribution,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PopulationDistribution {
+    /// Orbits spread uniformly in semi-major axis, like an asteroid belt
+    Belt {
+        semi_major_axis: [f32; 2],
+        eccentricity: [f32; 2],
+        inclination: [f32; 2],
+    },
+    /// Orbits sharing the one of a planet, around its L4 and L5 points
+    Trojans {
+        planet: String,
+        libration_amplitude: f32,
+        eccentricity: [f32; 2],
+        inclination: [f32; 2],
+    },
+    /// Very eccentric orbits with periapses spread between two distances
+    ScatteredDisk {
+        periapsis: [f32; 2],
+        eccentricity: [f32; 2],
+        inclination: [f32; 2],
+    },
 }
@@ -0,0 +1,156 @@
+// Scenario and physical constants files can be written in JSON, RON, TOML or YAML.
+// Every format is read into a JSON value first, so the validation and the JSON paths
+// in its messages are the same whatever the file was written in.
+
+use std::{fmt, path::Path};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{physical_constant_models::*, planet_models::*, scenario_validation::*};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScenarioFormat {
+    Json,
+    Ron,
+    Toml,
+    Yaml,
+}
+
+impl fmt::Display for ScenarioFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScenarioFormat::Json => write!(f, "JSON"),
+            ScenarioFormat::Ron => write!(f, "RON"),
+            ScenarioFormat::Toml => write!(f, "TOML"),
+            ScenarioFormat::Yaml => write!(f, "YAML"),
+        }
+    }
+}
+
+impl ScenarioFormat {
+    pub const EXTENSIONS: [&'static str; 5] = ["json", "ron", "toml", "yaml", "yml"];
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "json" => Some(ScenarioFormat::Json),
+            "ron" => Some(ScenarioFormat::Ron),
+            "toml" => Some(ScenarioFormat::Toml),
+            "yaml" | "yml" => Some(ScenarioFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    /// Format of a file by its extension, files without a known one are read as JSON.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        path.as_ref().extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(ScenarioFormat::from_extension)
+            .unwrap_or(ScenarioFormat::Json)
+    }
+
+    pub fn parse(&self, text: &str) -> Result<Value, String> {
+        let value = match self {
+            ScenarioFormat::Json => serde_json::from_str(text).map_err(|err| err.to_string()),
+            // Nested structs only read as maps through RON's own value type
+            ScenarioFormat::Ron => ron::from_str::<ron::Value>(text)
+                .map_err(|err| err.to_string())
+                .and_then(|value| serde_json::to_value(value).map_err(|err| err.to_string())),
+            ScenarioFormat::Toml => toml::from_str(text).map_err(|err| err.to_string()),
+            ScenarioFormat::Yaml => serde_yaml::from_str(text).map_err(|err| err.to_string()),
+        };
+        value.map_err(|err| format!("invalid {self}: {err}"))
+    }
+
+    pub fn write<T: Serialize>(&self, model: &T) -> Result<String, String> {
+        let text = match self {
+            ScenarioFormat::Json => serde_json::to_string_pretty(model).map_err(|err| err.to_string()),
+            ScenarioFormat::Ron => {
+                let config = ron::ser::PrettyConfig::new().struct_names(false);
+                ron::ser::to_string_pretty(model, config).map_err(|err| err.to_string())
+            }
+            // Through the shortest JSON text of each number, so f32 values are not written
+            // with the digits of their f64 conversion
+            ScenarioFormat::Toml => serde_json::to_string(model)
+                .and_then(|json| serde_json::from_str::<toml::Value>(&json))
+                .map_err(|err| err.to_string())
+                .and_then(|value| toml::to_string_pretty(&value).map_err(|err| err.to_string())),
+            ScenarioFormat::Yaml => serde_yaml::to_string(model).map_err(|err| err.to_string()),
+        };
+        text.map_err(|err| format!("could not write {self}: {err}"))
+    }
+}
+
+/// Converts a scenario or physical constants file to the format of the output path's extension.
+/// The input is validated on the way, and every problem found is returned when it is not valid.
+pub fn convert_scenario_file(input: &str, output: &str) -> Result<Vec<ScenarioIssue>, Vec<ScenarioIssue>> {
+    let format = Path::new(output).extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(ScenarioFormat::from_extension)
+        .ok_or_else(|| vec![ScenarioIssue::file(output, "unknown extension, expected one of .json, .ron, .toml, .yaml or .yml")])?;
+
+    let mut issues = Vec::new();
+    let value = read_document(input, &mut issues).ok_or_else(|| issues.clone())?;
+
+    // Constants files are the only ones with a gravitational constant at the top level
+    let text = if value.get("gravitational_constant").is_some() {
+        let constants: Option<PhysicalConstantsModel> = check_constants(input, value, &mut issues);
+        constants.map(|constants| format.write(&constants))
+    } else {
+        let solar_system: Option<SolarSystemModel> = check_scenario(input, value, &mut issues);
+        solar_system.map(|solar_system| format.write(&solar_system))
+    };
+
+    match text {
+        Some(Ok(text)) => match std::fs::write(output, text) {
+            Ok(()) => Ok(issues),
+            Err(err) => {
+                issues.push(ScenarioIssue::file(output, format!("could not write the file: {err}")));
+                Err(issues)
+            }
+        },
+        Some(Err(message)) => {
+            issues.push(ScenarioIssue::file(output, message));
+            Err(issues)
+        }
+        None => Err(issues),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scenario_survives_a_round_trip_through_every_format() {
+        let directory = std::env::temp_dir().join(format!("scenario_formats_test_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let mut input = "assets/planets/planets.json".to_string();
+        for extension in ["ron", "toml", "yaml", "json"] {
+            let output = directory.join(format!("planets.{extension}")).to_str().unwrap().to_string();
+            let converted = convert_scenario_file(&input, &output);
+            assert!(converted.is_ok(), "{input} to {output}: {converted:?}");
+            input = output;
+        }
+
+        let mut issues = Vec::new();
+        let original = load_scenario("assets/planets/planets.json", &mut issues).unwrap();
+        let converted = load_scenario(&input, &mut issues);
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(serde_json::to_value(converted.unwrap()).unwrap(), serde_json::to_value(original).unwrap());
+    }
+
+    #[test]
+    fn constants_survive_a_round_trip_through_every_format() {
+        let original = PhysicalConstantsModel::default();
+
+        for format in [ScenarioFormat::Json, ScenarioFormat::Ron, ScenarioFormat::Toml, ScenarioFormat::Yaml] {
+            let value = format.parse(&format.write(&original).unwrap()).unwrap();
+            let constants: PhysicalConstantsModel = serde_json::from_value(value).unwrap();
+
+            assert_eq!(serde_json::to_value(constants).unwrap(), serde_json::to_value(&original).unwrap(), "{format}");
+        }
+    }
+}
@@ -0,0 +1,748 @@
+use std::{collections::HashSet, path::{Path, PathBuf}};
+
+use crate::{calendar::*, physical_constant_models::*, planet_models::*, scenario_formats::*};
+use bevy::{asset::FileAssetIo, prelude::*};
+use serde_json::{Map, Value};
+
+/// Lists the problems that kept the scenario from loading, in the log and on screen,
+/// instead of crashing on the first one.
+pub struct ScenarioValidationPlugin;
+
+impl Plugin for ScenarioValidationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ScenarioProblems>()
+            .add_system(show_scenario_problems);
+    }
+}
+
+#[derive(Component)]
+struct ScenarioProblemsScreen;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IssueSeverity {
+    /// The scenario still loads, e.g. a texture is missing
+    Warning,
+    /// The scenario cannot be loaded
+    Error,
+}
+
+/// A problem found in a scenario or constants file.
+#[derive(Clone, Debug)]
+pub struct ScenarioIssue {
+    pub severity: IssueSeverity,
+    pub file: String,
+    /// Location of the offending value, e.g. `$.planets[2].radius`
+    pub path: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for ScenarioIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}: {}", self.file, self.path, self.message)
+    }
+}
+
+impl ScenarioIssue {
+    /// An error with a whole file, e.g. one that could not be read.
+    pub fn file(file: &str, message: impl ToString) -> Self {
+        ScenarioIssue {
+            severity: IssueSeverity::Error,
+            file: file.to_string(),
+            path: "$".to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Problems found while loading the configuration. The simulation runs with an empty
+/// system as long as any of them is an error.
+#[derive(Resource, Default, Debug)]
+pub struct ScenarioProblems(pub Vec<ScenarioIssue>);
+
+impl ScenarioProblems {
+    pub fn has_errors(&self) -> bool {
+        has_errors(&self.0)
+    }
+}
+
+fn has_errors(issues: &[ScenarioIssue]) -> bool {
+    issues.iter().any(|issue| issue.severity == IssueSeverity::Error)
+}
+
+struct Validator<'a> {
+    file: &'a str,
+    issues: Vec<ScenarioIssue>,
+}
+
+impl<'a> Validator<'a> {
+    fn report(&mut self, path: &str, message: impl ToString) {
+        self.issues.push(ScenarioIssue {
+            severity: IssueSeverity::Error,
+            file: self.file.to_string(),
+            path: path.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    fn warn(&mut self, path: &str, message: impl ToString) {
+        self.issues.push(ScenarioIssue {
+            severity: IssueSeverity::Warning,
+            file: self.file.to_string(),
+            path: path.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    fn object<'v>(&mut self, value: &'v Value, path: &str) -> Option<&'v Map<String, Value>> {
+        let object = value.as_object();
+        if object.is_none() {
+            self.report(path, "expected an object");
+        }
+        object
+    }
+
+    fn array<'v>(&mut self, object: &'v Map<String, Value>, path: &str, key: &str) -> Option<&'v Vec<Value>> {
+        let path = format!("{path}.{key}");
+        match object.get(key) {
+            Some(Value::Array(array)) => Some(array),
+            Some(_) => {
+                self.report(&path, "expected a list");
+                None
+            }
+            None => {
+                self.report(&path, "missing field");
+                None
+            }
+        }
+    }
+
+    fn string<'v>(&mut self, object: &'v Map<String, Value>, path: &str, key: &str) -> Option<&'v str> {
+        let path = format!("{path}.{key}");
+        match object.get(key) {
+            Some(Value::String(string)) => Some(string),
+            Some(_) => {
+                self.report(&path, "expected a string");
+                None
+            }
+            None => {
+                self.report(&path, "missing field");
+                None
+            }
+        }
+    }
+
+    fn number(&mut self, object: &Map<String, Value>, path: &str, key: &str) -> Option<f64> {
+        let path = format!("{path}.{key}");
+        match object.get(key) {
+            Some(Value::Number(number)) => number.as_f64(),
+            Some(_) => {
+                self.report(&path, "expected a number");
+                None
+            }
+            None => {
+                self.report(&path, "missing field");
+                None
+            }
+        }
+    }
+
+    fn positive(&mut self, object: &Map<String, Value>, path: &str, key: &str) -> Option<f64> {
+        let number = self.number(object, path, key)?;
+        if number <= 0.0 {
+            self.report(&format!("{path}.{key}"), format!("must be positive, found {number}"));
+        }
+        Some(number)
+    }
+
+    fn non_negative(&mut self, object: &Map<String, Value>, path: &str, key: &str) -> Option<f64> {
+        let number = self.number(object, path, key)?;
+        if number < 0.0 {
+            self.report(&format!("{path}.{key}"), format!("must not be negative, found {number}"));
+        }
+        Some(number)
+    }
+
+    fn non_zero(&mut self, object: &Map<String, Value>, path: &str, key: &str) -> Option<f64> {
+        let number = self.number(object, path, key)?;
+        if number == 0.0 {
+            self.report(&format!("{path}.{key}"), "must not be zero");
+        }
+        Some(number)
+    }
+
+    fn integer(&mut self, object: &Map<String, Value>, path: &str, key: &str) -> Option<u64> {
+        let integer = object.get(key).and_then(Value::as_u64);
+        if integer.is_none() {
+            let message = if object.contains_key(key) { "expected a whole positive number" } else { "missing field" };
+            self.report(&format!("{path}.{key}"), message);
+        }
+        integer
+    }
+
+    fn range(&mut self, object: &Map<String, Value>, path: &str, key: &str) {
+        let path = format!("{path}.{key}");
+        match object.get(key).and_then(Value::as_array).map(|range| range.iter().map(Value::as_f64).collect::<Vec<_>>()) {
+            Some(range) => match range.as_slice() {
+                [Some(min), Some(max)] if min > max => self.report(&path, format!("minimum {min} is above maximum {max}")),
+                [Some(_), Some(_)] => {}
+                _ => self.report(&path, "expected [min, max]"),
+            },
+            None if object.contains_key(key) => self.report(&path, "expected [min, max]"),
+            None => self.report(&path, "missing field"),
+        }
+    }
+
+    fn vector(&mut self, object: &Map<String, Value>, path: &str, key: &str) -> Option<[f64; 3]> {
+        let value = object.get(key)?;
+        let vector = value.as_array()
+            .filter(|array| array.len() == 3)
+            .and_then(|array| Some([array[0].as_f64()?, array[1].as_f64()?, array[2].as_f64()?]));
+        if vector.is_none() {
+            self.report(&format!("{path}.{key}"), "expected [x, y, z]");
+        }
+        vector
+    }
+
+    fn color(&mut self, object: &Map<String, Value>, path: &str) {
+        let Some(color) = object.get("color") else {
+            self.report(&format!("{path}.color"), "missing field");
+            return;
+        };
+        let path = format!("{path}.color");
+        let Some(color) = self.object(color, &path) else {
+            return;
+        };
+
+        for channel in ["red", "green", "blue"] {
+            if let Some(value) = self.integer(color, &path, channel) {
+                if value > 255 {
+                    self.report(&format!("{path}.{channel}"), format!("must be between 0 and 255, found {value}"));
+                }
+            }
+        }
+    }
+
+    fn texture(&mut self, object: &Map<String, Value>, path: &str, key: &str, asset_root: &Path) {
+        if let Some(texture) = self.string(object, path, key) {
+            if !texture.is_empty() && !asset_root.join(texture).is_file() {
+                self.warn(&format!("{path}.{key}"), format!("{texture} does not exist in {}", asset_root.display()));
+            }
+        }
+    }
+
+    // Unknown fields are ignored when loading, but often are misspelled known ones
+    fn unknown_fields(&mut self, object: &Map<String, Value>, path: &str, known: &[&str]) {
+        for key in object.keys().filter(|key| !known.contains(&key.as_str())) {
+            self.warn(&format!("{path}.{key}"), "unknown field, it is ignored");
+        }
+    }
+}
+
+const STAR_FIELDS: [&str; 6] = ["name", "radius", "mass", "gravitational_parameter", "sidereal_rotation_period", "color"];
+const PLANET_FIELDS: [&str; 14] = [
+    "name", "parent", "color_texture", "normal_texture", "radius", "mass", "gravitational_parameter",
+    "sidereal_rotation_period", "inclination", "periapsis", "orbital_velocity_pe", "color",
+    "initial_position", "initial_velocity",
+];
+const POPULATION_FIELDS: [&str; 6] = ["name", "count", "seed", "display_radius", "color", "distribution"];
+
+/// Root directory Bevy loads the textures from.
+pub fn asset_root() -> PathBuf {
+    FileAssetIo::get_base_path().join("assets")
+}
+
+/// Checks a scenario before it is turned into a [`SolarSystemModel`], and returns every problem found.
+pub fn validate_scenario(file: &str, scenario: &Value, asset_root: &Path) -> Vec<ScenarioIssue> {
+    let mut validator = Validator { file, issues: Vec::new() };
+    let Some(root) = validator.object(scenario, "$") else {
+        return validator.issues;
+    };
+    validator.unknown_fields(root, "$", &["stars", "planets", "populations", "epoch"]);
+
+    if let Some(epoch) = root.get("epoch") {
+        if let Some(epoch) = validator.object(epoch, "$.epoch") {
+            validator.unknown_fields(epoch, "$.epoch", &["calendar", "date"]);
+
+            let calendar = validator.string(epoch, "$.epoch", "calendar").map(|name| (name, Calendar::from_name(name)));
+            match calendar {
+                Some((name, None)) => validator.report("$.epoch.calendar", format!("unknown calendar {name}, expected kerbin or earth")),
+                Some((_, Some(calendar))) if epoch.contains_key("date") => {
+                    if let Some(date) = validator.string(epoch, "$.epoch", "date") {
+                        if calendar.parse(date).is_none() {
+                            let expected = match calendar {
+                                Calendar::Kerbin => "Y1, D1, 0:00:00",
+                                Calendar::Earth => "2000-01-01T12:00:00 or JD 2451545.0",
+                            };
+                            validator.report("$.epoch.date", format!("{date} is not a date like {expected}"));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut names = HashSet::new();
+    let mut check_name = |validator: &mut Validator, name: Option<&str>, path: &str| {
+        if let Some(name) = name {
+            if !names.insert(name.to_string()) {
+                validator.report(&format!("{path}.name"), format!("{name} is used by another body"));
+            }
+        }
+    };
+
+    let stars = validator.array(root, "$", "stars").cloned().unwrap_or_default();
+    if stars.is_empty() && root.contains_key("stars") {
+        validator.report("$.stars", "at least one star is needed");
+    }
+
+    let mut star_radius = None;
+    for (index, star) in stars.iter().enumerate() {
+        let path = format!("$.stars[{index}]");
+        let Some(star) = validator.object(star, &path) else {
+            continue;
+        };
+        validator.unknown_fields(star, &path, &STAR_FIELDS);
+
+        let name = validator.string(star, &path, "name");
+        check_name(&mut validator, name, &path);
+        let radius = validator.positive(star, &path, "radius");
+        validator.non_negative(star, &path, "mass");
+        validator.positive(star, &path, "gravitational_parameter");
+        validator.non_zero(star, &path, "sidereal_rotation_period");
+        validator.color(star, &path);
+
+        if index == 0 {
+            star_radius = radius.map(|radius| (name.unwrap_or("the star").to_string(), radius));
+        }
+    }
+
+    let planets = validator.array(root, "$", "planets").cloned().unwrap_or_default();
+    let planet_names: Vec<Option<&str>> = planets.iter()
+        .map(|planet| planet.get("name").and_then(Value::as_str))
+        .collect();
+    let asset_root = asset_root.to_path_buf();
+
+    for (index, planet) in planets.iter().enumerate() {
+        let path = format!("$.planets[{index}]");
+        let Some(planet) = validator.object(planet, &path) else {
+            continue;
+        };
+        validator.unknown_fields(planet, &path, &PLANET_FIELDS);
+
+        let name = validator.string(planet, &path, "name");
+        check_name(&mut validator, name, &path);
+        validator.texture(planet, &path, "color_texture", &asset_root);
+        validator.texture(planet, &path, "normal_texture", &asset_root);
+        validator.positive(planet, &path, "radius");
+        validator.non_negative(planet, &path, "mass");
+        if planet.contains_key("gravitational_parameter") {
+            validator.non_negative(planet, &path, "gravitational_parameter");
+        }
+        validator.non_zero(planet, &path, "sidereal_rotation_period");
+        validator.number(planet, &path, "inclination");
+        validator.non_negative(planet, &path, "orbital_velocity_pe");
+        validator.color(planet, &path);
+
+        // The periapsis is measured from the surface of the body the planet orbits
+        let parent = match planet.get("parent") {
+            Some(Value::String(parent)) => {
+                if Some(parent.as_str()) == name {
+                    validator.report(&format!("{path}.parent"), "a body cannot orbit itself");
+                } else if !planet_names.contains(&Some(parent.as_str())) {
+                    validator.report(&format!("{path}.parent"), format!("there is no planet called {parent}"));
+                }
+                Some(parent.clone())
+            }
+            Some(_) => {
+                validator.report(&format!("{path}.parent"), "expected a string");
+                None
+            }
+            None => None,
+        };
+        let parent_name = parent.or_else(|| star_radius.as_ref().map(|(name, _)| name.clone())).unwrap_or_default();
+
+        if let Some(periapsis) = validator.number(planet, &path, "periapsis") {
+            if periapsis < 0.0 {
+                validator.report(
+                    &format!("{path}.periapsis"),
+                    format!("periapsis is {:.3} Mm inside {parent_name}", -periapsis),
+                );
+            }
+        }
+
+        let initial_position = validator.vector(planet, &path, "initial_position");
+        validator.vector(planet, &path, "initial_velocity");
+        if planet.contains_key("initial_position") != planet.contains_key("initial_velocity") {
+            validator.report(&path, "initial_position and initial_velocity go together");
+        }
+
+        if let (Some(position), Some((star_name, radius)), None) = (initial_position, star_radius.as_ref(), planet.get("parent")) {
+            let distance = (position[0] * position[0] + position[1] * position[1] + position[2] * position[2]).sqrt();
+            if distance < *radius {
+                validator.report(&format!("{path}.initial_position"), format!("starts inside {star_name}"));
+            }
+        }
+    }
+
+    if let Some(populations) = root.get("populations") {
+        let populations = match populations.as_array() {
+            Some(populations) => populations.clone(),
+            None => {
+                validator.report("$.populations", "expected a list");
+                Vec::new()
+            }
+        };
+
+        for (index, population) in populations.iter().enumerate() {
+            let path = format!("$.populations[{index}]");
+            let Some(population) = validator.object(population, &path) else {
+                continue;
+            };
+            validator.unknown_fields(population, &path, &POPULATION_FIELDS);
+
+            validator.string(population, &path, "name");
+            validator.integer(population, &path, "count");
+            validator.integer(population, &path, "seed");
+            validator.positive(population, &path, "display_radius");
+            validator.color(population, &path);
+
+            let distribution_path = format!("{path}.distribution");
+            let Some(distribution) = population.get("distribution") else {
+                validator.report(&distribution_path, "missing field");
+                continue;
+            };
+            let Some(distribution) = validator.object(distribution, &distribution_path) else {
+                continue;
+            };
+
+            match validator.string(distribution, &distribution_path, "kind") {
+                Some("belt") => validator.range(distribution, &distribution_path, "semi_major_axis"),
+                Some("trojans") => {
+                    if let Some(planet) = validator.string(distribution, &distribution_path, "planet") {
+                        if !planet_names.contains(&Some(planet)) {
+                            validator.report(&format!("{distribution_path}.planet"), format!("there is no planet called {planet}"));
+                        }
+                    }
+                    validator.non_negative(distribution, &distribution_path, "libration_amplitude");
+                }
+                Some("scattered_disk") => validator.range(distribution, &distribution_path, "periapsis"),
+                Some(kind) => {
+                    validator.report(&format!("{distribution_path}.kind"), format!("{kind} is not one of belt, trojans or scattered_disk"));
+                    continue;
+                }
+                None => continue,
+            }
+            validator.range(distribution, &distribution_path, "eccentricity");
+            validator.range(distribution, &distribution_path, "inclination");
+        }
+    }
+
+    validator.issues
+}
+
+/// Checks a physical constants file, and returns every problem found.
+pub fn validate_constants(file: &str, constants: &Value) -> Vec<ScenarioIssue> {
+    let mut validator = Validator { file, issues: Vec::new() };
+    let Some(root) = validator.object(constants, "$") else {
+        return validator.issues;
+    };
+    validator.unknown_fields(root, "$", &["gravitational_constant", "dv", "seconds_per_time_unit", "integrator"]);
+
+    validator.positive(root, "$", "gravitational_constant");
+    validator.positive(root, "$", "dv");
+    if root.contains_key("seconds_per_time_unit") {
+        validator.positive(root, "$", "seconds_per_time_unit");
+    }
+    if root.contains_key("integrator") {
+        if let Some(integrator) = validator.string(root, "$", "integrator") {
+            if serde_json::from_value::<Integrator>(Value::String(integrator.to_string())).is_err() {
+                validator.report("$.integrator", format!("unknown integrator {integrator}, expected semi-implicit-euler or leapfrog"));
+            }
+        }
+    }
+
+    validator.issues
+}
+
+/// Reads a scenario or constants file in the format of its extension, as a JSON value.
+pub fn read_document(path: &str, issues: &mut Vec<ScenarioIssue>) -> Option<Value> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) => {
+            issues.push(ScenarioIssue::file(path, format!("could not read the file: {err}")));
+            return None;
+        }
+    };
+
+    match ScenarioFormat::from_path(path).parse(&text) {
+        Ok(value) => Some(value),
+        Err(message) => {
+            issues.push(ScenarioIssue::file(path, message));
+            None
+        }
+    }
+}
+
+fn deserialize<T: serde::de::DeserializeOwned>(path: &str, value: Value, issues: &mut Vec<ScenarioIssue>) -> Option<T> {
+    if has_errors(issues) {
+        return None;
+    }
+
+    match serde_json::from_value(value) {
+        Ok(model) => Some(model),
+        Err(err) => {
+            issues.push(ScenarioIssue::file(path, err));
+            None
+        }
+    }
+}
+
+/// Reads, checks and deserializes a scenario file. Every problem found is added to `issues`,
+/// nothing is returned if one of them is an error.
+pub fn load_scenario(path: &str, issues: &mut Vec<ScenarioIssue>) -> Option<SolarSystemModel> {
+    let scenario = read_document(path, issues)?;
+    check_scenario(path, scenario, issues)
+}
+
+/// Checks and deserializes a scenario already read from `path`, like [`load_scenario`].
+pub fn check_scenario(path: &str, scenario: Value, issues: &mut Vec<ScenarioIssue>) -> Option<SolarSystemModel> {
+    issues.extend(validate_scenario(path, &scenario, &asset_root()));
+    deserialize(path, scenario, issues)
+}
+
+/// Checks a system built by an importer the same way as a scenario file.
+pub fn check_solar_system(path: &str, solar_system: SolarSystemModel, issues: &mut Vec<ScenarioIssue>) -> Option<SolarSystemModel> {
+    let scenario = match serde_json::to_value(&solar_system) {
+        Ok(scenario) => scenario,
+        Err(err) => {
+            issues.push(ScenarioIssue::file(path, err));
+            return None;
+        }
+    };
+
+    let found = validate_scenario(path, &scenario, &asset_root());
+    let valid = !has_errors(&found);
+    issues.extend(found);
+
+    valid.then_some(solar_system)
+}
+
+/// Reads, checks and deserializes a physical constants file, like [`load_scenario`].
+pub fn load_constants(path: &str, issues: &mut Vec<ScenarioIssue>) -> Option<PhysicalConstantsModel> {
+    let constants = read_document(path, issues)?;
+    check_constants(path, constants, issues)
+}
+
+/// Checks and deserializes a physical constants file already read from `path`.
+pub fn check_constants(path: &str, constants: Value, issues: &mut Vec<ScenarioIssue>) -> Option<PhysicalConstantsModel> {
+    issues.extend(validate_constants(path, &constants));
+    deserialize(path, constants, issues)
+}
+
+// Runs again whenever the problems change, e.g. after a file is fixed and reloaded
+fn show_scenario_problems(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    problems: Res<ScenarioProblems>,
+    screens: Query<Entity, With<ScenarioProblemsScreen>>,
+) {
+    if !problems.is_changed() {
+        return;
+    }
+
+    for screen in screens.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+
+    for issue in problems.0.iter() {
+        match issue.severity {
+            IssueSeverity::Warning => warn!("{issue}"),
+            IssueSeverity::Error => error!("{issue}"),
+        }
+    }
+
+    if !problems.has_errors() {
+        return;
+    }
+
+    let font = asset_server.load("fonts/LcdRoundedRegular.ttf");
+    let listing = problems.0.iter()
+        .filter(|issue| issue.severity == IssueSeverity::Error)
+        .map(|issue| format!("{issue}\n"))
+        .collect::<String>();
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                position_type: PositionType::Absolute,
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(20.0)),
+                ..default()
+            },
+            background_color: Color::rgba(0.1, 0.0, 0.0, 0.9).into(),
+            ..default()
+        })
+        .insert(ScenarioProblemsScreen)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_sections([
+                TextSection::new(
+                    "The scenario could not be loaded:\n\n",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 24.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                TextSection::new(
+                    listing,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 18.0,
+                        color: Color::rgb(1.0, 0.5, 0.4),
+                    },
+                ),
+            ]));
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn scenario() -> Value {
+        json!({
+            "stars": [{
+                "name": "Kerbol", "radius": 261.6, "mass": 1.7565459e22, "gravitational_parameter": 15704.99,
+                "sidereal_rotation_period": 432000.0, "color": { "red": 245, "green": 255, "blue": 230 }
+            }],
+            "planets": [{
+                "name": "Kerbin", "color_texture": "", "normal_texture": "", "radius": 0.6, "mass": 5.29e22,
+                "gravitational_parameter": 3.53, "sidereal_rotation_period": 21549.4, "inclination": 0.0,
+                "periapsis": 13337.6, "orbital_velocity_pe": 9.285, "color": { "red": 40, "green": 90, "blue": 200 }
+            }, {
+                "name": "Mun", "parent": "Kerbin", "color_texture": "", "normal_texture": "", "radius": 0.2,
+                "mass": 9.76e20, "gravitational_parameter": 0.065, "sidereal_rotation_period": 138984.4,
+                "inclination": 0.0, "periapsis": 11.4, "orbital_velocity_pe": 0.543,
+                "color": { "red": 150, "green": 150, "blue": 150 }
+            }]
+        })
+    }
+
+    // Severity, path and message of every issue
+    fn issues(scenario: &Value) -> Vec<(IssueSeverity, String, String)> {
+        validate_scenario("planets.json", scenario, Path::new("no_assets"))
+            .into_iter()
+            .map(|issue| (issue.severity, issue.path, issue.message))
+            .collect()
+    }
+
+    fn error(path: &str, message: &str) -> (IssueSeverity, String, String) {
+        (IssueSeverity::Error, path.to_string(), message.to_string())
+    }
+
+    fn warning(path: &str, message: &str) -> (IssueSeverity, String, String) {
+        (IssueSeverity::Warning, path.to_string(), message.to_string())
+    }
+
+    #[test]
+    fn a_valid_scenario_has_no_issues() {
+        assert_eq!(issues(&scenario()), vec![]);
+    }
+
+    #[test]
+    fn reports_a_missing_field() {
+        let mut scenario = scenario();
+        scenario["planets"][0].as_object_mut().unwrap().remove("mass");
+
+        assert_eq!(issues(&scenario), vec![error("$.planets[0].mass", "missing field")]);
+    }
+
+    #[test]
+    fn reports_a_wrong_type() {
+        let mut scenario = scenario();
+        scenario["stars"][0]["radius"] = json!("large");
+
+        assert_eq!(issues(&scenario), vec![error("$.stars[0].radius", "expected a number")]);
+    }
+
+    #[test]
+    fn reports_a_negative_radius() {
+        let mut scenario = scenario();
+        scenario["planets"][1]["radius"] = json!(-0.2);
+
+        assert_eq!(issues(&scenario), vec![error("$.planets[1].radius", "must be positive, found -0.2")]);
+    }
+
+    #[test]
+    fn reports_a_periapsis_inside_the_star() {
+        let mut scenario = scenario();
+        scenario["planets"][0]["periapsis"] = json!(-1.5);
+
+        assert_eq!(issues(&scenario), vec![error("$.planets[0].periapsis", "periapsis is 1.500 Mm inside Kerbol")]);
+    }
+
+    #[test]
+    fn reports_a_periapsis_inside_the_parent() {
+        let mut scenario = scenario();
+        scenario["planets"][1]["periapsis"] = json!(-0.25);
+
+        assert_eq!(issues(&scenario), vec![error("$.planets[1].periapsis", "periapsis is 0.250 Mm inside Kerbin")]);
+    }
+
+    #[test]
+    fn reports_a_duplicate_name() {
+        let mut scenario = scenario();
+        scenario["planets"][1]["name"] = json!("Kerbol");
+
+        assert_eq!(issues(&scenario), vec![error("$.planets[1].name", "Kerbol is used by another body")]);
+    }
+
+    #[test]
+    fn reports_an_unknown_parent() {
+        let mut scenario = scenario();
+        scenario["planets"][1]["parent"] = json!("Duna");
+
+        assert_eq!(issues(&scenario), vec![error("$.planets[1].parent", "there is no planet called Duna")]);
+    }
+
+    #[test]
+    fn reports_a_body_orbiting_itself() {
+        let mut scenario = scenario();
+        scenario["planets"][1]["parent"] = json!("Mun");
+
+        assert_eq!(issues(&scenario), vec![error("$.planets[1].parent", "a body cannot orbit itself")]);
+    }
+
+    #[test]
+    fn warns_about_a_missing_texture() {
+        let mut scenario = scenario();
+        scenario["planets"][0]["color_texture"] = json!("planets/kerbin/color_texture.png");
+
+        let expected = format!("planets/kerbin/color_texture.png does not exist in {}", Path::new("no_assets").display());
+        assert_eq!(issues(&scenario), vec![warning("$.planets[0].color_texture", &expected)]);
+    }
+
+    #[test]
+    fn warns_about_an_unknown_field() {
+        let mut scenario = scenario();
+        scenario["planets"][0]["perapsis"] = json!(13337.6);
+
+        assert_eq!(issues(&scenario), vec![warning("$.planets[0].perapsis", "unknown field, it is ignored")]);
+    }
+
+    #[test]
+    fn issues_do_not_stop_the_validation() {
+        let mut scenario = scenario();
+        scenario["planets"][0]["radius"] = json!(0.0);
+        scenario["planets"][1]["parent"] = json!(3);
+
+        assert_eq!(issues(&scenario), vec![
+            error("$.planets[0].radius", "must be positive, found 0"),
+            error("$.planets[1].parent", "expected a string"),
+        ]);
+    }
+}
@@ -0,0 +1,71 @@
+use bevy::prelude::*;
+
+use crate::{calendar::*, planet_models::*};
+
+/// Simulated time, counted from the epoch of the scenario.
+#[derive(Resource, Debug, Clone)]
+pub struct SimulationClock {
+    /// Simulated time since the epoch, in the same time units as `dv`
+    pub elapsed: f64,
+    pub calendar: Calendar,
+    /// Date of the epoch, in seconds since the origin of the calendar
+    pub epoch: f64,
+    /// Length of the time unit in seconds, copied from the physical constants
+    pub seconds_per_time_unit: f64,
+}
+
+impl SimulationClock {
+    pub fn new(config: &SolarSystemConfiguration) -> Self {
+        let (calendar, epoch) = match &config.solar_system.epoch {
+            Some(epoch) => {
+                // The scenario was validated, an unreadable date can only come from an importer
+                let date = epoch.date.as_deref().and_then(|date| epoch.calendar.parse(date));
+                (epoch.calendar, date.unwrap_or_default())
+            }
+            None => (Calendar::default(), 0.0),
+        };
+
+        SimulationClock {
+            elapsed: 0.0,
+            calendar,
+            epoch,
+            seconds_per_time_unit: config.physical_constants.seconds_per_time_unit,
+        }
+    }
+
+    /// Seconds since the origin of the calendar at `time`, given in time units since the epoch.
+    /// For the Earth calendar these are seconds since J2000.
+    pub fn seconds_at(&self, time: f64) -> f64 {
+        self.epoch + time * self.seconds_per_time_unit
+    }
+
+    /// Time in time units since the epoch of a date given in seconds since the origin of the calendar.
+    pub fn time_at(&self, seconds: f64) -> f64 {
+        (seconds - self.epoch) / self.seconds_per_time_unit
+    }
+
+    /// Seconds since the origin of the calendar now.
+    pub fn now(&self) -> f64 {
+        self.seconds_at(self.elapsed)
+    }
+
+    /// Date of `time`, given in time units since the epoch, in the calendar of the scenario.
+    pub fn format_at(&self, time: f64) -> String {
+        self.calendar.format(self.seconds_at(time))
+    }
+
+    pub fn format_now(&self) -> String {
+        self.format_at(self.elapsed)
+    }
+
+    /// Julian Date now, only known with the Earth calendar.
+    pub fn julian_date(&self) -> Option<f64> {
+        (self.calendar == Calendar::Earth).then(|| julian_date(self.now()))
+    }
+}
+
+impl FromWorld for SimulationClock {
+    fn from_world(world: &mut World) -> Self {
+        SimulationClock::new(world.resource::<SolarSystemConfiguration>())
+    }
+}
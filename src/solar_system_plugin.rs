@@ -1,24 +1,67 @@
-use crate::{planet_components::*, planet_models::*, labels::*};
-use bevy::{prelude::*, render::mesh::VertexAttributeValues, input::{keyboard::KeyboardInput, ButtonState}};
+use crate::{physics::*, planet_components::*, planet_models::*, simulation_clock::*, time_warp_plugin::*, labels::*};
+use bevy::{prelude::*, render::mesh::VertexAttributeValues};
 
 pub struct SolarSystemPlugin;
 
 impl Plugin for SolarSystemPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<SolarSystemConfiguration>()
+        app.add_plugin(SolarSystemPhysicsPlugin)
             .add_startup_system(create_sun_and_planets)
+            .add_system(respawn_sun_and_planets
+                .before(SystemTypes::SnapshotLabel))
+            .add_system(respawn_bodies
+                .before(SystemTypes::SnapshotLabel));
+    }
+}
+
+/// Moves the bodies without drawing them or reading any input, so it can also run headless.
+pub struct SolarSystemPhysicsPlugin;
+
+impl Plugin for SolarSystemPhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SolarSystemConfiguration>()
+            .init_resource::<SimulationClock>()
+            .init_resource::<TimeWarp>()
+            .init_resource::<FrameSubsteps>()
+            .add_event::<RespawnSolarSystem>()
+            .add_event::<RespawnBodies>()
+            .add_system(record_previous_state
+                .with_run_criteria(simulation_running)
+                .label(SystemTypes::SnapshotLabel)
+                .before(SystemTypes::PhysicsLabel))
+            .add_system(advance_simulation_time
+                .with_run_criteria(simulation_running)
+                .label(SystemTypes::PhysicsLabel)
+                .before(SystemTypes::CameraLabel))
             .add_system(move_planets
+                .with_run_criteria(simulation_running)
                 .label(SystemTypes::PhysicsLabel)
                 .before(SystemTypes::CameraLabel))
             .add_system(rotate_planets
+                .with_run_criteria(simulation_running)
                 .label(SystemTypes::PhysicsLabel)
                 .before(SystemTypes::CameraLabel))
-            .add_system(change_time_dv
-                .label(SystemTypes::PhysicsLabel)
-                .before(SystemTypes::CameraLabel));
+            .add_system(finish_substep
+                .with_run_criteria(simulation_running)
+                .after(SystemTypes::PhysicsLabel))
+            .add_system_to_stage(CoreStage::PostUpdate, finish_frame);
     }
 }
 
+/// Sent after `SolarSystemConfiguration` was replaced, to throw away the current bodies
+/// and spawn the ones of the new configuration.
+pub struct RespawnSolarSystem;
+
+/// Sent after some bodies of `SolarSystemConfiguration` changed, to respawn only those.
+pub struct RespawnBodies {
+    /// Bodies to spawn again from the configuration, the ones it no longer has are only despawned
+    pub names: Vec<String>,
+    /// Keep the position and velocity the bodies had instead of starting over from the configuration
+    pub keep_state: bool,
+    /// Also respawn the particle populations
+    pub populations: bool,
+}
+
 fn create_sun_and_planets(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
@@ -26,125 +69,317 @@ fn create_sun_and_planets(
     mut materials: ResMut<Assets<StandardMaterial>>,
     asset_server: Res<AssetServer>,
 ) {
-    // Create the suns of the system
-    for sun in config.solar_system.stars.iter() {
-        let mesh = create_mesh(sun.radius, sun.color);
-
-        commands
-            .spawn((
-                PointLightBundle {
-                    transform: Transform::from_xyz(0.0, 0.0, 0.0),
-                    point_light: PointLight {
-                        intensity: 5.573E+10, // lumens
-                        color: sun.color.to_color(),
-                        shadows_enabled: true,
-                        range: f32::MAX,
-                        ..default()
-                    },
-                    ..default()
-                },
-                FocusableEntity { is_focused: true },
-                CelestialBody {
-                    mass: sun.mass,
-                    name: sun.name.clone(),
-                    radius: sun.radius,
-                    gravitational_parameter: sun.gravitational_parameter,
-                    vel: Velocity::default(),
-                    acc: Acceleration::default(),
-                    rot: 2.0*(std::f32::consts::PI)/sun.sidereal_rotation_period,
-                    inclination: 0.0,
-                },
-                Star,
-            ))
-            .with_children(|builder| {
-                builder.spawn(PbrBundle {
-                    mesh: meshes.add(mesh),
-                    material: materials.add(StandardMaterial {
-                        base_color: sun.color.to_color(),
-                        emissive: sun.color.to_color(),
-                        perceptual_roughness: 0.3,
-                        ..default()
-                    }),
-                    ..default()
-                });
-            });
+    spawn_sun_and_planets(&mut commands, &mut meshes, &mut materials, &asset_server, &config);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn respawn_sun_and_planets(
+    mut commands: Commands,
+    mut respawn_evr: EventReader<RespawnSolarSystem>,
+    bodies: Query<Entity, With<CelestialBody>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    config: Res<SolarSystemConfiguration>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    asset_server: Res<AssetServer>,
+    mut clock: ResMut<SimulationClock>,
+) {
+    if respawn_evr.iter().count() == 0 {
+        return;
     }
 
-    if let Some(sun) = config.solar_system.stars.iter().next() {
-        for planet in config.solar_system.planets.iter() {
-            let mut mesh = create_mesh(planet.radius, planet.color);
+    for entity in bodies.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
 
-            let base_color_texture: Option<Handle<Image>> = if planet.color_texture != "" {
-                Some(asset_server.load(planet.color_texture.to_owned()))
-            } else {
-                None
-            };
+    // The new configuration can start at another epoch
+    *clock = SimulationClock::new(&config);
+    spawn_sun_and_planets(&mut commands, &mut meshes, &mut materials, &asset_server, &config);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn respawn_bodies(
+    mut commands: Commands,
+    mut respawn_evr: EventReader<RespawnBodies>,
+    bodies: Query<(Entity, &Transform, &CelestialBody, &FocusableEntity)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    config: Res<SolarSystemConfiguration>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    asset_server: Res<AssetServer>,
+) {
+    for ev in respawn_evr.iter() {
+        for name in ev.names.iter() {
+            let existing = bodies.iter().find(|(_, _, body, _)| body.name == *name);
+            let state = existing
+                .filter(|_| ev.keep_state)
+                .map(|(_, pos, body, _)| (pos.translation, body.vel.vector));
+            let focused = existing.is_some_and(|(_, _, _, focus)| focus.is_focused);
 
-            let normal_map_texture: Option<Handle<Image>> = if planet.normal_texture != "" {
-                Some(asset_server.load(planet.normal_texture.to_owned()))
+            if let Some((entity, ..)) = existing {
+                commands.entity(entity).despawn_recursive();
+            }
+
+            let sun = config.solar_system.stars.iter().next();
+            let entity = if let Some(star) = config.solar_system.stars.iter().find(|star| star.name == *name) {
+                Some(spawn_star(&mut commands, &mut meshes, &mut materials, star))
+            } else if let (Some(planet), Some(sun)) = (config.solar_system.planets.iter().find(|planet| planet.name == *name), sun) {
+                Some(spawn_planet(&mut commands, &mut meshes, &mut materials, &asset_server, planet, sun, &config.solar_system.planets, state))
             } else {
                 None
             };
 
-            Mesh::generate_tangents(&mut mesh).expect("Something");
+            // Keep looking at a body that was replaced, and not at a new star
+            if let Some(entity) = entity {
+                commands.entity(entity).insert(FocusableEntity { is_focused: focused });
+            }
+        }
+    }
+}
 
-            let planet_pbr_bundle = PbrBundle {
+fn spawn_sun_and_planets(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    asset_server: &AssetServer,
+    config: &SolarSystemConfiguration,
+) {
+    // Create the suns of the system
+    for sun in config.solar_system.stars.iter() {
+        spawn_star(commands, meshes, materials, sun);
+    }
+
+    if let Some(sun) = config.solar_system.stars.iter().next() {
+        for planet in config.solar_system.planets.iter() {
+            spawn_planet(commands, meshes, materials, asset_server, planet, sun, &config.solar_system.planets, None);
+        }
+    }
+}
+
+fn spawn_star(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    sun: &StarModel,
+) -> Entity {
+    let mesh = create_mesh(sun.radius, sun.color);
+
+    commands
+        .spawn((
+            PointLightBundle {
+                transform: Transform::from_xyz(0.0, 0.0, 0.0),
+                point_light: PointLight {
+                    intensity: 5.573E+10, // lumens
+                    color: sun.color.to_color(),
+                    shadows_enabled: true,
+                    range: f32::MAX,
+                    ..default()
+                },
+                ..default()
+            },
+            FocusableEntity { is_focused: true },
+            star_physics(sun),
+            Star,
+        ))
+        .with_children(|builder| {
+            builder.spawn(PbrBundle {
                 mesh: meshes.add(mesh),
                 material: materials.add(StandardMaterial {
-                    base_color: planet.color.to_color(),
-                    base_color_texture,
-                    normal_map_texture,
-                    perceptual_roughness: 0.9,
-                    reflectance: 0.2,
+                    base_color: sun.color.to_color(),
+                    emissive: sun.color.to_color(),
+                    perceptual_roughness: 0.3,
                     ..default()
                 }),
-                transform:
-                    Transform::from_xyz(planet.periapsis + sun.radius, 0., 0.)
-                    .with_rotation(Quat::from_rotation_x(std::f32::consts::FRAC_PI_2 + (planet.inclination/180. * std::f32::consts::PI)))
-                    ,
                 ..default()
+            });
+        })
+        .id()
+}
+
+/// Spawns a planet in its initial state, or in the given position and velocity.
+#[allow(clippy::too_many_arguments)]
+fn spawn_planet(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    asset_server: &AssetServer,
+    planet: &PlanetModel,
+    sun: &StarModel,
+    planets: &[PlanetModel],
+    state: Option<(Vec3, Vec3)>,
+) -> Entity {
+    let mut mesh = create_mesh(planet.radius, planet.color);
+
+    let base_color_texture: Option<Handle<Image>> = if planet.color_texture != "" {
+        Some(asset_server.load(planet.color_texture.to_owned()))
+    } else {
+        None
+    };
+
+    let normal_map_texture: Option<Handle<Image>> = if planet.normal_texture != "" {
+        Some(asset_server.load(planet.normal_texture.to_owned()))
+    } else {
+        None
+    };
+
+    Mesh::generate_tangents(&mut mesh).expect("Something");
+
+    let (mut transform, mut body, mut previous_state) = planet_physics(planet, sun, planets);
+
+    if let Some((position, velocity)) = state {
+        transform.translation = position;
+        body.vel.vector = velocity;
+        previous_state = PreviousState { position, velocity };
+    }
+
+    let planet_pbr_bundle = PbrBundle {
+        mesh: meshes.add(mesh),
+        material: materials.add(StandardMaterial {
+            base_color: planet.color.to_color(),
+            base_color_texture,
+            normal_map_texture,
+            perceptual_roughness: 0.9,
+            reflectance: 0.2,
+            ..default()
+        }),
+        transform,
+        ..default()
+    };
+
+    commands.spawn((planet_pbr_bundle,
+        FocusableEntity::default(),
+        body,
+        previous_state,
+        Planet,
+    )).id()
+}
+
+/// Physical state of a star when the scenario starts. Stars sit at the origin.
+pub fn star_physics(sun: &StarModel) -> (CelestialBody, PreviousState) {
+    (
+        CelestialBody {
+            mass: sun.mass,
+            name: sun.name.clone(),
+            radius: sun.radius,
+            gravitational_parameter: sun.gravitational_parameter,
+            vel: Velocity::default(),
+            acc: Acceleration::default(),
+            rot: 2.0*(std::f32::consts::PI)/sun.sidereal_rotation_period,
+            inclination: 0.0,
+        },
+        PreviousState::default(),
+    )
+}
+
+/// Position and velocity of a planet relative to `sun` when the scenario starts, at its
+/// periapsis around `parent`, or the star, unless the scenario gives its initial state.
+/// The parent comes with its own initial state.
+pub fn initial_state(planet: &PlanetModel, sun: &StarModel, parent: Option<(&PlanetModel, (Vec3, Vec3))>) -> (Vec3, Vec3) {
+    match (planet.initial_position, planet.initial_velocity) {
+        (Some(position), Some(velocity)) => (Vec3::from_array(position), Vec3::from_array(velocity)),
+        _ => {
+            let (radius, (origin, origin_velocity)) = match parent {
+                Some((parent, state)) => (parent.radius, state),
+                None => (sun.radius, (Vec3::ZERO, Vec3::ZERO)),
             };
 
-            commands.spawn((planet_pbr_bundle,
-                FocusableEntity::default(),
-                CelestialBody {
-                    mass: planet.mass,
-                    name: planet.name.clone(),
-                    radius: planet.radius,
-                    gravitational_parameter: 0.0,
-                    vel: Velocity::from_xyz(0.0, 0.0, -planet.orbital_velocity_pe),
-                    acc: Acceleration::from_xyz(0.0, 0.0, 0.0),
-                    rot: 2.0*(std::f32::consts::PI)/planet.sidereal_rotation_period,
-                    inclination: planet.inclination,
-                },
-                Planet,
-            ));
+            (
+                origin + Vec3::new(planet.periapsis + radius, 0., 0.),
+                origin_velocity + Vec3::new(0.0, 0.0, -planet.orbital_velocity_pe),
+            )
         }
     }
 }
 
+/// Initial state of a planet of `planets`, with each moon placed around the initial state of its parent.
+pub fn system_initial_state(planet: &PlanetModel, sun: &StarModel, planets: &[PlanetModel]) -> (Vec3, Vec3) {
+    let find = |name: &str| planets.iter().find(|planet| planet.name == name);
+
+    // A cycle of parents would otherwise never end, its outermost body then orbits the star
+    let mut chain = vec![planet];
+    while let Some(parent) = chain[chain.len() - 1].parent.as_deref().and_then(find) {
+        if chain.len() > planets.len() {
+            break;
+        }
+        chain.push(parent);
+    }
+
+    let mut parent = None;
+    for body in chain.into_iter().rev() {
+        parent = Some((body, initial_state(body, sun, parent)));
+    }
+    parent.map_or((Vec3::ZERO, Vec3::ZERO), |(_, state)| state)
+}
+
+/// Physical state of a planet of `planets` when the scenario starts.
+pub fn planet_physics(planet: &PlanetModel, sun: &StarModel, planets: &[PlanetModel]) -> (Transform, CelestialBody, PreviousState) {
+    let (position, velocity) = system_initial_state(planet, sun, planets);
+
+    (
+        Transform::from_translation(position)
+            .with_rotation(Quat::from_rotation_x(std::f32::consts::FRAC_PI_2 + (planet.inclination/180. * std::f32::consts::PI))),
+        CelestialBody {
+            mass: planet.mass,
+            name: planet.name.clone(),
+            radius: planet.radius,
+            gravitational_parameter: planet.gravitational_parameter,
+            vel: Velocity::from_xyz(velocity.x, velocity.y, velocity.z),
+            acc: Acceleration::from_xyz(0.0, 0.0, 0.0),
+            rot: 2.0*(std::f32::consts::PI)/planet.sidereal_rotation_period,
+            inclination: planet.inclination,
+        },
+        PreviousState {
+            position,
+            velocity,
+        },
+    )
+}
+
+/// Copies the bodies into the physics core, steps them and copies them back. Stars stay at the
+/// origin and bodies following an ephemeris are moved by it, both still pull on the others.
 fn move_planets(
-    suns: Query<(&Transform, &CelestialBody), Without<Planet>>,
-    mut planets: Query<(&mut Transform, &mut CelestialBody, &Planet)>,
+    mut bodies: Query<(&mut Transform, &mut CelestialBody, Option<&Planet>, Option<&OemTrajectory>)>,
     constants: Res<SolarSystemConfiguration>,
 ) {
-    //dbg!(constants.physical_constants.dv);
-    for (sun_pos, sun_body) in suns.iter() {
-        for (mut planet_pos, mut planet_body, _) in planets.iter_mut() {
-            let r_vector = sun_pos.translation - planet_pos.translation;
-            let distance = r_vector.length();
+    let mut state: Vec<Body> = bodies.iter()
+        .map(|(pos, body, planet, trajectory)| Body {
+            position: pos.translation,
+            velocity: body.vel.vector,
+            acceleration: body.acc.vector,
+            gravitational_parameter: body.gravitational_parameter,
+            fixed: planet.is_none() || trajectory.is_some(),
+        })
+        .collect();
 
-            let acc = (sun_body.gravitational_parameter * r_vector) / (distance * distance * distance);
+    step(&mut state, constants.physical_constants.integrator.into(), constants.physical_constants.dv);
 
-            planet_body.acc.vector = acc;
+    // The query iterates in the same order as long as nothing was added or removed in between
+    for ((mut pos, mut body, _, _), stepped) in bodies.iter_mut().zip(state.iter()) {
+        if stepped.fixed {
+            continue;
+        }
 
-            planet_body.vel.vector += acc * constants.physical_constants.dv;
+        pos.translation = stepped.position;
+        body.vel.vector = stepped.velocity;
+        body.acc.vector = stepped.acceleration;
+    }
+}
 
-            planet_pos.translation += planet_body.vel.vector * constants.physical_constants.dv;
-        }
+fn record_previous_state(
+    mut bodies: Query<(&Transform, &CelestialBody, &mut PreviousState)>,
+) {
+    for (pos, body, mut previous) in bodies.iter_mut() {
+        previous.position = pos.translation;
+        previous.velocity = body.vel.vector;
     }
 }
 
+fn advance_simulation_time(
+    mut clock: ResMut<SimulationClock>,
+    constants: Res<SolarSystemConfiguration>,
+) {
+    clock.elapsed += constants.physical_constants.dv as f64;
+    clock.seconds_per_time_unit = constants.physical_constants.seconds_per_time_unit;
+}
+
 fn rotate_planets(
     mut planets: Query<(&mut Transform, &CelestialBody, &Planet)>,
     constants: Res<SolarSystemConfiguration>,
@@ -154,27 +389,6 @@ fn rotate_planets(
     }
 }
 
-fn change_time_dv(
-    mut key_evr: EventReader<KeyboardInput>,
-    mut constants: ResMut<SolarSystemConfiguration>
-) {
-
-    let increase_dv_button = KeyCode::Period;
-    let decrease_dv_button = KeyCode::Comma;
-
-    for ev in key_evr.iter() {
-        // Change focus to the next object when the button is pressed
-
-        if ev.state == ButtonState::Pressed && ev.key_code == Some(increase_dv_button) {
-            constants.physical_constants.dv *= 10.0;
-        }
-
-        if ev.state == ButtonState::Pressed && ev.key_code == Some(decrease_dv_button) {
-            constants.physical_constants.dv /= 10.0;
-        }
-    }
-}
-
 fn create_mesh(radius: f32, color: PlanetColor) -> Mesh {
     // Create the mesh of the sun
     let mut mesh = Mesh::from(shape::UVSphere {
@@ -194,3 +408,95 @@ fn create_mesh(radius: f32, color: PlanetColor) -> Mesh {
 
     return mesh;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_vec_close(actual: Vec3, expected: Vec3, tolerance: f32) {
+        assert!((actual - expected).length() <= tolerance, "{actual} is not within {tolerance} of {expected}");
+    }
+
+    fn planet(name: &str, parent: Option<&str>, radius: f32, periapsis: f32, orbital_velocity_pe: f32) -> PlanetModel {
+        PlanetModel {
+            name: name.to_string(),
+            parent: parent.map(str::to_string),
+            color_texture: String::new(),
+            normal_texture: String::new(),
+            radius,
+            mass: 1.0,
+            gravitational_parameter: 1.0,
+            sidereal_rotation_period: 1.0,
+            inclination: 0.0,
+            periapsis,
+            orbital_velocity_pe,
+            color: PlanetColor { red: 0, green: 0, blue: 0 },
+            initial_position: None,
+            initial_velocity: None,
+        }
+    }
+
+    fn sun() -> StarModel {
+        StarModel {
+            name: "Kerbol".to_string(),
+            radius: 261.6,
+            mass: 1.0,
+            gravitational_parameter: 1.0,
+            sidereal_rotation_period: 1.0,
+            color: PlanetColor { red: 255, green: 255, blue: 255 },
+        }
+    }
+
+    #[test]
+    fn a_planet_starts_at_its_periapsis_around_the_star() {
+        let planets = vec![planet("Kerbin", None, 0.6, 13337.6, 9.285)];
+        let (position, velocity) = system_initial_state(&planets[0], &sun(), &planets);
+
+        assert_vec_close(position, Vec3::new(13337.6 + 261.6, 0.0, 0.0), 1e-3);
+        assert_vec_close(velocity, Vec3::new(0.0, 0.0, -9.285), 1e-6);
+    }
+
+    #[test]
+    fn a_moon_starts_next_to_its_planet() {
+        let planets = vec![
+            planet("Mun", Some("Kerbin"), 0.2, 11.4, 0.543),
+            planet("Kerbin", None, 0.6, 13337.6, 9.285),
+            planet("Minmus", Some("Mun"), 0.06, 1.0, 0.1),
+        ];
+        let sun = sun();
+
+        let (kerbin_position, kerbin_velocity) = system_initial_state(&planets[1], &sun, &planets);
+        let (mun_position, mun_velocity) = system_initial_state(&planets[0], &sun, &planets);
+        let (minmus_position, minmus_velocity) = system_initial_state(&planets[2], &sun, &planets);
+
+        // The periapsis is measured from the surface of the parent
+        assert_vec_close(mun_position - kerbin_position, Vec3::new(11.4 + 0.6, 0.0, 0.0), 1e-3);
+        assert_vec_close(mun_velocity - kerbin_velocity, Vec3::new(0.0, 0.0, -0.543), 1e-5);
+        assert_vec_close(minmus_position - mun_position, Vec3::new(1.0 + 0.2, 0.0, 0.0), 1e-3);
+        assert_vec_close(minmus_velocity - mun_velocity, Vec3::new(0.0, 0.0, -0.1), 1e-5);
+    }
+
+    #[test]
+    fn a_cycle_of_parents_still_ends() {
+        let planets = vec![
+            planet("Mun", Some("Minmus"), 0.2, 11.4, 0.543),
+            planet("Minmus", Some("Mun"), 0.06, 1.0, 0.1),
+        ];
+        let (position, _) = system_initial_state(&planets[0], &sun(), &planets);
+
+        assert!(position.is_finite());
+    }
+
+    #[test]
+    fn a_given_initial_state_is_kept() {
+        let mut moon = planet("Mun", Some("Kerbin"), 0.2, 11.4, 0.543);
+        moon.initial_position = Some([1.0, 2.0, 3.0]);
+        moon.initial_velocity = Some([0.1, 0.2, 0.3]);
+        let planets = vec![planet("Kerbin", None, 0.6, 13337.6, 9.285), moon];
+
+        let (position, velocity) = system_initial_state(&planets[1], &sun(), &planets);
+
+        assert_eq!(position, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(velocity, Vec3::new(0.1, 0.2, 0.3));
+    }
+}
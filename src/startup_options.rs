@@ -0,0 +1,237 @@
+use bevy::prelude::*;
+use clap::{Parser, ValueEnum};
+
+use crate::physical_constant_models::*;
+
+/// Options given on the command line when starting the simulator.
+#[derive(Resource, Debug, Clone)]
+pub struct StartupOptions {
+    pub scenario_path: String,
+    pub constants_path: String,
+    /// Build the system from JPL Horizons vector tables listed in this properties file
+    pub horizons_path: Option<String>,
+    /// Build the system from a Kopernicus config file, or a directory of them
+    pub kopernicus_path: Option<String>,
+    pub headless: Option<HeadlessOptions>,
+    /// Ephemeris files driving the motion of the bodies they describe
+    pub oem_inputs: Vec<String>,
+    /// Replaces `dv` from the constants file
+    pub dv: Option<f32>,
+    /// Replaces the integrator from the constants file
+    pub integrator: Option<Integrator>,
+    /// Name of the body the camera looks at first
+    pub focus: Option<String>,
+    pub window_size: (f32, f32),
+    pub debug_overlay: bool,
+    /// Only check the scenario and exit
+    pub validate: bool,
+    /// Only write the first file in the format of the second one's extension and exit
+    pub convert: Option<(String, String)>,
+    /// Key, mouse and gamepad bindings replacing the defaults, read when the file exists
+    pub input_map_path: String,
+    /// Camera bookmarks and path, read when the file exists and saved as they change
+    pub camera_views_path: String,
+    /// Play the camera path from the start
+    pub play_camera_path: bool,
+    /// Pairs of bodies whose closest approaches are reported
+    pub closest_approach_pairs: Vec<(String, String)>,
+    /// Body from which conjunctions and oppositions are reported
+    pub conjunction_observer: Option<String>,
+}
+
+/// What a run without a window simulates and where it writes the results.
+#[derive(Debug, Clone)]
+pub struct HeadlessOptions {
+    /// Simulated duration, in time units
+    pub duration: f64,
+    /// Length of one physics step, replaces `dv` from the constants file
+    pub step: Option<f32>,
+    pub output_path: String,
+    pub format: EphemerisFormat,
+    /// Only write the states every this many steps
+    pub output_every: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EphemerisFormat {
+    Csv,
+    #[value(name = "jsonl")]
+    JsonLines,
+}
+
+impl Default for StartupOptions {
+    fn default() -> Self {
+        StartupOptions {
+            scenario_path: "assets/planets/planets.json".to_string(),
+            constants_path: "assets/planets/physical_constants.json".to_string(),
+            horizons_path: None,
+            kopernicus_path: None,
+            headless: None,
+            oem_inputs: Vec::new(),
+            dv: None,
+            integrator: None,
+            focus: None,
+            window_size: (1280.0, 720.0),
+            debug_overlay: true,
+            validate: false,
+            convert: None,
+            input_map_path: "input_map.toml".to_string(),
+            camera_views_path: "camera_views.toml".to_string(),
+            play_camera_path: false,
+            closest_approach_pairs: Vec::new(),
+            conjunction_observer: None,
+        }
+    }
+}
+
+impl Default for HeadlessOptions {
+    fn default() -> Self {
+        HeadlessOptions {
+            duration: 1000.0,
+            step: None,
+            output_path: "ephemerides.csv".to_string(),
+            format: EphemerisFormat::Csv,
+            output_every: 1,
+        }
+    }
+}
+
+/// Simulates a solar system from a scenario file.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct CommandLine {
+    /// Scenario file describing the stars, planets and populations, in JSON, RON, TOML or YAML
+    #[arg(long, value_name = "FILE", default_value = "assets/planets/planets.json")]
+    scenario: String,
+    /// Physical constants file
+    #[arg(long, value_name = "FILE", default_value = "assets/planets/physical_constants.json")]
+    constants: String,
+    /// Length of one physics step in time units, instead of the one from the constants file
+    #[arg(long)]
+    dv: Option<f32>,
+    /// Integrator moving the planets, instead of the one from the constants file
+    #[arg(long, value_enum)]
+    integrator: Option<Integrator>,
+    /// Name of the body to look at first
+    #[arg(long, value_name = "BODY")]
+    focus: Option<String>,
+    /// Window size in logical pixels, e.g. 1920x1080
+    #[arg(long, value_name = "WIDTHxHEIGHT", default_value = "1280x720", value_parser = parse_window_size)]
+    window_size: (f32, f32),
+    /// Start without the FPS and planet information overlay
+    #[arg(long)]
+    no_debug_overlay: bool,
+    /// Key, mouse and gamepad bindings replacing the defaults, in JSON, RON, TOML or YAML
+    #[arg(long, value_name = "FILE", default_value = "input_map.toml")]
+    input_map: String,
+    /// Camera bookmarks and path, in JSON, RON, TOML or YAML, saved as they change
+    #[arg(long, value_name = "FILE", default_value = "camera_views.toml")]
+    camera_views: String,
+    /// Play the camera path of the camera views file from the start, for recordings
+    #[arg(long)]
+    play_camera_path: bool,
+    /// Only check the scenario and constants files, print the problems found and exit
+    /// with a non-zero status if any of them is an error
+    #[arg(long)]
+    validate: bool,
+    /// Convert a scenario or constants file between JSON, RON, TOML and YAML, picked by the
+    /// extensions, and exit
+    #[arg(long, num_args = 2, value_names = ["INPUT", "OUTPUT"])]
+    convert: Option<Vec<String>>,
+
+    /// Build the system from the JPL Horizons vector tables listed in this properties file
+    #[arg(long, value_name = "FILE", conflicts_with = "kopernicus")]
+    horizons: Option<String>,
+    /// Build the system from a Kopernicus config file, or a directory of them
+    #[arg(long, value_name = "PATH")]
+    kopernicus: Option<String>,
+    /// CCSDS OEM file driving the motion of the bodies it describes, can be repeated
+    #[arg(long = "oem", value_name = "FILE")]
+    oem_inputs: Vec<String>,
+    /// Report the closest approaches between two bodies, e.g. Kerbin,Duna, can be repeated
+    #[arg(long = "closest-approach", value_name = "BODY,BODY", value_parser = parse_body_pair)]
+    closest_approach_pairs: Vec<(String, String)>,
+    /// Report the conjunctions and oppositions of the other bodies as seen from this one
+    #[arg(long, value_name = "BODY")]
+    conjunction_observer: Option<String>,
+
+    /// Run without a window and write the ephemerides to a file
+    #[arg(long, help_heading = "Headless")]
+    headless: bool,
+    /// Simulated duration, in time units
+    #[arg(long, default_value_t = 1000.0, help_heading = "Headless")]
+    duration: f64,
+    /// Length of one physics step, replaces --dv for the headless run
+    #[arg(long, value_parser = parse_positive, help_heading = "Headless")]
+    step: Option<f32>,
+    /// File the ephemerides are written to
+    #[arg(long, value_name = "FILE", default_value = "ephemerides.csv", help_heading = "Headless")]
+    output: String,
+    /// Only write the states every this many steps
+    #[arg(long, value_name = "STEPS", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..), help_heading = "Headless")]
+    output_every: u32,
+    /// Format of the ephemeris file
+    #[arg(long, value_enum, default_value_t = EphemerisFormat::Csv, help_heading = "Headless")]
+    format: EphemerisFormat,
+}
+
+fn parse_window_size(value: &str) -> Result<(f32, f32), String> {
+    let (width, height) = value.split_once(['x', 'X'])
+        .ok_or_else(|| "expected WIDTHxHEIGHT, e.g. 1920x1080".to_string())?;
+    let parse = |side: &str| side.trim().parse::<f32>().ok().filter(|size| *size > 0.0);
+
+    match (parse(width), parse(height)) {
+        (Some(width), Some(height)) => Ok((width, height)),
+        _ => Err(format!("{value} is not a valid window size")),
+    }
+}
+
+fn parse_positive(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(number) if number > 0.0 && number.is_finite() => Ok(number),
+        Ok(_) => Err(format!("{value} is not a positive number")),
+        Err(err) => Err(err.to_string()),
+    }
+}
+
+fn parse_body_pair(value: &str) -> Result<(String, String), String> {
+    match value.split_once(',') {
+        Some((first, second)) if !first.trim().is_empty() && !second.trim().is_empty() =>
+            Ok((first.trim().to_string(), second.trim().to_string())),
+        _ => Err(format!("expected two body names separated by a comma, got {value}")),
+    }
+}
+
+impl StartupOptions {
+    /// Reads the command line arguments, printing the usage and exiting when they are invalid.
+    pub fn from_args() -> Self {
+        let args = CommandLine::parse();
+
+        StartupOptions {
+            scenario_path: args.scenario,
+            constants_path: args.constants,
+            horizons_path: args.horizons,
+            kopernicus_path: args.kopernicus,
+            headless: args.headless.then_some(HeadlessOptions {
+                duration: args.duration,
+                step: args.step,
+                output_path: args.output,
+                format: args.format,
+                output_every: args.output_every,
+            }),
+            oem_inputs: args.oem_inputs,
+            dv: args.dv,
+            integrator: args.integrator,
+            focus: args.focus,
+            window_size: args.window_size,
+            debug_overlay: !args.no_debug_overlay,
+            validate: args.validate,
+            convert: args.convert.map(|paths| (paths[0].clone(), paths[1].clone())),
+            input_map_path: args.input_map,
+            camera_views_path: args.camera_views,
+            play_camera_path: args.play_camera_path,
+            closest_approach_pairs: args.closest_approach_pairs,
+            conjunction_observer: args.conjunction_observer,
+        }
+    }
+}
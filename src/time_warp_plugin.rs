@@ -0,0 +1,290 @@
+use crate::{input_map::*, physics::*, planet_components::*, planet_models::*, labels::*};
+use bevy::{ecs::schedule::ShouldRun, prelude::*};
+
+/// KSP-style time warp: a ladder of multiples of the `dv` of the constants file, a pause
+/// and single steps. Each frame makes as many steps of that `dv` as the multiplier, up to a
+/// cap beyond which the steps get longer, and the warp drops on its own when those longer
+/// steps get too long for the bodies.
+pub struct TimeWarpPlugin;
+
+impl Plugin for TimeWarpPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WarpSafety>()
+            .add_system(change_time_warp
+                .before(SystemTypes::SnapshotLabel)
+                .before(SystemTypes::PhysicsLabel))
+            .add_system(apply_time_warp
+                .after(change_time_warp)
+                .before(SystemTypes::SnapshotLabel)
+                .before(SystemTypes::PhysicsLabel))
+            .add_system(limit_time_warp
+                .with_run_criteria(simulation_running)
+                .after(SystemTypes::PhysicsLabel)
+                .before(SystemTypes::CameraLabel));
+    }
+}
+
+/// Warp multipliers of the base `dv`, from real time to the fastest.
+pub const WARP_LADDER: [f32; 8] = [1.0, 5.0, 10.0, 50.0, 100.0, 1000.0, 10000.0, 100000.0];
+
+#[derive(Resource, Debug)]
+pub struct TimeWarp {
+    /// Index in `WARP_LADDER`
+    pub level: usize,
+    pub paused: bool,
+    /// Advance by one step while paused, cleared once the step is made
+    pub single_step: bool,
+    /// `dv` at 1×, taken from the constants
+    pub base_dv: f32,
+    /// Most steps made in one frame, higher multipliers make longer steps instead
+    pub max_substeps: u32,
+    // `dv` written to the constants the last time, to notice when something else replaced it
+    applied_dv: f32,
+}
+
+impl TimeWarp {
+    pub fn multiplier(&self) -> f32 {
+        WARP_LADDER[self.level]
+    }
+
+    /// Steps made in each frame at the given level.
+    pub fn substeps_at(&self, level: usize) -> u32 {
+        (WARP_LADDER[level] as u32).clamp(1, self.max_substeps.max(1))
+    }
+
+    /// Length of the steps at the given level, the base `dv` unless the level needs more steps
+    /// than `max_substeps`.
+    pub fn step_dv_at(&self, level: usize) -> f32 {
+        self.base_dv * WARP_LADDER[level] / self.substeps_at(level) as f32
+    }
+
+    // Steps the current frame makes
+    fn substeps_this_frame(&self) -> u32 {
+        match (self.paused, self.single_step) {
+            (false, _) => self.substeps_at(self.level),
+            (true, true) => 1,
+            (true, false) => 0,
+        }
+    }
+}
+
+/// Steps made since the start of the frame.
+#[derive(Resource, Default, Debug)]
+pub struct FrameSubsteps(u32);
+
+impl FromWorld for TimeWarp {
+    fn from_world(world: &mut World) -> Self {
+        let dv = world.resource::<SolarSystemConfiguration>().physical_constants.dv;
+
+        TimeWarp {
+            level: 0,
+            paused: false,
+            single_step: false,
+            base_dv: dv,
+            max_substeps: 100,
+            applied_dv: dv,
+        }
+    }
+}
+
+/// When the warp has to drop to keep the integration accurate.
+#[derive(Resource, Debug)]
+pub struct WarpSafety {
+    /// Longest step allowed, as a fraction of `sqrt(r³ / mu)` between any body and an attractor,
+    /// about the time it takes to travel one radian of a circular orbit at that distance
+    pub max_step_fraction: f32,
+    /// Largest relative change of the total energy allowed in one step
+    pub energy_error_limit: f64,
+    // Number of attractors, warp level and total energy after the last step
+    last_energy: Option<(usize, usize, f64)>,
+}
+
+impl Default for WarpSafety {
+    fn default() -> Self {
+        WarpSafety {
+            max_step_fraction: 0.05,
+            energy_error_limit: 1e-3,
+            last_energy: None,
+        }
+    }
+}
+
+/// Run criteria of the systems that advance the simulation: they run once for each step of
+/// the frame, so nothing moves while paused.
+pub fn simulation_running(warp: Res<TimeWarp>, substeps: Res<FrameSubsteps>) -> ShouldRun {
+    if substeps.0 < warp.substeps_this_frame() {
+        ShouldRun::YesAndCheckAgain
+    } else {
+        ShouldRun::No
+    }
+}
+
+/// Counts the step that was just made, a single step is then done.
+pub fn finish_substep(mut warp: ResMut<TimeWarp>, mut substeps: ResMut<FrameSubsteps>) {
+    substeps.0 = substeps.0.saturating_add(1);
+    if warp.single_step {
+        warp.single_step = false;
+    }
+}
+
+/// Starts counting the steps of the next frame.
+pub fn finish_frame(mut substeps: ResMut<FrameSubsteps>) {
+    substeps.0 = 0;
+}
+
+/// `.` and `,` go up and down the warp ladder, `/` goes back to 1×, P pauses and N advances
+/// a single step while paused.
+fn change_time_warp(
+    actions: Res<ActionState>,
+    mut warp: ResMut<TimeWarp>,
+) {
+    if actions.just_pressed(Action::WarpFaster) {
+        warp.level = (warp.level + 1).min(WARP_LADDER.len() - 1);
+    }
+    if actions.just_pressed(Action::WarpSlower) {
+        warp.level = warp.level.saturating_sub(1);
+    }
+    if actions.just_pressed(Action::StopWarp) {
+        warp.level = 0;
+    }
+    if actions.just_pressed(Action::Pause) {
+        warp.paused = !warp.paused;
+    }
+    if actions.just_pressed(Action::SingleStep) && warp.paused {
+        warp.single_step = true;
+    }
+}
+
+/// Sets the `dv` of the constants to the length of the steps at the warp level.
+pub fn apply_time_warp(
+    mut warp: ResMut<TimeWarp>,
+    mut config: ResMut<SolarSystemConfiguration>,
+) {
+    // New constants were loaded, their dv is the new 1×
+    if config.physical_constants.dv != warp.applied_dv {
+        warp.base_dv = config.physical_constants.dv;
+    }
+
+    // Only write on changes, to keep change detection meaningful
+    let dv = warp.step_dv_at(warp.level);
+    if config.physical_constants.dv != dv {
+        config.physical_constants.dv = dv;
+    }
+    if warp.applied_dv != dv {
+        warp.applied_dv = dv;
+    }
+}
+
+/// Drops the warp to the highest level that is still safe after the step that was just made.
+/// Levels stepping by the base `dv` are left alone, dropping them would not make the steps shorter.
+fn limit_time_warp(
+    mut warp: ResMut<TimeWarp>,
+    mut safety: ResMut<WarpSafety>,
+    mut substeps: ResMut<FrameSubsteps>,
+    bodies: Query<(Entity, &Transform, &CelestialBody)>,
+) {
+    let attractors: Vec<(Entity, Vec3, &CelestialBody)> = bodies.iter()
+        .filter(|(_, _, body)| body.gravitational_parameter > 0.0)
+        .map(|(entity, pos, body)| (entity, pos.translation, body))
+        .collect();
+
+    // Shortest orbital time scale between a body and anything pulling on it
+    let mut closest: Option<(f32, &str, &str)> = None;
+    for (entity, pos, body) in bodies.iter() {
+        for (attractor, position, attractor_body) in attractors.iter() {
+            if *attractor == entity {
+                continue;
+            }
+
+            let distance = (*position - pos.translation).length().max(attractor_body.radius);
+            let time_scale = (distance.powi(3) / attractor_body.gravitational_parameter).sqrt();
+            if closest.is_none_or(|(shortest, _, _)| time_scale < shortest) {
+                closest = Some((time_scale, &body.name, &attractor_body.name));
+            }
+        }
+    }
+
+    if let Some((time_scale, body, attractor)) = closest {
+        let max_dv = (time_scale * safety.max_step_fraction).max(warp.base_dv);
+        let safe_level = (0..WARP_LADDER.len())
+            .rev()
+            .find(|level| warp.step_dv_at(*level) <= max_dv)
+            .unwrap_or(0);
+
+        if warp.level > safe_level {
+            warp.level = safe_level;
+            safety.last_energy = None;
+            // The rest of the frame would still make the longer steps
+            substeps.0 = u32::MAX;
+            info!("Warp dropped to {}× for the encounter of {body} with {attractor}", warp.multiplier());
+            return;
+        }
+    }
+
+    let energy = total_energy(&bodies.iter()
+        .map(|(_, pos, body)| Body::new(pos.translation, body.vel.vector, body.gravitational_parameter))
+        .collect::<Vec<_>>());
+
+    // Only compare steps made with the same bodies and the same warp
+    if let Some((count, level, last_energy)) = safety.last_energy {
+        let error = ((energy - last_energy) / last_energy).abs();
+        let comparable = count == attractors.len() && level == warp.level;
+
+        let lengthened = warp.step_dv_at(warp.level) > warp.base_dv;
+
+        if comparable && lengthened && error > safety.energy_error_limit {
+            warp.level -= 1;
+            safety.last_energy = None;
+            substeps.0 = u32::MAX;
+            info!("Warp dropped to {}×, the energy changed by {:.2e} in one step", warp.multiplier(), error);
+            return;
+        }
+    }
+    safety.last_energy = Some((attractors.len(), warp.level, energy));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warp(level: usize) -> TimeWarp {
+        TimeWarp {
+            level,
+            paused: false,
+            single_step: false,
+            base_dv: 0.5,
+            max_substeps: 100,
+            applied_dv: 0.5,
+        }
+    }
+
+    #[test]
+    fn levels_below_the_cap_make_one_base_step_per_multiple() {
+        for (level, multiplier) in WARP_LADDER.iter().enumerate().take(5) {
+            let warp = warp(level);
+            assert_eq!(warp.substeps_at(level), *multiplier as u32);
+            assert_eq!(warp.step_dv_at(level), 0.5);
+        }
+    }
+
+    #[test]
+    fn levels_above_the_cap_make_longer_steps() {
+        let warp = warp(7);
+        assert_eq!(warp.substeps_at(7), 100);
+        assert_eq!(warp.step_dv_at(7), 0.5 * 1000.0);
+        assert_eq!(warp.substeps_at(7) as f32 * warp.step_dv_at(7), 0.5 * WARP_LADDER[7]);
+    }
+
+    #[test]
+    fn a_pause_makes_no_steps_but_the_single_one() {
+        let mut warp = warp(3);
+        assert_eq!(warp.substeps_this_frame(), 50);
+
+        warp.paused = true;
+        assert_eq!(warp.substeps_this_frame(), 0);
+
+        warp.single_step = true;
+        assert_eq!(warp.substeps_this_frame(), 1);
+    }
+}